@@ -1,87 +1,474 @@
-use crate::{auth, config, paths, provider};
+use crate::cli::{AuthCommand, ConfigCommand};
+use crate::{auth, config, context, env, paths, provider, retry};
 use anyhow::Context;
-use provider::Provider;
+use futures_core::stream::BoxStream;
+use provider::{ChatEvent, ChatRequest, Provider};
+
+pub const DEFAULT_MODEL: &str = "gemini-1.5-flash";
+
+/// Resolve the model: CLI flag > `GEMINI_MODEL` > `providers.<provider>.default_model`
+/// > `model` in config > built-in default.
+pub fn resolve_model(flag: Option<String>, cfg: Option<&config::Config>, provider_name: &str) -> String {
+    resolve_model_with(flag, cfg, provider_name, env::var)
+}
+
+/// [`resolve_model`] with environment reads going through `var`.
+fn resolve_model_with(
+    flag: Option<String>,
+    cfg: Option<&config::Config>,
+    provider_name: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> String {
+    flag.or_else(|| var(env::GEMINI_MODEL))
+        .or_else(|| cfg.and_then(|c| c.provider_settings(provider_name)?.default_model.clone()))
+        .or_else(|| cfg.and_then(|c| c.model.clone()))
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string())
+}
+
+/// Resolve the provider: CLI flag > config > `google`.
+pub fn resolve_provider_name(flag: Option<String>, cfg: Option<&config::Config>) -> String {
+    flag.or_else(|| cfg.and_then(|c| c.provider.clone()))
+        .unwrap_or_else(|| "google".to_string())
+}
+
+/// Build the shared HTTP client. With `trust_env` off, proxy settings from
+/// the environment (`HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY`) are ignored.
+pub fn http_client(trust_env: bool) -> anyhow::Result<reqwest::Client> {
+    let mut builder =
+        reqwest::Client::builder().user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")));
+    if !trust_env {
+        builder = builder.no_proxy();
+    }
+    builder.build().context("failed to build HTTP client")
+}
+
+/// A short random id for one logical request, used to correlate its log
+/// lines and errors.
+pub fn new_request_id() -> String {
+    format!("{:08x}", fastrand::u32(..))
+}
+
+/// Estimated request size (in tokens) above which a size summary is printed.
+pub const DEFAULT_REQUEST_TOKEN_WARNING: usize = 100_000;
+
+/// Estimated request size below which the model's limits are not looked up
+/// (no current model accepts fewer input tokens).
+pub const MODEL_LIMIT_LOOKUP_TOKENS: usize = 8_192;
+
+/// The model's input token limit, if the provider reports one. The lookup
+/// costs a round trip (once per provider), so it is skipped for requests of
+/// `bytes` too small to possibly hit a limit.
+pub async fn input_token_limit(provider: &(dyn Provider + Send + Sync), model: &str, bytes: usize) -> Option<u64> {
+    if context::estimate_tokens(bytes) <= MODEL_LIMIT_LOOKUP_TOKENS {
+        return None;
+    }
+    match provider.model_info(model).await {
+        Ok(info) => info.and_then(|i| i.input_token_limit),
+        Err(e) => {
+            tracing::debug!("model info lookup failed: {e:#}");
+            None
+        }
+    }
+}
+
+/// Total text size of `history`, in bytes.
+pub fn history_bytes(history: &[provider::ChatMessage]) -> usize {
+    history.iter().map(|m| m.text.len()).sum()
+}
+
+/// Drop the oldest turns of `history` until it fits in `input_limit` tokens
+/// along with `prompt_bytes` of prompt. A dropped user message takes the
+/// model replies that follow it along, so the history still starts with a
+/// user turn; system messages are kept. Returns how many messages were dropped.
+pub fn trim_history(history: &mut Vec<provider::ChatMessage>, prompt_bytes: usize, input_limit: u64) -> usize {
+    use provider::Role;
+
+    let before = history.len();
+    let first_turn = |h: &[provider::ChatMessage]| h.iter().position(|m| m.role != Role::System);
+    while context::estimate_tokens(prompt_bytes + history_bytes(history)) as u64 > input_limit {
+        let Some(first) = first_turn(history) else { break };
+        history.remove(first);
+        while let Some(reply) = first_turn(history).filter(|&i| history[i].role == Role::Model) {
+            history.remove(reply);
+        }
+    }
+    before - history.len()
+}
+
+/// Estimate the token count of a request of `bytes` and, if it exceeds
+/// `warn_tokens`, `max_tokens` or the model's `input_limit`, print the
+/// estimate and the largest contributing sources. Exceeding `max_tokens` is
+/// an error.
+pub fn check_request_size(
+    bytes: usize,
+    sources: &mut [(String, usize)],
+    warn_tokens: usize,
+    max_tokens: Option<usize>,
+    input_limit: Option<u64>,
+) -> anyhow::Result<()> {
+    let estimate = context::estimate_tokens(bytes);
+    let over_max = max_tokens.is_some_and(|m| estimate > m);
+    let over_limit = input_limit.is_some_and(|l| estimate as u64 > l);
+    if estimate <= warn_tokens && !over_max && !over_limit {
+        tracing::debug!(estimate, "estimated request tokens");
+        return Ok(());
+    }
+
+    eprintln!("warning: request is about {estimate} tokens ({bytes} bytes); largest sources:");
+    if let Some(limit) = input_limit.filter(|_| over_limit) {
+        eprintln!("  (the model's input limit is {limit} tokens; expect the request to be rejected)");
+    }
+    sources.sort_by_key(|s| std::cmp::Reverse(s.1));
+    for (name, bytes) in sources.iter().take(5) {
+        eprintln!("  ~{:>8} tokens  {name}", context::estimate_tokens(*bytes));
+    }
+
+    if let Some(max) = max_tokens.filter(|_| over_max) {
+        anyhow::bail!("estimated request size ({estimate} tokens) exceeds --max-request-tokens {max}");
+    }
+    Ok(())
+}
+
+/// Wrap `prompt` with the optional prefix/suffix, one per line.
+pub fn wrap_prompt(prompt: &str, prefix: Option<&str>, suffix: Option<&str>) -> String {
+    let mut out = String::new();
+    if let Some(p) = prefix.filter(|p| !p.is_empty()) {
+        out.push_str(p);
+        out.push('\n');
+    }
+    out.push_str(prompt);
+    if let Some(s) = suffix.filter(|s| !s.is_empty()) {
+        out.push('\n');
+        out.push_str(s);
+    }
+    out
+}
+
+/// Separates the layers of a combined system instruction.
+pub const SYSTEM_SEPARATOR: &str = "\n\n---\n\n";
+
+/// The system instruction layers for a request, broadest first: the
+/// config's `system_instruction`, the nearest project `.gemini.toml`, then
+/// `flag` (--system). Each is `(source, text)`; empty layers are left out.
+pub fn system_layers(cfg: Option<&config::Config>, flag: Option<&str>) -> anyhow::Result<Vec<(String, String)>> {
+    let mut layers = Vec::new();
+    if let Some(text) = cfg.and_then(|c| c.system_instruction.as_deref()) {
+        layers.push(("config".to_string(), text.to_string()));
+    }
+    let cwd = std::env::current_dir().context("failed to read current directory")?;
+    if let Some((path, project)) = config::ProjectConfig::find(&cwd)? {
+        if let Some(text) = project.system_instruction {
+            layers.push((path.display().to_string(), text));
+        }
+    }
+    if let Some(text) = flag {
+        layers.push(("--system".to_string(), text.to_string()));
+    }
+    layers.retain(|(_, text)| !text.trim().is_empty());
+    Ok(layers)
+}
+
+/// Join `layers` into one system instruction, or `None` if there are none.
+pub fn compose_system(layers: &[(String, String)]) -> Option<String> {
+    if layers.is_empty() {
+        return None;
+    }
+    let texts: Vec<&str> = layers.iter().map(|(_, text)| text.trim()).collect();
+    Some(texts.join(SYSTEM_SEPARATOR))
+}
+
+/// Start streaming `req`, retrying transient errors per `backoff` and then
+/// moving down `fallbacks` while the current model is unavailable
+/// (overloaded, rate limited, or not found). `requests` is incremented for
+/// every request sent, retries and fallbacks included.
+pub async fn stream_with_fallback(
+    provider: &(dyn Provider + Send + Sync),
+    req: ChatRequest,
+    fallbacks: &[String],
+    backoff: &retry::Backoff,
+    requests: &mut u32,
+) -> anyhow::Result<BoxStream<'static, anyhow::Result<ChatEvent>>> {
+    with_fallback(req, fallbacks, backoff, requests, |req| provider.stream_chat(req)).await
+}
+
+/// [`stream_with_fallback`] for `--no-stream`: the whole answer from
+/// [`Provider::generate`].
+pub async fn generate_with_fallback(
+    provider: &(dyn Provider + Send + Sync),
+    req: ChatRequest,
+    fallbacks: &[String],
+    backoff: &retry::Backoff,
+    requests: &mut u32,
+) -> anyhow::Result<String> {
+    with_fallback(req, fallbacks, backoff, requests, |req| provider.generate(req)).await
+}
+
+async fn with_fallback<T, F, Fut>(
+    mut req: ChatRequest,
+    fallbacks: &[String],
+    backoff: &retry::Backoff,
+    requests: &mut u32,
+    mut send: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut(ChatRequest) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
+    let primary = req.model.clone();
+    let mut next_models = fallbacks.iter().filter(move |m| **m != primary);
+    loop {
+        let attempt = retry::retry_with(backoff, retry::is_transient, || {
+            *requests += 1;
+            send(req.clone())
+        });
+        let err = match attempt.await {
+            Ok(v) => return Ok(v),
+            Err(e) => e,
+        };
+        let status = err
+            .downcast_ref::<provider::ApiError>()
+            .filter(|a| a.is_unavailable())
+            .map(|a| a.status);
+        let (Some(status), Some(next)) = (status, next_models.next()) else {
+            return Err(err);
+        };
+        eprintln!("note: model {} unavailable (HTTP {status}); falling back to {next}", req.model);
+        req.model = next.clone();
+    }
+}
+
+/// Tracks whether a response said anything, to tell a transient empty
+/// response (no text, no tool call, no reason) from a deliberate one.
+#[derive(Debug, Clone, Default)]
+pub struct EmptyCheck {
+    content: bool,
+    explained: bool,
+}
+
+impl EmptyCheck {
+    pub fn observe(&mut self, event: &ChatEvent) {
+        match event {
+            ChatEvent::Text(t) | ChatEvent::Alternate { text: t, .. } if !t.is_empty() => self.content = true,
+            ChatEvent::FunctionCall { .. } => self.content = true,
+            ChatEvent::Blocked { .. } => self.explained = true,
+            // STOP with nothing generated is exactly the transient case.
+            ChatEvent::Finish { reason } if reason != "STOP" => self.explained = true,
+            _ => {}
+        }
+    }
+
+    /// The stream ended with no content and no block or finish reason that
+    /// would explain it.
+    pub fn is_unexplained_empty(&self) -> bool {
+        !self.content && !self.explained
+    }
+}
+
+/// The `--stats` block, on stderr so it never mixes with the answer.
+/// Throughput counts output tokens over the time spent receiving the body.
+pub fn print_stats(stats: Option<provider::StreamStats>, usage: Option<provider::TokenUsage>, retries: u32) {
+    eprintln!("--- stats ---");
+    match stats {
+        Some(s) => {
+            eprintln!("time to first byte: {} ms", s.time_to_first_byte.as_millis());
+            eprintln!("duration:           {} ms", s.duration.as_millis());
+            eprintln!("chunks:             {}", s.chunks);
+            eprintln!("bytes received:     {}", s.bytes);
+            let receiving = s.duration.saturating_sub(s.time_to_first_byte).as_secs_f64();
+            match usage {
+                Some(u) if receiving > 0.0 => {
+                    eprintln!("tokens/sec:         {:.1}", u.output_tokens as f64 / receiving)
+                }
+                _ => eprintln!("tokens/sec:         -"),
+            }
+        }
+        None => eprintln!("(no timings: the stream did not complete)"),
+    }
+    eprintln!("retries:            {retries}");
+}
+
+/// `--show-usage`: the token counts of the last request, to stderr.
+pub fn print_usage(usage: Option<provider::TokenUsage>) {
+    match usage {
+        Some(u) => eprintln!(
+            "tokens: {} prompt + {} output = {} total",
+            u.prompt_tokens, u.output_tokens, u.total_tokens
+        ),
+        None => eprintln!("tokens: (no usage reported)"),
+    }
+}
 
 pub async fn cmd_login(http: &reqwest::Client, cfg: Option<&config::Config>) -> anyhow::Result<()> {
-    use std::io::Write;
+    login(http, cfg, &mut std::io::stdout()).await.map(|_| ())
+}
+
+/// Run the device-code flow and save the token.
+async fn login(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    out: &mut dyn std::io::Write,
+) -> anyhow::Result<auth::OAuthToken> {
+    let oauth = oauth_client(cfg)?;
+    let tok = auth::device_login(http, &oauth, out).await?;
+
+    let path = paths::google_token_path()?;
+    auth::save_token_atomic(&path, &tok)?;
+
+    writeln!(out, "Saved token to: {}", path.display()).ok();
+    if let Some(warning) = tok.missing_refresh_token_warning() {
+        writeln!(out, "warning: {warning}").ok();
+    }
+    Ok(tok)
+}
+
+pub async fn cmd_auth(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    cmd: AuthCommand,
+) -> anyhow::Result<()> {
+    match cmd {
+        AuthCommand::Refresh => {
+            let path = paths::google_token_path()?;
+            let tok = auth::load_token(&path)?.with_context(|| {
+                format!("no stored OAuth token at {}; run `gemini login`", path.display())
+            })?;
+
+            let oauth = token_oauth_client(cfg, &tok)?;
+            let tok = auth::refresh(http, &oauth, tok).await?;
+            auth::save_token_atomic(&path, &tok)?;
+
+            match tok.expires_at() {
+                Some(exp) => println!(
+                    "Token refreshed. expires_at: {exp} (in {}s)",
+                    exp.saturating_sub(auth::now_secs())
+                ),
+                None => println!("Token refreshed. expires_at: unknown"),
+            }
+            Ok(())
+        }
+        AuthCommand::Import { from } => {
+            let bytes = std::fs::read(&from).with_context(|| format!("failed to read {}", from.display()))?;
+            let (tok, format) = auth::import_token(&bytes)?;
+            if tok.refresh_token.is_none() && !tok.is_valid_for(std::time::Duration::ZERO) {
+                anyhow::bail!("{} has an expired access token and no refresh token", from.display());
+            }
+
+            let path = paths::google_token_path()?;
+            auth::save_token_atomic(&path, &tok)?;
+            println!("Imported {format} from {} into {}", from.display(), path.display());
+            if let Some(warning) = tok.missing_refresh_token_warning() {
+                eprintln!("warning: {warning}");
+            }
+            if format == auth::ImportFormat::GeminiCli && tok.refresh_token.is_some() {
+                println!(
+                    "note: the refresh token belongs to the gemini CLI's OAuth client; set google.oauth.client_id \
+                     (and client_secret) to that client for refreshes to work"
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `config set` / `config get`. (`config doctor` is handled elsewhere.)
+pub fn cmd_config(path: &std::path::Path, cmd: ConfigCommand) -> anyhow::Result<()> {
+    let known = |key: &str| {
+        config::ScalarKey::find(key).with_context(|| {
+            let keys: Vec<_> = config::SCALAR_KEYS.iter().map(|k| k.key).collect();
+            format!("unknown or non-scalar setting {key:?}; known settings: {}", keys.join(", "))
+        })
+    };
+    match cmd {
+        ConfigCommand::Set {
+            key,
+            value,
+            allow_secret,
+        } => {
+            let setting = known(&key)?;
+            if setting.secret && !allow_secret {
+                anyhow::bail!(
+                    "{key} is a credential; values on the command line end up in shell history. \
+                     Pass --allow-secret to write it anyway, or edit the file"
+                );
+            }
+            config::set_value(path, &key, setting.parse(&value)?)?;
+            println!("Set {key} in {}", path.display());
+            Ok(())
+        }
+        ConfigCommand::Get { key } => {
+            known(&key)?;
+            match config::get_value(path, &key)? {
+                Some(toml_edit::Value::String(s)) => println!("{}", s.value()),
+                Some(v) => println!("{}", v.to_string().trim()),
+                None => anyhow::bail!("{key} is not set in {}", path.display()),
+            }
+            Ok(())
+        }
+        // Async, so main runs it with `doctor::cmd_config_doctor` instead.
+        ConfigCommand::Doctor { .. } => anyhow::bail!("config doctor is not handled by cmd_config"),
+    }
+}
+
+/// The OAuth client to refresh `tok` with: the client it was issued to, if
+/// recorded (imported tokens), otherwise the configured one.
+pub fn token_oauth_client(cfg: Option<&config::Config>, tok: &auth::OAuthToken) -> anyhow::Result<auth::OAuthClient> {
+    match &tok.client_id {
+        Some(id) => auth::OAuthClient::google_device_flow(id.clone(), tok.client_secret.clone(), Vec::new()),
+        None => oauth_client(cfg),
+    }
+}
 
-    let client_id = std::env::var("GEMINI_OAUTH_CLIENT_ID")
-        .ok()
+/// OAuth client settings: env vars override config.
+fn oauth_client(cfg: Option<&config::Config>) -> anyhow::Result<auth::OAuthClient> {
+    let client_id = env::oauth_client_id()
         .or_else(|| cfg.and_then(|c| c.google.oauth.client_id.clone()))
         .context("missing OAuth client id (set GEMINI_OAUTH_CLIENT_ID or config.toml google.oauth.client_id)")?;
 
-    let client_secret = std::env::var("GEMINI_OAUTH_CLIENT_SECRET")
-        .ok()
+    let client_secret = env::oauth_client_secret()
         .or_else(|| cfg.and_then(|c| c.google.oauth.client_secret.clone()));
 
     let scopes = cfg
         .and_then(|c| c.google.oauth.scopes.clone())
         .unwrap_or_else(|| vec!["https://www.googleapis.com/auth/generative-language".to_string()]);
 
-    let oauth = auth::OAuthClient::google_device_flow(client_id, client_secret, scopes)?;
-
-    let mut out = std::io::stdout();
-    let tok = auth::device_login(http, &oauth, &mut out).await?;
-
-    let path = paths::google_token_path()?;
-    auth::save_token_atomic(&path, &tok)?;
+    auth::OAuthClient::google_device_flow(client_id, client_secret, scopes)
+}
 
-    writeln!(out, "Saved token to: {}", path.display()).ok();
-    Ok(())
+/// Knobs for `build_provider` that come from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderOptions {
+    /// Use the stored OAuth access token as-is, without refreshing it.
+    #[cfg(feature = "google")]
+    pub no_refresh: bool,
+    /// Never prompt; fail when credentials are missing.
+    #[cfg(feature = "google")]
+    pub non_interactive: bool,
+    /// Google API version (overrides `google.api_version`).
+    #[cfg(feature = "google")]
+    pub api_version: Option<String>,
+    /// Google generation endpoint (default SSE streaming).
+    #[cfg(feature = "google")]
+    pub streaming_mode: Option<provider::google::StreamingMode>,
 }
 
 pub async fn build_provider(
     http: &reqwest::Client,
     cfg: Option<&config::Config>,
     provider_name: &str,
+    opts: &ProviderOptions,
 ) -> anyhow::Result<Box<dyn Provider + Send + Sync>> {
     match provider_name {
         "google" => {
             #[cfg(feature = "google")]
             {
-                let api_key = std::env::var("GEMINI_API_KEY")
-                    .ok()
-                    .or_else(|| cfg.and_then(|c| c.google.api_key.clone()));
-
-                let auth = if let Some(key) = api_key {
-                    provider::google::GoogleAuth::ApiKey(key)
-                } else {
-                    let tok_path = paths::google_token_path()?;
-                    let Some(tok) = auth::load_token(&tok_path)? else {
-                        anyhow::bail!(
-                            "No API key or OAuth token found. Set GEMINI_API_KEY or run `gemini login`. (token path: {})",
-                            tok_path.display()
-                        );
-                    };
-
-                    let client_id = std::env::var("GEMINI_OAUTH_CLIENT_ID")
-                        .ok()
-                        .or_else(|| cfg.and_then(|c| c.google.oauth.client_id.clone()))
-                        .context("missing OAuth client id for refresh (set GEMINI_OAUTH_CLIENT_ID or config.toml)")?;
-
-                    let client_secret = std::env::var("GEMINI_OAUTH_CLIENT_SECRET")
-                        .ok()
-                        .or_else(|| cfg.and_then(|c| c.google.oauth.client_secret.clone()));
-
-                    let scopes = cfg
-                        .and_then(|c| c.google.oauth.scopes.clone())
-                        .unwrap_or_else(|| {
-                            vec!["https://www.googleapis.com/auth/generative-language".to_string()]
-                        });
-
-                    let oauth = auth::OAuthClient::google_device_flow(client_id, client_secret, scopes)?;
-                    let tok = auth::refresh_if_needed(http, &oauth, tok).await?;
-                    auth::save_token_atomic(&tok_path, &tok)?;
-                    provider::google::GoogleAuth::BearerToken(tok.access_token)
-                };
-
-                let p = provider::google::GoogleProvider::new(http.clone(), auth)?;
+                let p = build_google_provider(http, cfg, opts).await?;
                 Ok(Box::new(p))
             }
             #[cfg(not(feature = "google"))]
             {
                 let _ = http;
                 let _ = cfg;
+                let _ = opts;
                 anyhow::bail!("google provider is not enabled in this build")
             }
         }
@@ -89,3 +476,233 @@ pub async fn build_provider(
         other => anyhow::bail!("unknown provider: {other}"),
     }
 }
+
+#[cfg(feature = "google")]
+pub async fn build_google_provider(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    opts: &ProviderOptions,
+) -> anyhow::Result<provider::google::GoogleProvider> {
+    let chain = crate::credentials::chain(cfg);
+    let cx = crate::credentials::CredentialContext { http, cfg, no_refresh: opts.no_refresh };
+    let auth = match crate::credentials::resolve(&chain, &cx).await? {
+        Some((auth, source)) => {
+            tracing::debug!(source, "using Google credentials");
+            auth
+        }
+        None => interactive_auth(http, cfg, opts, &chain).await?,
+    };
+
+    let mut provider = provider::google::GoogleProvider::new(http.clone(), auth)?;
+    if let Some(version) = opts.api_version.clone().or_else(|| cfg.and_then(|c| c.google.api_version.clone())) {
+        provider = provider.with_api_version(&version)?;
+    }
+    if let Some(mode) = opts.streaming_mode {
+        provider = provider.with_streaming_mode(mode);
+    }
+    if let Some(settings) = cfg.and_then(|c| c.provider_settings("google")) {
+        if let Some(base) = &settings.base_url {
+            let base = reqwest::Url::parse(base).with_context(|| format!("invalid providers.google.base_url: {base}"))?;
+            provider = provider.with_base_url(base);
+        }
+        provider = provider.with_headers(&settings.headers)?;
+    }
+    Ok(provider)
+}
+
+/// No credential source had anything: prompt for credentials if allowed.
+#[cfg(feature = "google")]
+async fn interactive_auth(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    opts: &ProviderOptions,
+    chain: &[Box<dyn crate::credentials::CredentialProvider>],
+) -> anyhow::Result<provider::google::GoogleAuth> {
+    let missing = || {
+        let tried: Vec<&str> = chain.iter().map(|p| p.name()).collect();
+        anyhow::anyhow!(
+            "No Google credentials found (tried: {}). Set GEMINI_API_KEY or run `gemini login`.",
+            tried.join(", ")
+        )
+    };
+    if opts.non_interactive || !is_interactive() {
+        return Err(missing());
+    }
+    prompt_for_credentials(http, cfg).await?.ok_or_else(missing)
+}
+
+#[cfg(feature = "google")]
+fn is_interactive() -> bool {
+    use std::io::IsTerminal;
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// First-use helper: offer to enter an API key (optionally saved to
+/// config.toml) or to sign in with OAuth. `None` if the user declines.
+#[cfg(feature = "google")]
+async fn prompt_for_credentials(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+) -> anyhow::Result<Option<provider::google::GoogleAuth>> {
+    eprintln!("No API key or OAuth token found.");
+    eprintln!("  [k] enter an API key");
+    eprintln!("  [l] sign in with OAuth (device code)");
+    eprintln!("  [q] quit");
+    match read_answer("Choice [k/l/q]: ")?.as_str() {
+        "k" => {
+            let key = rpassword::prompt_password("API key: ").context("failed to read API key")?;
+            let key = key.trim().to_string();
+            if key.is_empty() {
+                return Ok(None);
+            }
+            if read_answer("Save it to config.toml? [y/N]: ")? == "y" {
+                let path = paths::config_dir()?.join("config.toml");
+                config::set_value(&path, "google.api_key", key.clone().into())?;
+                eprintln!("Saved API key to: {}", path.display());
+            }
+            Ok(Some(provider::google::GoogleAuth::ApiKey(key)))
+        }
+        "l" => {
+            let tok = login(http, cfg, &mut std::io::stderr()).await?;
+            Ok(Some(provider::google::GoogleAuth::BearerToken(tok.access_token)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Prompt on stderr and read one trimmed, lowercased line from stdin.
+#[cfg(feature = "google")]
+fn read_answer(prompt: &str) -> anyhow::Result<String> {
+    use std::io::Write;
+    eprint!("{prompt}");
+    std::io::stderr().flush().ok();
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).context("failed to read answer")?;
+    Ok(line.trim().to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn model(flag: Option<&str>, vars: &[(&str, &str)], cfg: Option<&config::Config>) -> String {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        resolve_model_with(flag.map(String::from), cfg, "google", |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn model_precedence() {
+        let cfg = config::Config {
+            model: Some("from-config".into()),
+            ..Default::default()
+        };
+        let env = [(env::GEMINI_MODEL, "from-env")];
+        assert_eq!(model(Some("from-flag"), &env, Some(&cfg)), "from-flag");
+        assert_eq!(model(None, &env, Some(&cfg)), "from-env");
+        assert_eq!(model(None, &[], Some(&cfg)), "from-config");
+        assert_eq!(model(None, &[], None), DEFAULT_MODEL);
+        assert_eq!(model(None, &[], Some(&config::Config::default())), DEFAULT_MODEL);
+    }
+
+    fn config(toml: &str) -> config::Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn provider_default_model_sits_between_env_and_config_model() {
+        let cfg = config(
+            r#"
+            model = "from-config"
+            [providers.google]
+            default_model = "from-provider"
+            [providers.stub]
+            default_model = "from-stub"
+            "#,
+        );
+        assert_eq!(model(Some("from-flag"), &[], Some(&cfg)), "from-flag");
+        assert_eq!(model(None, &[(env::GEMINI_MODEL, "from-env")], Some(&cfg)), "from-env");
+        assert_eq!(model(None, &[], Some(&cfg)), "from-provider");
+        assert_eq!(resolve_model_with(None, Some(&cfg), "stub", |_| None), "from-stub");
+        assert_eq!(resolve_model_with(None, Some(&cfg), "other", |_| None), "from-config");
+    }
+
+    #[test]
+    fn provider_name_precedence() {
+        let cfg = config(r#"provider = "stub""#);
+        assert_eq!(resolve_provider_name(Some("google".into()), Some(&cfg)), "google");
+        assert_eq!(resolve_provider_name(None, Some(&cfg)), "stub");
+        assert_eq!(resolve_provider_name(None, Some(&config::Config::default())), "google");
+        assert_eq!(resolve_provider_name(None, None), "google");
+    }
+
+    fn roles(history: &[provider::ChatMessage]) -> String {
+        history
+            .iter()
+            .map(|m| match m.role {
+                provider::Role::User => 'u',
+                provider::Role::Model => 'm',
+                provider::Role::System => 's',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trim_history_drops_oldest_turns_first() {
+        use provider::ChatMessage;
+
+        // 400 bytes per message is 100 tokens.
+        let text = || "x".repeat(400);
+        let mut history = vec![
+            ChatMessage {
+                role: provider::Role::System,
+                text: text(),
+            },
+            ChatMessage::user(text()),
+            ChatMessage::model(text()),
+            ChatMessage::model(text()),
+            ChatMessage::user(text()),
+            ChatMessage::model(text()),
+        ];
+        assert_eq!(trim_history(&mut history, 400, 1_000), 0);
+        assert_eq!(history.len(), 6);
+
+        // The first user turn goes with both of its replies.
+        assert_eq!(trim_history(&mut history, 400, 500), 3);
+        assert_eq!(roles(&history), "sum");
+
+        // The system message stays even when nothing else fits.
+        assert_eq!(trim_history(&mut history, 400, 100), 2);
+        assert_eq!(roles(&history), "s");
+    }
+
+    #[tokio::test]
+    async fn with_fallback_moves_to_next_model_when_unavailable() {
+        let backoff = retry::Backoff {
+            retries: 0,
+            ..Default::default()
+        };
+        let req = ChatRequest {
+            model: "primary".into(),
+            ..Default::default()
+        };
+        let fallbacks = ["primary".to_string(), "missing".to_string(), "backup".to_string()];
+        let mut requests = 0;
+        let mut tried = Vec::new();
+        let answer = with_fallback(req, &fallbacks, &backoff, &mut requests, |req| {
+            tried.push(req.model.clone());
+            let status = if req.model == "backup" { None } else { Some(404) };
+            async move {
+                match status {
+                    Some(status) => Err(provider::ApiError::new(status, String::new(), &Default::default()).into()),
+                    None => Ok(req.model),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(answer, "backup");
+        assert_eq!(tried, ["primary", "missing", "backup"]);
+        assert_eq!(requests, 3);
+    }
+}
@@ -0,0 +1,172 @@
+#![cfg(feature = "google")]
+
+//! `--attach`: upload files through the Files API and reference them by URI.
+//! Uploads are remembered so the same unchanged file is not sent again while
+//! the server still holds it.
+
+use crate::provider::google::{GoogleProvider, UploadedFile};
+use crate::provider::FileAttachment;
+use crate::{auth, paths, state};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The API deletes files 48 hours after upload; stop reusing them a little
+/// earlier so a request never references a file that expires mid-flight.
+const REUSE_SECS: u64 = 46 * 60 * 60;
+
+/// How long to wait for an upload to finish processing.
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct UploadsFile {
+    #[serde(default)]
+    entries: Vec<Upload>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Upload {
+    /// Canonical path of the local file.
+    path: PathBuf,
+    size: u64,
+    /// Modification time (seconds since UNIX epoch) when uploaded.
+    modified: u64,
+    /// Seconds since UNIX epoch.
+    uploaded_at: u64,
+    file: UploadedFile,
+}
+
+/// Upload each of `files` (or reuse an earlier upload of it) and wait until
+/// all are ready to use.
+pub async fn attach(provider: &GoogleProvider, files: &[PathBuf]) -> anyhow::Result<Vec<FileAttachment>> {
+    let mut uploads = load()?;
+    let now = auth::now_secs();
+    uploads.entries.retain(|u| u.uploaded_at + REUSE_SECS > now);
+
+    let mut out = Vec::new();
+    for path in files {
+        let canonical = path
+            .canonicalize()
+            .with_context(|| format!("failed to read attachment: {}", path.display()))?;
+        let meta = std::fs::metadata(&canonical).with_context(|| format!("failed to read {}", path.display()))?;
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+
+        let previous = uploads
+            .entries
+            .iter()
+            .position(|u| u.path == canonical && u.size == meta.len() && u.modified == modified);
+        let reused = match previous {
+            // Check it is still there: it may have been deleted server-side.
+            Some(i) => match provider.get_file(&uploads.entries[i].file.name).await {
+                Ok(file) if file.state.as_deref() != Some("FAILED") => Some(file),
+                Ok(_) | Err(_) => {
+                    tracing::debug!(path = %path.display(), "earlier upload is gone; uploading again");
+                    uploads.entries.remove(i);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let file = match reused {
+            Some(file) => {
+                tracing::debug!(path = %path.display(), name = %file.name, "reusing uploaded file");
+                file
+            }
+            None => {
+                let mime_type = mime_type_for(&canonical)?;
+                let bytes = std::fs::read(&canonical).with_context(|| format!("failed to read {}", path.display()))?;
+                let display_name = canonical.file_name().and_then(|n| n.to_str()).unwrap_or("attachment");
+                eprintln!("uploading {} ({} bytes)", path.display(), bytes.len());
+                let file = provider
+                    .upload_file(bytes, mime_type, display_name)
+                    .await
+                    .with_context(|| format!("failed to upload {}", path.display()))?;
+                uploads.entries.push(Upload {
+                    path: canonical,
+                    size: meta.len(),
+                    modified,
+                    uploaded_at: now,
+                    file: file.clone(),
+                });
+                file
+            }
+        };
+
+        let file = provider.wait_for_file(file, PROCESSING_TIMEOUT).await?;
+        out.push(FileAttachment {
+            uri: file.uri,
+            mime_type: file.mime_type,
+        });
+    }
+
+    save(&uploads)?;
+    Ok(out)
+}
+
+/// MIME types the API accepts for uploads, by file extension.
+fn mime_type_for(path: &Path) -> anyhow::Result<&'static str> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    let mime = match ext.as_str() {
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "heif" => "image/heif",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mp3",
+        "aiff" => "audio/aiff",
+        "aac" => "audio/aac",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "mpeg" | "mpg" => "video/mpeg",
+        "mov" => "video/mov",
+        "avi" => "video/avi",
+        "flv" => "video/x-flv",
+        "webm" => "video/webm",
+        "wmv" => "video/wmv",
+        "3gp" | "3gpp" => "video/3gpp",
+        "txt" | "md" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "xml" => "text/xml",
+        "rtf" => "text/rtf",
+        "js" => "application/x-javascript",
+        "py" => "application/x-python",
+        _ => anyhow::bail!(
+            "cannot attach {}: unsupported file type (use PDF, image, audio, video or text files)",
+            path.display()
+        ),
+    };
+    Ok(mime)
+}
+
+/// Format history of `uploaded_files.json` (see [`state`]).
+const MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
+fn load() -> anyhow::Result<UploadsFile> {
+    let path = paths::uploaded_files_path()?;
+    let bytes = match std::fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(UploadsFile::default()),
+        Err(e) => {
+            return Err(anyhow::Error::new(e))
+                .with_context(|| format!("failed to read uploaded files list: {}", path.display()))
+        }
+    };
+    state::from_json(&bytes, MIGRATIONS).with_context(|| format!("failed to parse JSON: {}", path.display()))
+}
+
+fn save(file: &UploadsFile) -> anyhow::Result<()> {
+    let path = paths::uploaded_files_path()?;
+    let bytes = state::to_json(file, MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(&path, &bytes)
+}
@@ -1,7 +1,8 @@
+use crate::{paths, state};
 use anyhow::{anyhow, Context};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// OAuth token persisted on disk.
@@ -17,6 +18,13 @@ pub struct OAuthToken {
 
     /// Lifetime in seconds.
     pub expires_in: Option<u64>,
+
+    /// OAuth client the refresh token belongs to, when it is not the
+    /// configured one (imported tokens).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
 }
 
 impl OAuthToken {
@@ -33,6 +41,22 @@ impl OAuthToken {
         let skew = skew.as_secs();
         now.saturating_add(skew) < exp
     }
+
+    /// A warning for a token that cannot be refreshed, so it stops working
+    /// once the access token expires. `None` if it has a refresh token.
+    pub fn missing_refresh_token_warning(&self) -> Option<String> {
+        if self.refresh_token.is_some() {
+            return None;
+        }
+        let until = match self.expires_at() {
+            Some(exp) => format!(" in {}s", exp.saturating_sub(now_secs())),
+            None => String::new(),
+        };
+        Some(format!(
+            "the OAuth token has no refresh_token and will stop working when it expires{until}; \
+             run `gemini login` to get one (credentials from other tools need offline access, access_type=offline)"
+        ))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -61,7 +85,9 @@ impl OAuthClient {
 struct DeviceCodeResponse {
     device_code: String,
     user_code: String,
-    verification_url: String,
+    /// Google's name for `verification_uri` (RFC 8628).
+    #[serde(default)]
+    verification_url: Option<String>,
     #[serde(default)]
     verification_uri: Option<String>,
     #[serde(default)]
@@ -108,6 +134,7 @@ pub async fn device_login(
         .form(&form)
         .send()
         .await
+        .map_err(crate::net::hinted)
         .context("failed to request device code")?;
 
     let status = resp.status();
@@ -118,11 +145,7 @@ pub async fn device_login(
 
     let dc: DeviceCodeResponse = serde_json::from_slice(&body).context("failed to parse device code JSON")?;
 
-    let verify = dc
-        .verification_uri_complete
-        .clone()
-        .or_else(|| dc.verification_uri.clone())
-        .unwrap_or_else(|| dc.verification_url.clone());
+    let verify = verification_url(&dc)?;
 
     writeln!(out, "Open this URL in your browser and complete sign-in:")?;
     writeln!(out, "  {verify}")?;
@@ -161,6 +184,7 @@ pub async fn device_login(
             .form(&form)
             .send()
             .await
+            .map_err(crate::net::hinted)
             .context("failed to poll token endpoint")?;
 
         let status = resp.status();
@@ -175,6 +199,8 @@ pub async fn device_login(
                 refresh_token: ok.refresh_token,
                 obtained_at: now_secs(),
                 expires_in: ok.expires_in,
+                client_id: None,
+                client_secret: None,
             });
         }
 
@@ -210,6 +236,28 @@ pub async fn device_login(
     }
 }
 
+/// The first well-formed http(s) URL among `verification_uri_complete`,
+/// `verification_uri` and `verification_url`.
+fn verification_url(dc: &DeviceCodeResponse) -> anyhow::Result<Url> {
+    let candidates = [
+        ("verification_uri_complete", &dc.verification_uri_complete),
+        ("verification_uri", &dc.verification_uri),
+        ("verification_url", &dc.verification_url),
+    ];
+    for (field, value) in candidates {
+        let Some(raw) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        match Url::parse(raw) {
+            Ok(url) if matches!(url.scheme(), "https" | "http") => return Ok(url),
+            Ok(_) | Err(_) => tracing::debug!(field, value = raw, "ignoring malformed verification URL"),
+        }
+    }
+    Err(anyhow!(
+        "device code response has no valid verification URL (verification_uri_complete, verification_uri, verification_url)"
+    ))
+}
+
 pub async fn refresh_if_needed(
     http: &reqwest::Client,
     oauth: &OAuthClient,
@@ -219,8 +267,24 @@ pub async fn refresh_if_needed(
         return Ok(token);
     }
 
+    if token.refresh_token.is_none() {
+        return Err(anyhow!(
+            "access token expired and no refresh_token is available; run `gemini login` \
+             (credentials from other tools need offline access, access_type=offline)"
+        ));
+    }
+
+    refresh(http, oauth, token).await
+}
+
+/// Exchange the stored refresh token for a new access token, regardless of expiry.
+pub async fn refresh(
+    http: &reqwest::Client,
+    oauth: &OAuthClient,
+    token: OAuthToken,
+) -> anyhow::Result<OAuthToken> {
     let Some(refresh_token) = token.refresh_token.clone() else {
-        return Err(anyhow!("access token expired and no refresh_token is available; run `gemini login`"));
+        return Err(anyhow!("stored token has no refresh_token; run `gemini login`"));
     };
 
     let mut form: Vec<(&str, String)> = vec![
@@ -237,6 +301,7 @@ pub async fn refresh_if_needed(
         .form(&form)
         .send()
         .await
+        .map_err(crate::net::hinted)
         .context("failed to refresh token")?;
 
     let status = resp.status();
@@ -256,9 +321,194 @@ pub async fn refresh_if_needed(
         refresh_token: token.refresh_token,
         obtained_at: now_secs(),
         expires_in: ok.expires_in,
+        client_id: token.client_id,
+        client_secret: token.client_secret,
+    })
+}
+
+/// The fields of a Google service-account key file that are needed to mint
+/// access tokens.
+#[cfg(feature = "google")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub private_key_id: Option<String>,
+    #[serde(default = "default_token_uri")]
+    pub token_uri: String,
+}
+
+#[cfg(feature = "google")]
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+#[cfg(feature = "google")]
+impl ServiceAccountKey {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let v: serde_json::Value =
+            serde_json::from_slice(&bytes).with_context(|| format!("{} is not valid JSON", path.display()))?;
+        match v.get("type").and_then(|t| t.as_str()) {
+            Some("service_account") => {}
+            other => {
+                return Err(anyhow!(
+                    "{} is not a service-account key (type: {})",
+                    path.display(),
+                    other.unwrap_or("missing")
+                ))
+            }
+        }
+        serde_json::from_value(v).with_context(|| format!("invalid service-account key: {}", path.display()))
+    }
+}
+
+/// Mint an access token for a service account: sign a JWT assertion with
+/// its private key and exchange it at the token endpoint (RFC 7523).
+/// The token's `client_id` records the account's email.
+#[cfg(feature = "google")]
+pub async fn service_account_token(
+    http: &reqwest::Client,
+    key: &ServiceAccountKey,
+    scopes: &[String],
+) -> anyhow::Result<OAuthToken> {
+    #[derive(Serialize)]
+    struct Claims<'a> {
+        iss: &'a str,
+        scope: String,
+        aud: &'a str,
+        iat: u64,
+        exp: u64,
+    }
+
+    let now = now_secs();
+    let claims = Claims {
+        iss: &key.client_email,
+        scope: scopes.join(" "),
+        aud: &key.token_uri,
+        iat: now,
+        exp: now + 3600,
+    };
+    let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = key.private_key_id.clone();
+    let signing_key = jsonwebtoken::EncodingKey::from_rsa_pem(key.private_key.as_bytes())
+        .context("service-account private_key is not a valid RSA PEM key")?;
+    let assertion = jsonwebtoken::encode(&header, &claims, &signing_key).context("failed to sign JWT assertion")?;
+
+    let form = [
+        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+        ("assertion", assertion.as_str()),
+    ];
+    let resp = http
+        .post(&key.token_uri)
+        .form(&form)
+        .send()
+        .await
+        .map_err(crate::net::hinted)
+        .context("failed to request service-account token")?;
+
+    let status = resp.status();
+    let body = resp.bytes().await.context("failed to read token response")?;
+    if !status.is_success() {
+        let msg = String::from_utf8_lossy(&body);
+        return Err(anyhow!("service-account token request failed: HTTP {status}: {msg}"));
+    }
+
+    let ok: TokenSuccessResponse = serde_json::from_slice(&body).context("failed to parse token JSON")?;
+    Ok(OAuthToken {
+        access_token: ok.access_token,
+        token_type: ok.token_type,
+        scope: ok.scope,
+        refresh_token: None,
+        obtained_at: now,
+        expires_in: ok.expires_in,
+        client_id: Some(key.client_email.clone()),
+        client_secret: None,
     })
 }
 
+/// Credential file formats accepted by [`import_token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// gcloud application default credentials (`"type": "authorized_user"`).
+    GcloudAdc,
+    /// The official gemini CLI's `oauth_creds.json`.
+    GeminiCli,
+    /// A token saved by this CLI.
+    Native,
+}
+
+impl std::fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ImportFormat::GcloudAdc => "gcloud application default credentials",
+            ImportFormat::GeminiCli => "gemini CLI OAuth credentials",
+            ImportFormat::Native => "gemini token",
+        })
+    }
+}
+
+/// Map a credentials JSON file from another tool onto [`OAuthToken`].
+pub fn import_token(bytes: &[u8]) -> anyhow::Result<(OAuthToken, ImportFormat)> {
+    let v: serde_json::Value = serde_json::from_slice(bytes).context("credentials file is not valid JSON")?;
+    let str_field = |k: &str| v.get(k).and_then(|x| x.as_str()).filter(|s| !s.is_empty()).map(str::to_string);
+
+    match v.get("type").and_then(|t| t.as_str()) {
+        Some("authorized_user") => {
+            let refresh_token = str_field("refresh_token").context("authorized_user credentials have no refresh_token")?;
+            let client_id = str_field("client_id").context("authorized_user credentials have no client_id")?;
+            // No access token: expired from the start, so the first use refreshes.
+            let tok = OAuthToken {
+                access_token: String::new(),
+                token_type: "Bearer".to_string(),
+                scope: None,
+                refresh_token: Some(refresh_token),
+                obtained_at: now_secs(),
+                expires_in: Some(0),
+                client_id: Some(client_id),
+                client_secret: str_field("client_secret"),
+            };
+            return Ok((tok, ImportFormat::GcloudAdc));
+        }
+        Some(other) => return Err(anyhow!("unsupported credentials type: {other}")),
+        None => {}
+    }
+
+    if v.get("obtained_at").is_some() {
+        let tok: OAuthToken = serde_json::from_value(v).context("failed to parse token JSON")?;
+        return Ok((tok, ImportFormat::Native));
+    }
+
+    if let Some(access_token) = str_field("access_token") {
+        // google-auth-library credentials: `expiry_date` is in milliseconds.
+        let now = now_secs();
+        let expires_in = v
+            .get("expiry_date")
+            .and_then(|x| x.as_u64())
+            .map(|ms| (ms / 1000).saturating_sub(now));
+        let tok = OAuthToken {
+            access_token,
+            token_type: str_field("token_type").unwrap_or_else(|| "Bearer".to_string()),
+            scope: str_field("scope"),
+            refresh_token: str_field("refresh_token"),
+            obtained_at: now,
+            expires_in,
+            client_id: None,
+            client_secret: None,
+        };
+        return Ok((tok, ImportFormat::GeminiCli));
+    }
+
+    Err(anyhow!(
+        "unrecognized credentials format (expected gcloud application default credentials or gemini CLI oauth_creds.json)"
+    ))
+}
+
+/// Format history of saved token files (see [`state`]).
+const TOKEN_MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
 pub fn load_token(path: impl AsRef<Path>) -> anyhow::Result<Option<OAuthToken>> {
     let path = path.as_ref();
     let bytes = match std::fs::read(path) {
@@ -266,37 +516,62 @@ pub fn load_token(path: impl AsRef<Path>) -> anyhow::Result<Option<OAuthToken>>
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
         Err(e) => return Err(anyhow!(e)).with_context(|| format!("failed to read token: {}", path.display())),
     };
-    let tok: OAuthToken = serde_json::from_slice(&bytes).context("failed to parse token JSON")?;
+    let tok: OAuthToken = state::from_json(&bytes, TOKEN_MIGRATIONS).context("failed to parse token JSON")?;
     Ok(Some(tok))
 }
 
 pub fn save_token_atomic(path: impl AsRef<Path>, tok: &OAuthToken) -> anyhow::Result<()> {
-    let path = path.as_ref();
-    let dir = path.parent().unwrap_or_else(|| Path::new("."));
-    std::fs::create_dir_all(dir)
-        .with_context(|| format!("failed to create token directory: {}", dir.display()))?;
-
-    let tmp = tmp_path(path);
-    let bytes = serde_json::to_vec_pretty(tok).context("failed to serialize token")?;
-    std::fs::write(&tmp, bytes).with_context(|| format!("failed to write temp token: {}", tmp.display()))?;
-    std::fs::rename(&tmp, path)
-        .with_context(|| format!("failed to move token into place: {}", path.display()))?;
-    Ok(())
-}
-
-fn tmp_path(path: &Path) -> PathBuf {
-    let mut p = path.to_path_buf();
-    let file = path
-        .file_name()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_else(|| "token.json".to_string());
-    p.set_file_name(format!("{file}.tmp"));
-    p
+    let bytes = state::to_json(tok, TOKEN_MIGRATIONS).context("failed to serialize token")?;
+    paths::write_private_atomic(path.as_ref(), &bytes)
 }
 
-fn now_secs() -> u64 {
+pub fn now_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_else(|_| Duration::from_secs(0))
         .as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pick(url: Option<&str>, uri: Option<&str>, complete: Option<&str>) -> anyhow::Result<String> {
+        let dc = DeviceCodeResponse {
+            device_code: "dev".into(),
+            user_code: "ABCD-EFGH".into(),
+            verification_url: url.map(String::from),
+            verification_uri: uri.map(String::from),
+            verification_uri_complete: complete.map(String::from),
+            expires_in: 1800,
+            interval: None,
+        };
+        verification_url(&dc).map(String::from)
+    }
+
+    const URL: &str = "https://www.google.com/device";
+    const URI: &str = "https://example.com/device";
+    const COMPLETE: &str = "https://example.com/device?user_code=ABCD-EFGH";
+
+    #[test]
+    fn verification_url_prefers_complete_then_uri_then_url() {
+        assert_eq!(pick(Some(URL), Some(URI), Some(COMPLETE)).unwrap(), COMPLETE);
+        assert_eq!(pick(Some(URL), Some(URI), None).unwrap(), URI);
+        assert_eq!(pick(Some(URL), None, Some(COMPLETE)).unwrap(), COMPLETE);
+        assert_eq!(pick(Some(URL), None, None).unwrap(), URL);
+        assert_eq!(pick(None, Some(URI), None).unwrap(), URI);
+    }
+
+    #[test]
+    fn verification_url_skips_blank_and_malformed_values() {
+        assert_eq!(pick(Some(URL), Some("  "), Some("")).unwrap(), URL);
+        assert_eq!(pick(Some(URL), Some("not a url"), Some("javascript:alert(1)")).unwrap(), URL);
+        assert_eq!(pick(Some(&format!(" {URL} ")), None, None).unwrap(), URL);
+    }
+
+    #[test]
+    fn verification_url_requires_a_valid_candidate() {
+        assert!(pick(None, None, None).is_err());
+        assert!(pick(Some("file:///etc/passwd"), Some("ftp://example.com/"), None).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! `gemini bench`: the same prompt against several models (and providers),
+//! with latency, token usage and output side by side.
+
+use crate::{app, config};
+use gemini::provider::{ChatEvent, ChatRequest, Provider};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+
+/// Providers a target may name as `PROVIDER:MODEL`.
+const PROVIDERS: &[&str] = &["google", "stub"];
+
+#[derive(Debug, Clone, Serialize)]
+struct Target {
+    provider: String,
+    model: String,
+}
+
+impl Target {
+    /// `MODEL`, or `PROVIDER:MODEL` for a known provider.
+    fn parse(spec: &str, default_provider: &str) -> Self {
+        match spec.split_once(':') {
+            Some((provider, model)) if PROVIDERS.contains(&provider) => Self {
+                provider: provider.to_string(),
+                model: model.to_string(),
+            },
+            _ => Self {
+                provider: default_provider.to_string(),
+                model: spec.to_string(),
+            },
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("{}:{}", self.provider, self.model)
+    }
+}
+
+/// One request. Times are from just before it was sent.
+#[derive(Debug, Clone, Default, Serialize)]
+struct Run {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Until the first answer text.
+    time_to_first_token_ms: Option<u128>,
+    duration_ms: u128,
+    prompt_tokens: u64,
+    output_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finish_reason: Option<String>,
+    output: String,
+}
+
+/// Averages over a target's successful runs.
+#[derive(Debug, Clone, Default, Serialize)]
+struct Summary {
+    runs: usize,
+    ok: usize,
+    avg_time_to_first_token_ms: Option<u128>,
+    avg_duration_ms: Option<u128>,
+    avg_output_tokens: Option<u64>,
+    /// Output tokens over the time spent after the first token.
+    tokens_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TargetResult {
+    #[serde(flatten)]
+    target: Target,
+    summary: Summary,
+    runs: Vec<Run>,
+}
+
+pub struct BenchOptions {
+    pub prompt: String,
+    /// Empty: `default_model` on `default_provider`.
+    pub models: Vec<String>,
+    pub repeat: u32,
+    pub concurrency: u32,
+    pub json: bool,
+}
+
+pub async fn cmd_bench(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    provider_opts: &app::ProviderOptions,
+    default_provider: &str,
+    default_model: String,
+    opts: BenchOptions,
+) -> anyhow::Result<()> {
+    let targets: Vec<Target> = if opts.models.is_empty() {
+        vec![Target {
+            provider: default_provider.to_string(),
+            model: default_model,
+        }]
+    } else {
+        opts.models.iter().map(|m| Target::parse(m, default_provider)).collect()
+    };
+
+    // One provider (and its credentials) per name, shared by all its runs.
+    let mut providers: BTreeMap<String, Arc<dyn Provider + Send + Sync>> = BTreeMap::new();
+    for t in &targets {
+        if !providers.contains_key(&t.provider) {
+            let p = app::build_provider(http, cfg, &t.provider, provider_opts).await?;
+            providers.insert(t.provider.clone(), Arc::from(p));
+        }
+    }
+
+    let permits = Arc::new(tokio::sync::Semaphore::new(opts.concurrency as usize));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (ti, t) in targets.iter().enumerate() {
+        for ri in 0..opts.repeat as usize {
+            let provider = providers[&t.provider].clone();
+            let permits = permits.clone();
+            let req = ChatRequest {
+                model: t.model.clone(),
+                prompt: opts.prompt.clone(),
+                request_id: Some(app::new_request_id()),
+                ..Default::default()
+            };
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                (ti, ri, run_once(provider.as_ref(), req).await)
+            });
+        }
+    }
+
+    let mut runs: Vec<Vec<Run>> = vec![vec![Run::default(); opts.repeat as usize]; targets.len()];
+    while let Some(done) = tasks.join_next().await {
+        let (ti, ri, run) = done?;
+        if !opts.json {
+            let status = run.error.as_deref().map_or("ok".to_string(), |e| format!("failed: {e}"));
+            eprintln!("{} run {}: {status} ({} ms)", targets[ti].label(), ri + 1, run.duration_ms);
+        }
+        runs[ti][ri] = run;
+    }
+
+    let results: Vec<TargetResult> = targets
+        .into_iter()
+        .zip(runs)
+        .map(|(target, runs)| TargetResult {
+            target,
+            summary: summarize(&runs),
+            runs,
+        })
+        .collect();
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        print_table(&results);
+    }
+    if results.iter().all(|r| r.summary.ok == 0) {
+        anyhow::bail!("every run failed");
+    }
+    Ok(())
+}
+
+async fn run_once(provider: &(dyn Provider + Send + Sync), req: ChatRequest) -> Run {
+    let started = Instant::now();
+    let mut run = Run::default();
+    let result: anyhow::Result<()> = async {
+        let mut stream = provider.stream_chat(req).await?;
+        while let Some(event) = stream.next().await {
+            match event? {
+                ChatEvent::Text(t) => {
+                    run.time_to_first_token_ms.get_or_insert_with(|| started.elapsed().as_millis());
+                    run.output.push_str(&t);
+                }
+                ChatEvent::Usage(u) => {
+                    run.prompt_tokens = u.prompt_tokens;
+                    run.output_tokens = u.output_tokens;
+                }
+                ChatEvent::Finish { reason } => run.finish_reason = Some(reason),
+                ChatEvent::Blocked { reason } => run.finish_reason = Some(format!("blocked: {reason}")),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    .await;
+    run.duration_ms = started.elapsed().as_millis();
+    if let Err(e) = result {
+        run.error = Some(format!("{e:#}"));
+    }
+    run
+}
+
+fn summarize(runs: &[Run]) -> Summary {
+    let ok: Vec<&Run> = runs.iter().filter(|r| r.error.is_none()).collect();
+    let n = ok.len() as u128;
+    let avg = |f: &dyn Fn(&Run) -> u128| (n > 0).then(|| ok.iter().map(|r| f(r)).sum::<u128>() / n);
+    let ttft: Vec<u128> = ok.iter().filter_map(|r| r.time_to_first_token_ms).collect();
+    let generating: Duration = ok
+        .iter()
+        .map(|r| Duration::from_millis((r.duration_ms - r.time_to_first_token_ms.unwrap_or(r.duration_ms)) as u64))
+        .sum();
+    let output_tokens: u64 = ok.iter().map(|r| r.output_tokens).sum();
+    Summary {
+        runs: runs.len(),
+        ok: ok.len(),
+        avg_time_to_first_token_ms: (!ttft.is_empty()).then(|| ttft.iter().sum::<u128>() / ttft.len() as u128),
+        avg_duration_ms: avg(&|r| r.duration_ms),
+        avg_output_tokens: avg(&|r| u128::from(r.output_tokens)).map(|t| t as u64),
+        tokens_per_sec: (!generating.is_zero()).then(|| output_tokens as f64 / generating.as_secs_f64()),
+    }
+}
+
+fn print_table(results: &[TargetResult]) {
+    let ms = |v: Option<u128>| v.map_or("-".to_string(), |v| v.to_string());
+    let width = results.iter().map(|r| r.target.label().chars().count()).max().unwrap_or(0).max(6);
+    println!(
+        "{:<width$}  {:>7}  {:>9}  {:>9}  {:>10}  {:>7}",
+        "target", "ok", "ttft ms", "total ms", "out tokens", "tok/s"
+    );
+    for r in results {
+        let s = &r.summary;
+        println!(
+            "{:<width$}  {:>7}  {:>9}  {:>9}  {:>10}  {:>7}",
+            r.target.label(),
+            format!("{}/{}", s.ok, s.runs),
+            ms(s.avg_time_to_first_token_ms),
+            ms(s.avg_duration_ms),
+            s.avg_output_tokens.map_or("-".to_string(), |t| t.to_string()),
+            s.tokens_per_sec.map_or("-".to_string(), |t| format!("{t:.1}")),
+        );
+    }
+    for r in results {
+        println!("\n--- {} ---", r.target.label());
+        match r.runs.iter().find(|run| run.error.is_none()) {
+            Some(run) => println!("{}", run.output.trim_end()),
+            None => println!("(no successful run)"),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "google")]
+
+use crate::cli::CacheCommand;
+use crate::provider::google::{CachedContent, CreateCachedContent};
+use crate::{app, config, paths, state};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Cached content handles created by this CLI, so they can be referenced by
+/// display name.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct CachedContentsFile {
+    #[serde(default)]
+    entries: Vec<CachedContent>,
+}
+
+pub async fn cmd_cache_content(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    provider_opts: &app::ProviderOptions,
+    model: String,
+    cmd: CacheCommand,
+) -> anyhow::Result<()> {
+    let provider = app::build_google_provider(http, cfg, provider_opts).await?;
+
+    match cmd {
+        CacheCommand::Create {
+            files,
+            system,
+            display_name,
+            ttl,
+        } => {
+            if files.is_empty() && system.is_none() {
+                anyhow::bail!("nothing to cache: pass --file and/or --system");
+            }
+
+            let mut texts = Vec::new();
+            for f in &files {
+                let text = std::fs::read_to_string(f)
+                    .with_context(|| format!("failed to read {}", f.display()))?;
+                texts.push(format!("File: {}\n\n{text}", f.display()));
+            }
+
+            let created = provider
+                .create_cached_content(&CreateCachedContent {
+                    model,
+                    display_name,
+                    system,
+                    texts,
+                    ttl_secs: ttl,
+                })
+                .await?;
+
+            let mut file = load()?;
+            file.entries.retain(|e| e.name != created.name);
+            file.entries.push(created.clone());
+            save(&file)?;
+
+            println!("{}", created.name);
+            Ok(())
+        }
+        CacheCommand::List => {
+            let items = provider.list_cached_contents().await?;
+            if items.is_empty() {
+                println!("(no cached contents)");
+                return Ok(());
+            }
+            for c in &items {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    c.name,
+                    c.display_name.as_deref().unwrap_or(""),
+                    c.model.as_deref().unwrap_or(""),
+                    c.expire_time.as_deref().unwrap_or("")
+                );
+            }
+            Ok(())
+        }
+        CacheCommand::Delete { name } => {
+            let name = resolve_name(&name)?;
+            provider.delete_cached_content(&name).await?;
+
+            let mut file = load()?;
+            file.entries.retain(|e| e.name != name);
+            save(&file)?;
+            Ok(())
+        }
+    }
+}
+
+/// Map a `--cached-content` argument to a `cachedContents/...` resource name.
+///
+/// Full names pass through; otherwise a saved display name is looked up, and
+/// a bare id is prefixed.
+pub fn resolve_name(name: &str) -> anyhow::Result<String> {
+    if name.contains('/') {
+        return Ok(name.to_string());
+    }
+    let file = load()?;
+    if let Some(e) = file
+        .entries
+        .iter()
+        .find(|e| e.display_name.as_deref() == Some(name))
+    {
+        return Ok(e.name.clone());
+    }
+    Ok(format!("cachedContents/{name}"))
+}
+
+/// Format history of `cached_contents.json` (see [`state`]).
+const MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
+fn load() -> anyhow::Result<CachedContentsFile> {
+    let path = paths::cached_contents_path()?;
+    let bytes = match std::fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(CachedContentsFile::default()),
+        Err(e) => {
+            return Err(anyhow::Error::new(e))
+                .with_context(|| format!("failed to read cached contents file: {}", path.display()))
+        }
+    };
+    state::from_json(&bytes, MIGRATIONS).with_context(|| format!("failed to parse JSON: {}", path.display()))
+}
+
+fn save(file: &CachedContentsFile) -> anyhow::Result<()> {
+    let path = paths::cached_contents_path()?;
+    let bytes = state::to_json(file, MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(&path, &bytes)
+}
@@ -11,14 +11,310 @@ pub struct Args {
     #[arg(short = 'm', long = "model")]
     pub model: Option<String>,
 
-    /// Directories to include as context (placeholder)
+    /// Sampling temperature, 0.0-2.0
+    #[arg(long = "temperature", value_name = "T")]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling probability mass, 0.0-1.0
+    #[arg(long = "top-p", value_name = "P")]
+    pub top_p: Option<f32>,
+
+    /// Sample from the K most likely tokens (>= 1)
+    #[arg(long = "top-k", value_name = "K")]
+    pub top_k: Option<u32>,
+
+    /// Stop after at most N generated tokens
+    #[arg(long = "max-tokens", value_name = "N")]
+    pub max_tokens: Option<u32>,
+
+    /// Stop generating when the answer would contain TEXT (repeatable, up to 5)
+    #[arg(long = "stop", value_name = "TEXT")]
+    pub stop: Vec<String>,
+
+    /// Penalize tokens that already appeared (-2.0-2.0)
+    #[arg(long = "presence-penalty", value_name = "P", allow_negative_numbers = true)]
+    pub presence_penalty: Option<f32>,
+
+    /// Penalize tokens by how often they appeared (-2.0-2.0)
+    #[arg(long = "frequency-penalty", value_name = "P", allow_negative_numbers = true)]
+    pub frequency_penalty: Option<f32>,
+
+    /// Sampling seed
+    #[arg(long = "seed", value_name = "N")]
+    pub seed: Option<i32>,
+
+    /// Report per-token log probabilities with N top alternatives (0-20; table on stderr, or structured in ndjson)
+    #[arg(long = "logprobs", value_name = "N")]
+    pub logprobs: Option<u32>,
+
+    /// MIME type the answer must have, passed to the API as-is (e.g.
+    /// application/json, text/x.enum)
+    #[arg(long = "response-mime-type", value_name = "MIME")]
+    pub response_mime_type: Option<String>,
+
+    /// Answer with exactly one of these comma-separated values (e.g. for
+    /// classification); any other answer is an error
+    #[arg(long = "enum", value_name = "VALUES", value_delimiter = ',', conflicts_with = "response_mime_type")]
+    pub enum_values: Vec<String>,
+
+    /// Shorthand for --temperature 0 with a fixed seed (unless --seed is given)
+    #[arg(long = "deterministic", conflicts_with = "temperature")]
+    pub deterministic: bool,
+
+    /// Model to fall back to if the previous one is unavailable (repeatable; default: config model_fallbacks)
+    #[arg(long = "fallback-model", value_name = "MODEL")]
+    pub fallback_models: Vec<String>,
+
+    /// Retries on HTTP 429/5xx before falling back or failing (exponential backoff with jitter)
+    #[arg(long = "retries", value_name = "N", default_value_t = 3)]
+    pub retries: u32,
+
+    /// Retry once when the response is empty without a block or finish reason
+    #[arg(long = "retry-empty")]
+    pub retry_empty: bool,
+
+    /// Never prompt (e.g. for credentials); fail instead
+    #[arg(long = "non-interactive")]
+    pub non_interactive: bool,
+
+    /// Use the stored OAuth access token as-is (no refresh request, even if expired)
+    #[arg(long = "no-refresh")]
+    pub no_refresh: bool,
+
+    /// Directories to include as context (files are read, honoring .gitignore)
     #[arg(long = "include-directories", value_name = "DIR")]
     pub include_directories: Vec<PathBuf>,
 
+    /// Only include files with these extensions (comma-separated, e.g. rs,md)
+    #[arg(long = "include-ext", value_name = "EXT", value_delimiter = ',')]
+    pub include_ext: Vec<String>,
+
+    /// Skip included paths matching this glob (repeatable, e.g. '**/target/**')
+    #[arg(long = "exclude-glob", value_name = "GLOB")]
+    pub exclude_globs: Vec<String>,
+
+    /// Fetch a web page and include its text as context (repeatable)
+    #[arg(long = "context-from-url", value_name = "URL")]
+    pub context_from_url: Vec<reqwest::Url>,
+
+    /// Timeout for each --context-from-url fetch, in seconds
+    #[arg(long = "url-timeout", value_name = "SECS", default_value_t = 20)]
+    pub url_timeout: u64,
+
+    /// Upper bound on included directory and URL context, in bytes
+    #[arg(long = "max-context-bytes", value_name = "N")]
+    pub max_context_bytes: Option<usize>,
+
+    /// Honor proxy environment variables (default; overrides config trust_env = false)
+    #[arg(long = "trust-env", overrides_with = "no_trust_env")]
+    pub trust_env: bool,
+
+    /// Ignore proxy environment variables for a hermetic HTTP client
+    #[arg(long = "no-trust-env", overrides_with = "trust_env")]
+    pub no_trust_env: bool,
+
+    /// Fail on unknown keys in config.toml instead of ignoring them
+    #[arg(long = "strict-config")]
+    pub strict_config: bool,
+
+    /// Refuse to send requests estimated above this many tokens
+    #[arg(long = "max-request-tokens", value_name = "N")]
+    pub max_request_tokens: Option<usize>,
+
+    /// Summarize included context with a cheaper model (default gemini-1.5-flash-8b;
+    /// pick one with --context-summary=MODEL) and send the summary instead
+    #[arg(long = "context-summary", value_name = "MODEL", num_args = 0..=1,
+          require_equals = true, default_missing_value = crate::summary::DEFAULT_SUMMARY_MODEL)]
+    pub context_summary: Option<String>,
+
+    /// Mark included context as data, not instructions: wrap (default) or escape
+    /// (also quote lines that look like prompt directives)
+    #[arg(long = "untrusted-context", value_enum, value_name = "MODE",
+          num_args = 0..=1, default_missing_value = "wrap")]
+    pub untrusted_context: Option<crate::context::UntrustedContext>,
+
+    /// Remove ANSI escapes and control characters from included context (default)
+    #[arg(long = "strip-ansi", overrides_with = "no_strip_ansi")]
+    pub strip_ansi: bool,
+
+    /// Include context byte-for-byte, escape sequences and all
+    #[arg(long = "no-strip-ansi", overrides_with = "strip_ansi")]
+    pub no_strip_ansi: bool,
+
+    /// How included files and pages are laid out in the prompt (default: markdown)
+    #[arg(long = "context-format", value_enum, value_name = "FORMAT")]
+    pub context_format: Option<crate::context::ContextFormat>,
+
+    /// Which included files to keep when they exceed --max-context-bytes (default: fail)
+    #[arg(long = "context-budget-strategy", value_enum, value_name = "STRATEGY", requires = "max_context_bytes")]
+    pub context_budget_strategy: Option<crate::context::BudgetStrategy>,
+
+    /// Priority list for --context-budget-strategy manifest (one path per line)
+    #[arg(long = "context-manifest", value_name = "FILE")]
+    pub context_manifest: Option<PathBuf>,
+
     /// Provider (default: config/provider or "google")
     #[arg(long = "provider")]
     pub provider: Option<String>,
 
+    /// Upload a file (PDF, image, audio, video, text) with the Files API and send
+    /// it with the prompt; repeatable. Uploads are reused while they last (48h)
+    #[cfg(feature = "google")]
+    #[arg(long = "attach", value_name = "PATH")]
+    pub attach: Vec<PathBuf>,
+
+    /// Ground the answer in Google Search results and list the sources
+    /// (references after text output; a `citations` object in ndjson)
+    #[arg(long = "search")]
+    pub search: bool,
+
+    /// Reference a context cache by name (`cachedContents/...` or a display name saved by `cache-content create`)
+    #[cfg(feature = "google")]
+    #[arg(long = "cached-content", value_name = "NAME")]
+    pub cached_content: Option<String>,
+
+    /// Call an MCP tool first and send its output as context: '<server> <tool> [json args]'
+    #[cfg(feature = "mcp")]
+    #[arg(long = "tool-result-from", value_name = "SPEC")]
+    pub tool_result_from: Option<String>,
+
+    /// Text placed before the prompt (default: config prompt_prefix)
+    #[arg(long = "prompt-prefix", value_name = "TEXT")]
+    pub prompt_prefix: Option<String>,
+
+    /// Text placed after the prompt (default: config prompt_suffix)
+    #[arg(long = "prompt-suffix", value_name = "TEXT")]
+    pub prompt_suffix: Option<String>,
+
+    /// System instruction for this request, added after the config's and the
+    /// project's (.gemini.toml) instructions
+    #[arg(long = "system", value_name = "TEXT")]
+    pub system: Option<String>,
+
+    /// Print the final prompt to stderr before sending
+    #[arg(long = "echo-prompt")]
+    pub echo_prompt: bool,
+
+    /// Output format
+    #[arg(long = "output-format", value_enum, default_value_t = crate::output::OutputFormat::Text)]
+    pub output_format: crate::output::OutputFormat,
+
+    /// Print the request that would be sent (API key redacted) and exit
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Google API version to call (overrides google.api_version; default v1beta)
+    #[arg(long = "api-version", value_name = "VERSION", value_parser = ["v1", "v1beta", "v1alpha"])]
+    pub api_version: Option<String>,
+
+    /// How to call the Google API: sse (streamGenerateContent?alt=sse, default),
+    /// chunked (streamed JSON array) or oneshot (generateContent, no streaming)
+    #[cfg(feature = "google")]
+    #[arg(long = "endpoint-streaming-mode", value_enum, value_name = "MODE")]
+    pub streaming_mode: Option<crate::provider::google::StreamingMode>,
+
+    /// Print this request's correlation id (also used in logs and errors) to stderr
+    #[arg(long = "echo-request-id")]
+    pub echo_request_id: bool,
+
+    /// On an API error, also print the full response (status, headers, body) to stderr
+    #[arg(long = "show-raw-errors")]
+    pub show_raw_errors: bool,
+
+    /// Print the request URL (API key redacted) to stderr before sending
+    #[arg(long = "print-url")]
+    pub print_url: bool,
+
+    /// After the response, print transfer stats (time to first byte, duration,
+    /// chunks, bytes, tokens/sec, retries) to stderr
+    #[arg(long = "stats")]
+    pub stats: bool,
+
+    /// After the response, print its token counts (prompt, output, total) to stderr
+    #[arg(long = "show-usage")]
+    pub show_usage: bool,
+
+    /// Show the response in $GEMINI_PAGER/$PAGER (default less) once it completes,
+    /// instead of streaming it; only when stdout is a terminal
+    #[arg(long = "pager", conflicts_with = "typewriter")]
+    pub pager: bool,
+
+    /// Send the request but discard the response; print a one-line result
+    /// (status, tokens, duration) to stderr. For health checks and warm-ups
+    #[arg(
+        long = "quiet-stream",
+        conflicts_with_all = ["pager", "typewriter", "output_file", "only_final", "session"]
+    )]
+    pub quiet_stream: bool,
+
+    /// Cancel the request as soon as the first chunk of the response arrives
+    /// and print the time it took; for comparing model and region latency
+    #[arg(
+        long = "abort-after-first-chunk",
+        conflicts_with_all = ["quiet_stream", "output_file", "only_final", "session", "stats", "show_usage"]
+    )]
+    pub abort_after_first_chunk: bool,
+
+    /// Wait for the whole answer and print it at once (text output; no
+    /// streaming or usage)
+    #[arg(
+        long = "no-stream",
+        conflicts_with_all = [
+            "abort_after_first_chunk", "quiet_stream", "typewriter", "pager", "max_tokens_per_minute",
+            "only_final", "session", "stats", "retry_empty", "show_usage",
+        ]
+    )]
+    pub no_stream: bool,
+
+    /// Print text output at a steady rate (characters per second) when stdout is a terminal
+    #[arg(long = "typewriter", value_name = "CHARS_PER_SEC", value_parser = clap::value_parser!(u32).range(1..))]
+    pub typewriter: Option<u32>,
+
+    /// Release output (stdout or --output-file, any format) at no more than
+    /// this many tokens per minute (about 4 characters per token), for
+    /// throttled downstream consumers; the response is still received at full speed
+    #[arg(
+        long = "max-tokens-per-minute",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["typewriter", "pager", "quiet_stream"]
+    )]
+    pub max_tokens_per_minute: Option<u32>,
+
+    /// Write the response to this file instead of stdout
+    #[arg(long = "output-file", value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    /// Refuse to send a request once this many tokens would be used in total
+    /// (by this --session, or by this run; in the TUI, by the whole chat)
+    #[arg(long = "budget-tokens-total", value_name = "N")]
+    pub budget_tokens_total: Option<u64>,
+
+    /// Billing label for Vertex AI requests (repeatable; overrides google.labels; the Gemini API rejects them)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub labels: Vec<(String, String)>,
+
+    /// Continue the named conversation: send its earlier turns and append this one
+    #[arg(long = "session", value_name = "NAME")]
+    pub session: Option<String>,
+
+    /// Don't save this response for `gemini last`
+    #[arg(long = "no-save-last")]
+    pub no_save_last: bool,
+
+    /// Print only the final answer text once the response completes, without
+    /// intermediate text before tool calls (text and md formats)
+    #[arg(long = "only-final")]
+    pub only_final: bool,
+
+    /// Suppress text that the stream re-sends across overlapping events (workaround)
+    #[arg(long = "dedup-stream")]
+    pub dedup_stream: bool,
+
+    /// Mask text matching this regex in the answer as it streams; repeatable (not with json or sse output)
+    #[arg(long = "redact", value_name = "REGEX")]
+    pub redact: Vec<String>,
+
     #[command(subcommand)]
     pub cmd: Option<Command>,
 
@@ -32,9 +328,96 @@ pub enum Command {
     /// Authenticate using Google OAuth device-code flow and save token under state
     Login,
 
+    /// Manage the stored OAuth token
+    Auth {
+        #[command(subcommand)]
+        cmd: AuthCommand,
+    },
+
+    /// Print the most recent response again
+    Last {
+        /// Print the saved record (model, prompt, response, time) as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
+
+    /// Print a shell completion script (bash, zsh, fish, powershell, elvish)
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Inspect or change the config file
+    Config {
+        #[command(subcommand)]
+        cmd: ConfigCommand,
+    },
+
+    /// Manage conversations kept with --session
+    Session {
+        #[command(subcommand)]
+        cmd: SessionCommand,
+    },
+
+    /// Run one prompt against several models and compare latency, tokens and output
+    Bench {
+        /// Prompt sent to every model
+        #[arg(required = true)]
+        prompt: Vec<String>,
+        /// Comma-separated MODEL or PROVIDER:MODEL entries (default: the configured model)
+        #[arg(long = "models", value_name = "LIST", value_delimiter = ',')]
+        models: Vec<String>,
+        /// Send the prompt this many times per model and average the results
+        #[arg(long = "repeat", value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+        /// Requests in flight at once
+        #[arg(long = "concurrency", value_name = "N", default_value_t = 2,
+              value_parser = clap::value_parser!(u32).range(1..))]
+        concurrency: u32,
+        /// Print per-model summaries and every run as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
+
+    /// List available models or show one model's metadata
+    #[cfg(feature = "google")]
+    Models {
+        #[command(subcommand)]
+        cmd: ModelsCommand,
+    },
+
+    /// Manage Gemini context caches (cachedContents API)
+    #[cfg(feature = "google")]
+    CacheContent {
+        #[command(subcommand)]
+        cmd: CacheCommand,
+    },
+
     /// Run an interactive terminal chat UI
     #[cfg(feature = "tui")]
-    Tui,
+    Tui {
+        /// While idle, periodically ping the API to keep the connection warm
+        #[arg(long = "keep-warm")]
+        keep_warm: bool,
+        /// Message layout: compact (role line + text) or blocks (bordered, with role badges)
+        #[arg(long = "message-style", value_enum, default_value_t = crate::tui::MessageStyle::Compact)]
+        message_style: crate::tui::MessageStyle,
+        /// Request this many candidates per turn and stream them side by side (1-8)
+        #[arg(long = "candidates", value_name = "N", default_value_t = 1,
+              value_parser = clap::value_parser!(u32).range(1..=8))]
+        candidates: u32,
+        /// Continue a conversation saved with /save (name or path)
+        #[arg(long = "resume", value_name = "NAME")]
+        resume: Option<String>,
+        /// Only browse the resumed transcript; nothing is sent
+        #[arg(long = "read-only", requires = "resume")]
+        read_only: bool,
+        /// Screen-reader friendly: no borders or styling, "You:"/"Assistant:"
+        /// labels, fewer redraws (styling is also off when NO_COLOR is set)
+        #[arg(long = "simple")]
+        simple: bool,
+    },
 
     /// Manage MCP stdio servers (config) and inspect tools
     #[cfg(feature = "mcp")]
@@ -44,6 +427,91 @@ pub enum Command {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum AuthCommand {
+    /// Refresh the stored OAuth token now (even if still valid) and print its new expiry
+    Refresh,
+    /// Import credentials from gcloud (application default credentials) or the official gemini CLI
+    Import {
+        /// Credentials JSON, e.g. ~/.config/gcloud/application_default_credentials.json or ~/.gemini/oauth_creds.json
+        #[arg(long = "from", value_name = "PATH")]
+        from: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Check config.toml for unknown keys, conflicting or invalid settings,
+    /// with suggested fixes
+    Doctor {
+        /// Also check that the API base URLs are reachable
+        #[arg(long = "check-network")]
+        check_network: bool,
+    },
+    /// Set a setting (dotted key, e.g. `model` or `google.api_version`),
+    /// keeping the rest of the file as it is
+    Set {
+        key: String,
+        value: String,
+        /// Allow writing credentials (API keys, client secrets), which
+        /// otherwise stay out of shell history
+        #[arg(long = "allow-secret")]
+        allow_secret: bool,
+    },
+    /// Print a setting's value
+    Get { key: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    /// List sessions with their turn counts
+    List,
+    /// Delete a session's history
+    Clear { name: String },
+}
+
+#[cfg(feature = "google")]
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Create a cached content handle from files and/or a system instruction
+    Create {
+        /// File to include in the cache (repeatable)
+        #[arg(long = "file", value_name = "PATH")]
+        files: Vec<PathBuf>,
+        /// System instruction to cache
+        #[arg(long = "system", value_name = "TEXT")]
+        system: Option<String>,
+        /// Display name, usable with --cached-content
+        #[arg(long = "display-name", value_name = "NAME")]
+        display_name: Option<String>,
+        /// Time to live in seconds
+        #[arg(long = "ttl", value_name = "SECS", default_value_t = 3600)]
+        ttl: u64,
+    },
+    /// List cached contents
+    List,
+    /// Delete a cached content handle
+    Delete {
+        /// `cachedContents/...` name or a saved display name
+        name: String,
+    },
+}
+
+#[cfg(feature = "google")]
+#[derive(Debug, Subcommand)]
+pub enum ModelsCommand {
+    /// List models with their token limits
+    List,
+    /// Show a model's description, version, limits, methods and sampling defaults
+    Show {
+        /// Model name, with or without the `models/` prefix
+        name: String,
+        /// Print the metadata as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
+}
+
 #[cfg(feature = "mcp")]
 #[derive(Debug, Subcommand)]
 pub enum McpCommand {
@@ -51,19 +519,110 @@ pub enum McpCommand {
     Add {
         /// Server name
         name: String,
-        /// Command to execute (e.g. "node")
-        command: String,
+        /// Command to execute (e.g. "node"); with --npx/--uvx/--docker, the first extra arg
+        #[arg(required_unless_present_any = ["npx", "uvx", "docker"])]
+        command: Option<String>,
         /// Remaining args passed to the command
         args: Vec<String>,
+        /// Run an npm package: `npx -y <PACKAGE> [ARGS]`
+        #[arg(long = "npx", value_name = "PACKAGE", group = "launcher")]
+        npx: Option<String>,
+        /// Run a Python package: `uvx <PACKAGE> [ARGS]`
+        #[arg(long = "uvx", value_name = "PACKAGE", group = "launcher")]
+        uvx: Option<String>,
+        /// Run a container: `docker run -i --rm <IMAGE> [ARGS]`
+        #[arg(long = "docker", value_name = "IMAGE", group = "launcher")]
+        docker: Option<String>,
+        /// Message framing used when writing to the server
+        #[arg(long = "framing", value_enum, default_value_t = crate::mcp::Framing::Lsp)]
+        framing: crate::mcp::Framing,
+        /// Times to try the `initialize` handshake while the server starts (default 3)
+        #[arg(long = "init-attempts", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        init_attempts: Option<u32>,
+        /// Delay between handshake attempts in milliseconds (default 500)
+        #[arg(long = "init-retry-delay-ms", value_name = "MS")]
+        init_retry_delay_ms: Option<u64>,
+        /// Give up if the server isn't initialized after this many seconds (default 30)
+        #[arg(long = "spawn-timeout-secs", value_name = "SECS")]
+        spawn_timeout_secs: Option<u64>,
+        /// Fail if a message the server started isn't complete after this many seconds (default 30)
+        #[arg(long = "read-timeout-secs", value_name = "SECS")]
+        read_timeout_secs: Option<u64>,
+        /// Refuse to save the server if its command is not found on PATH
+        /// (otherwise only a warning)
+        #[arg(long = "strict")]
+        strict: bool,
     },
     /// List configured servers
     List,
     /// Remove a server by name
     Remove { name: String },
-    /// Enable a server
-    Enable { name: String },
-    /// Disable a server
-    Disable { name: String },
+    /// Enable a server, or every server with --all
+    Enable {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+        /// Every server in the file
+        #[arg(long = "all")]
+        all: bool,
+        /// With --all, leave this server as it is; repeatable
+        #[arg(long = "except", value_name = "NAME", requires = "all")]
+        except: Vec<String>,
+    },
+    /// Disable a server, or every server with --all
+    Disable {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+        /// Every server in the file
+        #[arg(long = "all")]
+        all: bool,
+        /// With --all, leave this server as it is; repeatable
+        #[arg(long = "except", value_name = "NAME", requires = "all")]
+        except: Vec<String>,
+    },
     /// Print discovered tools from enabled servers
-    Tools,
+    Tools {
+        /// Only this server (even if disabled)
+        #[arg(long = "server", value_name = "NAME")]
+        server: Option<String>,
+        /// Print the tools as Gemini function declarations (JSON)
+        #[arg(long = "declarations", conflicts_with = "json")]
+        declarations: bool,
+        /// Print the full tool list, including input schemas, as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
+    /// Call a tool on a configured server and print its result
+    Call {
+        /// Server name
+        server: String,
+        /// Tool name
+        tool: String,
+        /// Tool arguments as a JSON object (default: {})
+        #[arg(value_name = "JSON", conflicts_with = "stdin_json")]
+        args: Option<String>,
+        /// Read the tool arguments JSON object from stdin
+        #[arg(long = "stdin-json")]
+        stdin_json: bool,
+    },
+    /// Measure request round-trip latency to a server
+    Ping {
+        /// Server name
+        name: String,
+        /// Requests to time
+        #[arg(long = "count", short = 'n', default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// Spawn a server and relay raw JSON-RPC between the terminal and it (one message per line)
+    Run {
+        /// Server name
+        name: String,
+        /// Pretty-print messages received from the server
+        #[arg(long = "pretty")]
+        pretty: bool,
+    },
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))?;
+    Ok((key.to_string(), value.to_string()))
 }
@@ -1,6 +1,8 @@
+use crate::paths;
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -10,9 +12,96 @@ pub struct Config {
     /// Provider identifier (e.g., "google").
     pub provider: Option<String>,
 
+    /// Models to try in order when the primary model is unavailable.
+    #[serde(default)]
+    pub model_fallbacks: Vec<String>,
+
+    /// Text placed before every prompt (overridden by --prompt-prefix).
+    pub prompt_prefix: Option<String>,
+
+    /// Text placed after every prompt (overridden by --prompt-suffix).
+    pub prompt_suffix: Option<String>,
+
+    /// Base system instruction (persona) for every request. A project's
+    /// `.gemini.toml` and --system add to it rather than replace it.
+    pub system_instruction: Option<String>,
+
     /// Google provider settings.
     #[serde(default)]
     pub google: GoogleConfig,
+
+    /// Settings for any provider, keyed by provider name (`[providers.google]`).
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+
+    /// Strip terminal escapes and control characters from included context
+    /// (default true; see --no-strip-ansi).
+    pub strip_ansi: Option<bool>,
+
+    /// Frame included context as untrusted data (overridden by --untrusted-context).
+    pub untrusted_context: Option<crate::context::UntrustedContext>,
+
+    /// Layout of included files and pages (overridden by --context-format).
+    pub context_format: Option<crate::context::ContextFormat>,
+
+    /// Estimated request size (tokens) above which a size warning is printed.
+    pub request_token_warning: Option<usize>,
+
+    /// Honor proxy environment variables (default true; see --no-trust-env).
+    pub trust_env: Option<bool>,
+
+    /// Reject unknown keys in this file (same as --strict-config).
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// Per-project settings from the nearest `.gemini.toml` at or above the
+/// working directory.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProjectConfig {
+    /// System instruction for requests made in this project, placed after
+    /// the config's `system_instruction`.
+    pub system_instruction: Option<String>,
+}
+
+impl ProjectConfig {
+    pub const FILE_NAME: &'static str = ".gemini.toml";
+
+    /// Find and load the nearest project file, searching `start` and then its
+    /// ancestors. Returns its path alongside the settings.
+    pub fn find(start: &Path) -> anyhow::Result<Option<(PathBuf, Self)>> {
+        for dir in start.ancestors() {
+            let path = dir.join(Self::FILE_NAME);
+            let text = match std::fs::read_to_string(&path) {
+                Ok(t) => t,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(anyhow::Error::new(e))
+                        .with_context(|| format!("failed to read project config: {}", path.display()))
+                }
+            };
+            let cfg = toml::from_str(&text).with_context(|| format!("failed to parse TOML: {}", path.display()))?;
+            return Ok(Some((path, cfg)));
+        }
+        Ok(None)
+    }
+}
+
+/// `[providers.<name>]`: settings every provider understands.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProviderConfig {
+    /// API base URL, e.g. a gateway or proxy in front of the API.
+    pub base_url: Option<String>,
+
+    /// API key. For `google`, takes precedence over `google.api_key`.
+    pub api_key: Option<String>,
+
+    /// Model to use with this provider when neither -m nor GEMINI_MODEL is set.
+    pub default_model: Option<String>,
+
+    /// Extra HTTP headers sent with every request.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -20,9 +109,51 @@ pub struct GoogleConfig {
     /// API key (recommended for quick start). Can also be provided via GEMINI_API_KEY.
     pub api_key: Option<String>,
 
+    /// API version for generation and model requests: v1, v1beta (default) or v1alpha.
+    pub api_version: Option<String>,
+
     /// OAuth device-flow settings (for `gemini login`).
     #[serde(default)]
     pub oauth: GoogleOAuthConfig,
+
+    /// Service-account key file (JSON). `GOOGLE_APPLICATION_CREDENTIALS` takes
+    /// precedence when it names a service-account key.
+    pub service_account_file: Option<PathBuf>,
+
+    /// Credential sources to try, in order. If unset, [`CredentialSource::DEFAULT_ORDER`].
+    pub credential_sources: Option<Vec<CredentialSource>>,
+
+    /// Billing labels for Vertex AI (`--label` adds more). Requests to the
+    /// Gemini API fail while any are set.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Where Google credentials can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialSource {
+    /// `GEMINI_API_KEY`.
+    Env,
+    /// `google.api_key` in this file.
+    Config,
+    /// The token saved by `gemini login` / `gemini auth import`.
+    Oauth,
+    /// A service-account key file (`GOOGLE_APPLICATION_CREDENTIALS` or
+    /// `google.service_account_file`).
+    ServiceAccount,
+    /// gcloud application default credentials.
+    Adc,
+}
+
+impl CredentialSource {
+    pub const DEFAULT_ORDER: [CredentialSource; 5] = [
+        CredentialSource::Env,
+        CredentialSource::Config,
+        CredentialSource::Oauth,
+        CredentialSource::ServiceAccount,
+        CredentialSource::Adc,
+    ];
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -37,9 +168,167 @@ pub struct GoogleOAuthConfig {
     pub scopes: Option<Vec<String>>,
 }
 
+/// Type of a setting `config set` can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarKind {
+    String,
+    Bool,
+    Integer,
+}
+
+/// A setting `config set`/`config get` know about. A `*` segment matches any
+/// name (`providers.*.base_url`).
+#[derive(Debug, Clone, Copy)]
+pub struct ScalarKey {
+    pub key: &'static str,
+    pub kind: ScalarKind,
+    /// Credentials, which `config set` only writes when asked explicitly.
+    pub secret: bool,
+}
+
+const fn scalar(key: &'static str, kind: ScalarKind) -> ScalarKey {
+    ScalarKey { key, kind, secret: false }
+}
+
+const fn secret(key: &'static str) -> ScalarKey {
+    ScalarKey {
+        key,
+        kind: ScalarKind::String,
+        secret: true,
+    }
+}
+
+pub const SCALAR_KEYS: &[ScalarKey] = &[
+    scalar("model", ScalarKind::String),
+    scalar("provider", ScalarKind::String),
+    scalar("prompt_prefix", ScalarKind::String),
+    scalar("prompt_suffix", ScalarKind::String),
+    scalar("system_instruction", ScalarKind::String),
+    scalar("strip_ansi", ScalarKind::Bool),
+    scalar("untrusted_context", ScalarKind::String),
+    scalar("context_format", ScalarKind::String),
+    scalar("request_token_warning", ScalarKind::Integer),
+    scalar("trust_env", ScalarKind::Bool),
+    scalar("strict", ScalarKind::Bool),
+    secret("google.api_key"),
+    scalar("google.api_version", ScalarKind::String),
+    scalar("google.service_account_file", ScalarKind::String),
+    scalar("google.oauth.client_id", ScalarKind::String),
+    secret("google.oauth.client_secret"),
+    scalar("providers.*.base_url", ScalarKind::String),
+    secret("providers.*.api_key"),
+    scalar("providers.*.default_model", ScalarKind::String),
+];
+
+impl ScalarKey {
+    /// The known setting `key` names, if any.
+    pub fn find(key: &str) -> Option<&'static ScalarKey> {
+        SCALAR_KEYS.iter().find(|k| {
+            let pattern = k.key.split('.');
+            let parts = key.split('.');
+            pattern.clone().count() == parts.clone().count()
+                && pattern.zip(parts).all(|(p, part)| p == part || (p == "*" && !part.is_empty()))
+        })
+    }
+
+    /// Parse `raw` as a value of this setting's type.
+    pub fn parse(&self, raw: &str) -> anyhow::Result<toml_edit::Value> {
+        Ok(match self.kind {
+            ScalarKind::String => raw.into(),
+            ScalarKind::Bool => raw
+                .parse::<bool>()
+                .map_err(|_| anyhow::anyhow!("{} expects true or false, got {raw:?}", self.key))?
+                .into(),
+            ScalarKind::Integer => raw
+                .parse::<u32>()
+                .map(i64::from)
+                .map_err(|_| anyhow::anyhow!("{} expects a non-negative integer, got {raw:?}", self.key))?
+                .into(),
+        })
+    }
+}
+
+fn read_document(path: &Path) -> anyhow::Result<toml_edit::DocumentMut> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)).with_context(|| format!("failed to read config: {}", path.display()))
+        }
+    };
+    text.parse().with_context(|| format!("failed to parse TOML: {}", path.display()))
+}
+
+/// The value of `key` (dotted) in the TOML file at `path`, if set.
+pub fn get_value(path: impl AsRef<Path>, key: &str) -> anyhow::Result<Option<toml_edit::Value>> {
+    let doc = read_document(path.as_ref())?;
+    let mut item = doc.as_item();
+    for part in key.split('.') {
+        match item.get(part) {
+            Some(next) => item = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(item.as_value().cloned())
+}
+
+/// Set `key` (dotted, e.g. `google.api_key`) to `value` in the TOML file at
+/// `path`, creating the file and tables as needed. Comments and formatting of
+/// the rest of the file are preserved. The file is left alone if the result
+/// would not load as a [`Config`].
+pub fn set_value(path: impl AsRef<Path>, key: &str, value: toml_edit::Value) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut doc = read_document(path)?;
+
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let leaf = parts.pop().filter(|l| !l.is_empty()).context("empty config key")?;
+    let mut table = doc.as_table_mut();
+    for part in parts {
+        let entry = table
+            .entry(part)
+            .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+        table = entry
+            .as_table_mut()
+            .with_context(|| format!("config key {part} is not a table"))?;
+    }
+    table[leaf] = toml_edit::value(value);
+
+    let text = doc.to_string();
+    toml::from_str::<Config>(&text).with_context(|| format!("invalid value for {key}"))?;
+    // The config may now hold a secret.
+    paths::write_private_atomic(path, text.as_bytes())
+}
+
 impl Config {
+    /// `[providers.<name>]`, if configured.
+    pub fn provider_settings(&self, name: &str) -> Option<&ProviderConfig> {
+        self.providers.get(name)
+    }
+
     /// Load config if the file exists, otherwise return Ok(None).
     pub fn load_optional(path: impl AsRef<Path>) -> anyhow::Result<Option<Self>> {
+        Self::load_optional_with(path, false)
+    }
+
+    /// Like [`Config::load_optional`], but with `strict` (or `strict = true`
+    /// in the file) unknown keys are an error instead of being ignored.
+    pub fn load_optional_with(path: impl AsRef<Path>, strict: bool) -> anyhow::Result<Option<Self>> {
+        let path = path.as_ref();
+        let Some((cfg, unknown)) = Self::load_with_unknown(path)? else {
+            return Ok(None);
+        };
+        if !unknown.is_empty() {
+            if strict || cfg.strict {
+                anyhow::bail!("unknown config keys in {}: {}", path.display(), unknown.join(", "));
+            }
+            tracing::debug!(?unknown, "ignoring unknown config keys");
+        }
+        Ok(Some(cfg))
+    }
+
+    /// Load config if the file exists, along with the (dotted) keys it has
+    /// that no setting uses.
+    pub fn load_with_unknown(path: impl AsRef<Path>) -> anyhow::Result<Option<(Self, Vec<String>)>> {
         let path = path.as_ref();
         let bytes = match std::fs::read(path) {
             Ok(b) => b,
@@ -51,8 +340,94 @@ impl Config {
         };
 
         let s = String::from_utf8(bytes).context("config is not valid UTF-8")?;
-        let cfg: Config = toml::from_str(&s)
-            .with_context(|| format!("failed to parse TOML: {}", path.display()))?;
-        Ok(Some(cfg))
+        let mut unknown = Vec::new();
+        let cfg: Config = serde_ignored::deserialize(toml::Deserializer::new(&s), |key| {
+            unknown.push(key.to_string())
+        })
+        .with_context(|| format!("failed to parse TOML: {}", path.display()))?;
+        Ok(Some((cfg, unknown)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory under the system temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gemini-config-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn find_matches_provider_wildcard() {
+        assert_eq!(ScalarKey::find("providers.gateway.base_url").unwrap().key, "providers.*.base_url");
+        assert!(ScalarKey::find("providers.gateway.api_key").unwrap().secret);
+        assert!(ScalarKey::find("providers..base_url").is_none());
+        assert!(ScalarKey::find("providers.a.b.base_url").is_none());
+        assert!(ScalarKey::find("providers.gateway").is_none());
+        assert!(ScalarKey::find("model.name").is_none());
+        assert_eq!(ScalarKey::find("google.api_version").unwrap().kind, ScalarKind::String);
+    }
+
+    #[test]
+    fn parse_checks_value_type() {
+        let strict = ScalarKey::find("strict").unwrap();
+        assert_eq!(strict.parse("true").unwrap().as_bool(), Some(true));
+        let err = strict.parse("yes").unwrap_err();
+        assert!(err.to_string().contains("expects true or false"), "{err}");
+
+        let warning = ScalarKey::find("request_token_warning").unwrap();
+        assert_eq!(warning.parse("5000").unwrap().as_integer(), Some(5000));
+        for raw in ["-1", "1.5", "many", ""] {
+            let err = warning.parse(raw).unwrap_err();
+            assert!(err.to_string().contains("non-negative integer"), "{raw}: {err}");
+        }
+    }
+
+    #[test]
+    fn set_value_keeps_comments_and_creates_tables() {
+        let dir = scratch("set");
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "# my settings\nmodel = \"m\" # pinned\n\n[google]\n# version\napi_version = \"v1\"\n").unwrap();
+
+        set_value(&path, "providers.gateway.base_url", "https://gw.example/".into()).unwrap();
+        set_value(&path, "google.api_version", "v1beta".into()).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# my settings\nmodel = \"m\" # pinned\n"), "{text}");
+        assert!(text.contains("# version\napi_version = \"v1beta\"\n"), "{text}");
+        let cfg: Config = toml::from_str(&text).unwrap();
+        assert_eq!(
+            cfg.provider_settings("gateway").and_then(|p| p.base_url.as_deref()),
+            Some("https://gw.example/")
+        );
+        assert_eq!(
+            get_value(&path, "providers.gateway.base_url").unwrap().and_then(|v| v.as_str().map(String::from)),
+            Some("https://gw.example/".to_string())
+        );
+        assert!(get_value(&path, "providers.other.base_url").unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_value_leaves_file_alone_when_result_is_invalid() {
+        let dir = scratch("invalid");
+        let path = dir.join("config.toml");
+        let original = "# keep me\nmodel = \"m\"\n";
+        std::fs::write(&path, original).unwrap();
+
+        assert!(set_value(&path, "request_token_warning", "lots".into()).is_err());
+        // `model` is a string, so it can't become a table.
+        assert!(set_value(&path, "model.name", "x".into()).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+
+        // Nothing is created when the first write would be invalid.
+        let missing = dir.join("new.toml");
+        assert!(set_value(&missing, "strict", "yes".into()).is_err());
+        assert!(!missing.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,229 @@
+//! Google credential sources, tried in order until one yields a [`GoogleAuth`].
+//!
+//! Each source answers `Ok(None)` when it has nothing to offer (no key set,
+//! no file on disk) so the next one is tried; an `Err` means the source was
+//! present but unusable (e.g. the refresh failed) and stops the chain.
+
+use crate::config::{self, CredentialSource};
+use crate::provider::google::GoogleAuth;
+use crate::{app, auth, env, paths};
+use anyhow::Context;
+use futures_core::future::BoxFuture;
+use std::path::PathBuf;
+
+/// What a credential source may consult.
+pub struct CredentialContext<'a> {
+    pub http: &'a reqwest::Client,
+    pub cfg: Option<&'a config::Config>,
+    /// Use stored access tokens as-is, without refreshing them.
+    pub no_refresh: bool,
+}
+
+pub trait CredentialProvider: Send + Sync {
+    /// Short name used in logs and error messages.
+    fn name(&self) -> &'static str;
+
+    fn resolve<'a>(&'a self, cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>>;
+}
+
+/// `GEMINI_API_KEY`.
+pub struct EnvApiKey;
+
+impl CredentialProvider for EnvApiKey {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn resolve<'a>(&'a self, _cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>> {
+        Box::pin(async { Ok(env::api_key().map(GoogleAuth::ApiKey)) })
+    }
+}
+
+/// `providers.google.api_key`, else `google.api_key`, in config.toml.
+pub struct FileApiKey;
+
+impl CredentialProvider for FileApiKey {
+    fn name(&self) -> &'static str {
+        "config"
+    }
+
+    fn resolve<'a>(&'a self, cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>> {
+        Box::pin(async move {
+            let key = cx
+                .cfg
+                .and_then(|c| c.provider_settings("google").and_then(|p| p.api_key.clone()).or_else(|| c.google.api_key.clone()))
+                .filter(|k| !k.trim().is_empty());
+            Ok(key.map(GoogleAuth::ApiKey))
+        })
+    }
+}
+
+/// The OAuth token saved by `gemini login` or `gemini auth import`,
+/// refreshed (and re-saved) when it is about to expire.
+pub struct StoredOAuth;
+
+impl CredentialProvider for StoredOAuth {
+    fn name(&self) -> &'static str {
+        "oauth"
+    }
+
+    fn resolve<'a>(&'a self, cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>> {
+        Box::pin(async move {
+            let tok_path = paths::google_token_path()?;
+            let Some(tok) = auth::load_token(&tok_path)? else {
+                return Ok(None);
+            };
+
+            if let Some(warning) = tok.missing_refresh_token_warning() {
+                tracing::warn!("{warning}");
+            }
+
+            let tok = if cx.no_refresh {
+                if !tok.is_valid_for(std::time::Duration::ZERO) {
+                    tracing::warn!("stored access token appears to be expired; using it anyway (--no-refresh)");
+                }
+                tok
+            } else {
+                let oauth = app::token_oauth_client(cx.cfg, &tok)?;
+                let tok = auth::refresh_if_needed(cx.http, &oauth, tok).await?;
+                auth::save_token_atomic(&tok_path, &tok)?;
+                tok
+            };
+            Ok(Some(GoogleAuth::BearerToken(tok.access_token)))
+        })
+    }
+}
+
+/// A service-account key: `GOOGLE_APPLICATION_CREDENTIALS` when it names
+/// one, else `google.service_account_file`. The minted access token is
+/// cached in the state directory and re-minted shortly before it expires.
+pub struct ServiceAccount;
+
+/// Scopes requested for service-account tokens unless `google.oauth.scopes` is set.
+const SERVICE_ACCOUNT_SCOPES: [&str; 2] = [
+    "https://www.googleapis.com/auth/cloud-platform",
+    "https://www.googleapis.com/auth/generative-language",
+];
+
+impl ServiceAccount {
+    fn key_path(cfg: Option<&config::Config>) -> anyhow::Result<Option<PathBuf>> {
+        if let Some(path) = env::google_application_credentials().map(PathBuf::from) {
+            if credentials_type(&path)?.as_deref() == Some("service_account") {
+                return Ok(Some(path));
+            }
+        }
+        Ok(cfg.and_then(|c| c.google.service_account_file.clone()))
+    }
+}
+
+impl CredentialProvider for ServiceAccount {
+    fn name(&self) -> &'static str {
+        "service-account"
+    }
+
+    fn resolve<'a>(&'a self, cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>> {
+        Box::pin(async move {
+            let Some(path) = Self::key_path(cx.cfg)? else {
+                return Ok(None);
+            };
+            let key = auth::ServiceAccountKey::load(&path)?;
+
+            let cache_path = paths::service_account_token_path()?;
+            let cached = auth::load_token(&cache_path)?
+                .filter(|t| t.client_id.as_deref() == Some(key.client_email.as_str()));
+            if let Some(tok) = cached {
+                if cx.no_refresh || tok.is_valid_for(std::time::Duration::from_secs(60)) {
+                    return Ok(Some(GoogleAuth::BearerToken(tok.access_token)));
+                }
+            }
+
+            let scopes = cx
+                .cfg
+                .and_then(|c| c.google.oauth.scopes.clone())
+                .unwrap_or_else(|| SERVICE_ACCOUNT_SCOPES.iter().map(|s| s.to_string()).collect());
+            let tok = auth::service_account_token(cx.http, &key, &scopes).await?;
+            auth::save_token_atomic(&cache_path, &tok)?;
+            Ok(Some(GoogleAuth::BearerToken(tok.access_token)))
+        })
+    }
+}
+
+/// Application default credentials of the `authorized_user` kind:
+/// `GOOGLE_APPLICATION_CREDENTIALS`, else gcloud's well-known file. A fresh
+/// access token is minted from the refresh token on every run.
+pub struct Adc;
+
+impl CredentialProvider for Adc {
+    fn name(&self) -> &'static str {
+        "adc"
+    }
+
+    fn resolve<'a>(&'a self, cx: &'a CredentialContext<'a>) -> BoxFuture<'a, anyhow::Result<Option<GoogleAuth>>> {
+        Box::pin(async move {
+            let path = match env::google_application_credentials() {
+                Some(p) => PathBuf::from(p),
+                None => paths::gcloud_adc_path()?,
+            };
+            if credentials_type(&path)?.as_deref() != Some("authorized_user") {
+                return Ok(None);
+            }
+
+            let bytes = std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            let (tok, _) = auth::import_token(&bytes).with_context(|| format!("invalid credentials: {}", path.display()))?;
+            let oauth = app::token_oauth_client(cx.cfg, &tok)?;
+            let tok = auth::refresh(cx.http, &oauth, tok)
+                .await
+                .with_context(|| format!("failed to refresh application default credentials from {}", path.display()))?;
+            Ok(Some(GoogleAuth::BearerToken(tok.access_token)))
+        })
+    }
+}
+
+/// The `type` field of a Google credentials JSON file; `None` if the file
+/// does not exist.
+fn credentials_type(path: &std::path::Path) -> anyhow::Result<Option<String>> {
+    let bytes = match std::fs::read(path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow::Error::new(e)).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let v: serde_json::Value =
+        serde_json::from_slice(&bytes).with_context(|| format!("{} is not valid JSON", path.display()))?;
+    Ok(v.get("type").and_then(|t| t.as_str()).map(str::to_string))
+}
+
+pub fn provider_for(source: CredentialSource) -> Box<dyn CredentialProvider> {
+    match source {
+        CredentialSource::Env => Box::new(EnvApiKey),
+        CredentialSource::Config => Box::new(FileApiKey),
+        CredentialSource::Oauth => Box::new(StoredOAuth),
+        CredentialSource::ServiceAccount => Box::new(ServiceAccount),
+        CredentialSource::Adc => Box::new(Adc),
+    }
+}
+
+/// The configured chain (`google.credential_sources`), or the default order.
+pub fn chain(cfg: Option<&config::Config>) -> Vec<Box<dyn CredentialProvider>> {
+    let order = cfg.and_then(|c| c.google.credential_sources.clone());
+    let order = order.as_deref().unwrap_or(&CredentialSource::DEFAULT_ORDER);
+    order.iter().map(|s| provider_for(*s)).collect()
+}
+
+/// Try each provider in turn. Returns the first credentials found and the
+/// name of the source that supplied them.
+pub async fn resolve(
+    chain: &[Box<dyn CredentialProvider>],
+    cx: &CredentialContext<'_>,
+) -> anyhow::Result<Option<(GoogleAuth, &'static str)>> {
+    for provider in chain {
+        let auth = provider
+            .resolve(cx)
+            .await
+            .with_context(|| format!("credential source `{}` failed", provider.name()))?;
+        if let Some(auth) = auth {
+            return Ok(Some((auth, provider.name())));
+        }
+        tracing::debug!(source = provider.name(), "no credentials");
+    }
+    Ok(None)
+}
@@ -0,0 +1,262 @@
+//! `gemini config doctor`: checks config.toml for settings that parse but
+//! will not do what was meant.
+
+use crate::config::{Config, CredentialSource};
+use crate::{app, net};
+use std::path::Path;
+
+struct Finding {
+    error: bool,
+    problem: String,
+    fix: String,
+}
+
+#[derive(Default)]
+struct Findings(Vec<Finding>);
+
+impl Findings {
+    fn error(&mut self, problem: impl Into<String>, fix: impl Into<String>) {
+        self.0.push(Finding {
+            error: true,
+            problem: problem.into(),
+            fix: fix.into(),
+        });
+    }
+
+    fn warn(&mut self, problem: impl Into<String>, fix: impl Into<String>) {
+        self.0.push(Finding {
+            error: false,
+            problem: problem.into(),
+            fix: fix.into(),
+        });
+    }
+}
+
+/// Providers `provider = "..."` may name.
+const PROVIDERS: &[&str] = &["google", "stub"];
+
+pub async fn cmd_config_doctor(path: &Path, check_network: bool, no_trust_env: bool) -> anyhow::Result<()> {
+    println!("config: {}", path.display());
+    let Some((cfg, unknown)) = Config::load_with_unknown(path)? else {
+        println!("no config file; built-in defaults are used");
+        return Ok(());
+    };
+
+    let mut findings = Findings::default();
+    for key in &unknown {
+        findings.warn(
+            format!("unknown key `{key}` is ignored"),
+            "check the spelling against the README, or remove it",
+        );
+    }
+    check_provider(&cfg, &mut findings);
+    check_credentials(&cfg, &mut findings);
+    check_models(&cfg, &mut findings);
+    check_settings(&cfg, &mut findings);
+    if check_network {
+        let http = app::http_client(!no_trust_env && cfg.trust_env.unwrap_or(true))?;
+        check_reachable(&http, &cfg, &mut findings).await;
+    }
+
+    for f in &findings.0 {
+        println!("{}: {}", if f.error { "error" } else { "warning" }, f.problem);
+        println!("  fix: {}", f.fix);
+    }
+    let errors = findings.0.iter().filter(|f| f.error).count();
+    let warnings = findings.0.len() - errors;
+    if findings.0.is_empty() {
+        println!("no problems found");
+    } else {
+        println!("{errors} error(s), {warnings} warning(s)");
+    }
+    if errors > 0 {
+        anyhow::bail!("config has {errors} error(s)");
+    }
+    Ok(())
+}
+
+fn check_provider(cfg: &Config, findings: &mut Findings) {
+    if let Some(provider) = cfg.provider.as_deref().filter(|p| !PROVIDERS.contains(p)) {
+        findings.error(
+            format!("unknown provider `{provider}`"),
+            format!("set provider to one of: {}", PROVIDERS.join(", ")),
+        );
+    }
+    for (name, settings) in &cfg.providers {
+        if !PROVIDERS.contains(&name.as_str()) {
+            findings.warn(
+                format!("[providers.{name}] is for an unknown provider and is ignored"),
+                format!("rename it to one of: {}", PROVIDERS.join(", ")),
+            );
+        }
+        if let Some(base) = &settings.base_url {
+            match reqwest::Url::parse(base) {
+                Err(e) => findings.error(
+                    format!("providers.{name}.base_url {base:?} is not a valid URL ({e})"),
+                    "use a full URL such as https://gateway.example/",
+                ),
+                Ok(url) if url.scheme() == "http" => findings.warn(
+                    format!("providers.{name}.base_url uses plain http; credentials are sent unencrypted"),
+                    "use https unless the endpoint is on localhost",
+                ),
+                Ok(_) => {}
+            }
+        }
+        for header in settings.headers.keys() {
+            if reqwest::header::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                findings.error(
+                    format!("providers.{name}.headers has an invalid header name {header:?}"),
+                    "use letters, digits and '-' only",
+                );
+            }
+        }
+    }
+}
+
+fn check_credentials(cfg: &Config, findings: &mut Findings) {
+    let google = &cfg.google;
+    let table_key = cfg.provider_settings("google").and_then(|p| p.api_key.as_deref());
+    let legacy_key = google.api_key.as_deref();
+    if table_key.is_some() && legacy_key.is_some() {
+        findings.warn(
+            "both providers.google.api_key and google.api_key are set; google.api_key is never used",
+            "remove google.api_key",
+        );
+    }
+    if table_key.or(legacy_key).is_some_and(|k| k.trim().is_empty()) {
+        findings.warn("the configured API key is empty and is skipped", "set the key or remove the setting");
+    }
+
+    let order = google.credential_sources.as_deref().unwrap_or(&CredentialSource::DEFAULT_ORDER);
+    if order.is_empty() {
+        findings.error(
+            "google.credential_sources is empty, so no credentials can be found",
+            "list at least one source, or remove the setting for the default order",
+        );
+    }
+    for (i, source) in order.iter().enumerate() {
+        if order[..i].contains(source) {
+            findings.warn(
+                format!("google.credential_sources lists {source:?} more than once"),
+                "remove the duplicate",
+            );
+        }
+    }
+
+    let has_key = table_key.or(legacy_key).is_some_and(|k| !k.trim().is_empty());
+    if let Some(sa) = &google.service_account_file {
+        if !sa.exists() {
+            findings.error(
+                format!("google.service_account_file {} does not exist", sa.display()),
+                "fix the path or remove the setting",
+            );
+        }
+        let position = |s: CredentialSource| order.iter().position(|o| *o == s);
+        if has_key {
+            let winner = match (position(CredentialSource::Config), position(CredentialSource::ServiceAccount)) {
+                (Some(k), Some(s)) if s < k => "service account",
+                (Some(_), _) => "API key",
+                (None, _) => "service account",
+            };
+            findings.warn(
+                format!("both an API key and a service account are configured; the {winner} is used"),
+                "remove the one you don't use, or order them with google.credential_sources",
+            );
+        }
+    }
+
+    let oauth = &google.oauth;
+    if oauth.client_secret.is_some() && oauth.client_id.is_none() {
+        findings.warn(
+            "google.oauth.client_secret is set without google.oauth.client_id",
+            "set client_id as well (or GEMINI_OAUTH_CLIENT_ID)",
+        );
+    }
+    if oauth.scopes.as_ref().is_some_and(|s| s.is_empty()) {
+        findings.error(
+            "google.oauth.scopes is empty, so tokens grant no access",
+            "remove the setting to use the default scopes",
+        );
+    }
+
+    #[cfg(feature = "google")]
+    if let Err(e) = crate::provider::google::validate_labels(&google.labels) {
+        findings.error(format!("google.labels: {e}"), "fix or remove the label");
+    } else if !google.labels.is_empty() {
+        findings.error(
+            "google.labels is set, but only Vertex AI accepts labels and requests to the Gemini API fail",
+            "remove google.labels",
+        );
+    }
+}
+
+fn check_models(cfg: &Config, findings: &mut Findings) {
+    let model = cfg.model.as_deref();
+    for (i, fallback) in cfg.model_fallbacks.iter().enumerate() {
+        if Some(fallback.as_str()) == model {
+            findings.warn(
+                format!("model_fallbacks includes the default model {fallback}; it is skipped"),
+                "remove it from model_fallbacks",
+            );
+        } else if cfg.model_fallbacks[..i].contains(fallback) {
+            findings.warn(format!("model_fallbacks lists {fallback} more than once"), "remove the duplicate");
+        }
+    }
+
+    #[cfg(feature = "google")]
+    if let Some(version) = cfg.google.api_version.as_deref() {
+        let versions = crate::provider::google::API_VERSIONS;
+        if !versions.contains(&version) {
+            findings.error(
+                format!("unknown google.api_version {version:?}"),
+                format!("use one of: {}", versions.join(", ")),
+            );
+        }
+    }
+}
+
+fn check_settings(cfg: &Config, findings: &mut Findings) {
+    if cfg.request_token_warning == Some(0) {
+        findings.warn(
+            "request_token_warning = 0 prints a size warning for every request",
+            "raise it, or remove it for the default",
+        );
+    }
+    for (key, value) in [
+        ("prompt_prefix", &cfg.prompt_prefix),
+        ("prompt_suffix", &cfg.prompt_suffix),
+        ("system_instruction", &cfg.system_instruction),
+    ] {
+        if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+            findings.warn(format!("{key} is empty"), format!("remove {key}"));
+        }
+    }
+}
+
+/// One round trip to each configured API base. Any HTTP response counts as
+/// reachable; only connection-level failures are reported.
+async fn check_reachable(http: &reqwest::Client, cfg: &Config, findings: &mut Findings) {
+    #[cfg(feature = "google")]
+    let defaults = [("google", crate::provider::google::DEFAULT_API_BASE)];
+    #[cfg(not(feature = "google"))]
+    let defaults: [(&str, &str); 0] = [];
+
+    let configured = cfg
+        .providers
+        .iter()
+        .filter_map(|(name, p)| Some((name.clone(), p.base_url.clone()?)));
+    let unconfigured = defaults
+        .into_iter()
+        .filter(|(name, _)| cfg.provider_settings(name).and_then(|p| p.base_url.as_ref()).is_none())
+        .map(|(name, base)| (name.to_string(), base.to_string()));
+    for (name, base) in configured.chain(unconfigured) {
+        let request = http.head(&base).timeout(std::time::Duration::from_secs(10));
+        match request.send().await {
+            Ok(resp) => println!("reachable: {name} at {base} (HTTP {})", resp.status().as_u16()),
+            Err(e) => findings.error(
+                format!("{name} API base {base} is unreachable: {:#}", net::hinted(e)),
+                "check the URL, network and proxy settings (HTTPS_PROXY)",
+            ),
+        }
+    }
+}
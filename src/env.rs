@@ -0,0 +1,73 @@
+//! Environment variables read by the CLI.
+//!
+//! All env reads go through here so precedence is applied the same way
+//! everywhere: CLI flag > environment > config file > built-in default.
+
+use std::ffi::OsString;
+
+pub const GEMINI_API_KEY: &str = "GEMINI_API_KEY";
+pub const GEMINI_MODEL: &str = "GEMINI_MODEL";
+pub const GEMINI_OAUTH_CLIENT_ID: &str = "GEMINI_OAUTH_CLIENT_ID";
+pub const GEMINI_OAUTH_CLIENT_SECRET: &str = "GEMINI_OAUTH_CLIENT_SECRET";
+pub const GEMINI_HOME: &str = "GEMINI_HOME";
+pub const GOOGLE_APPLICATION_CREDENTIALS: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+pub const CLOUDSDK_CONFIG: &str = "CLOUDSDK_CONFIG";
+pub const XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
+pub const XDG_STATE_HOME: &str = "XDG_STATE_HOME";
+pub const HOME: &str = "HOME";
+pub const GEMINI_PAGER: &str = "GEMINI_PAGER";
+pub const NO_COLOR: &str = "NO_COLOR";
+pub const PAGER: &str = "PAGER";
+pub const LESS: &str = "LESS";
+pub const PATH: &str = "PATH";
+
+/// Read a UTF-8 variable. Unset and empty values are both treated as absent.
+pub fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.trim().is_empty())
+}
+
+/// Read a path-like variable. Unset and empty values are both treated as absent.
+pub fn var_os(name: &str) -> Option<OsString> {
+    std::env::var_os(name).filter(|v| !v.is_empty())
+}
+
+pub fn api_key() -> Option<String> {
+    var(GEMINI_API_KEY)
+}
+
+pub fn model() -> Option<String> {
+    var(GEMINI_MODEL)
+}
+
+pub fn oauth_client_id() -> Option<String> {
+    var(GEMINI_OAUTH_CLIENT_ID)
+}
+
+pub fn oauth_client_secret() -> Option<String> {
+    var(GEMINI_OAUTH_CLIENT_SECRET)
+}
+
+/// Pager command for `--pager`: `GEMINI_PAGER`, else `PAGER`.
+pub fn pager() -> Option<String> {
+    var(GEMINI_PAGER).or_else(|| var(PAGER))
+}
+
+/// Options for `less`. Unlike the other variables, an empty value counts as
+/// set: it asks for no options.
+pub fn less() -> Option<OsString> {
+    std::env::var_os(LESS)
+}
+
+/// <https://no-color.org>: any non-empty value turns styling off.
+pub fn no_color() -> bool {
+    var_os(NO_COLOR).is_some()
+}
+
+pub fn google_application_credentials() -> Option<OsString> {
+    var_os(GOOGLE_APPLICATION_CREDENTIALS)
+}
+
+/// Directories searched for commands, e.g. MCP server launchers.
+pub fn path() -> Option<OsString> {
+    var_os(PATH)
+}
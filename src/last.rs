@@ -0,0 +1,58 @@
+use crate::{auth, paths, state};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The most recent one-shot response, kept for `gemini last`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastResponse {
+    pub model: String,
+    pub prompt: String,
+    pub response: String,
+    /// Seconds since UNIX epoch.
+    pub saved_at: u64,
+}
+
+impl LastResponse {
+    pub fn new(model: String, prompt: String, response: String) -> Self {
+        Self {
+            model,
+            prompt,
+            response,
+            saved_at: auth::now_secs(),
+        }
+    }
+}
+
+pub fn cmd_last(json: bool) -> anyhow::Result<()> {
+    let Some(last) = load()? else {
+        anyhow::bail!("no saved response yet");
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&last)?);
+    } else {
+        println!("{}", last.response);
+    }
+    Ok(())
+}
+
+/// Format history of the last-response file (see [`state`]).
+const MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
+pub fn load() -> anyhow::Result<Option<LastResponse>> {
+    let path = paths::last_response_path()?;
+    let bytes = match std::fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)).with_context(|| format!("failed to read: {}", path.display()))
+        }
+    };
+    let last = state::from_json(&bytes, MIGRATIONS).with_context(|| format!("failed to parse JSON: {}", path.display()))?;
+    Ok(Some(last))
+}
+
+pub fn save(last: &LastResponse) -> anyhow::Result<()> {
+    let path = paths::last_response_path()?;
+    let bytes = state::to_json(last, MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(&path, &bytes)
+}
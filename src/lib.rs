@@ -0,0 +1,50 @@
+//! Gemini client library behind the `gemini` CLI.
+//!
+//! Providers stream chat responses behind the [`Provider`] trait; [`auth`]
+//! implements the Google OAuth device-code flow and token storage; [`config`]
+//! and [`paths`] resolve the CLI's config and state locations.
+//!
+//! Minimal programmatic streaming call:
+//!
+//! ```no_run
+//! # #[cfg(feature = "google")]
+//! # mod example {
+//! use gemini::{ChatRequest, GoogleAuth, GoogleProvider, Provider};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let provider = GoogleProvider::new(reqwest::Client::new(), GoogleAuth::ApiKey("...".into()))?;
+//! let req = ChatRequest {
+//!     model: "gemini-1.5-flash".into(),
+//!     prompt: "Write a haiku about Rust".into(),
+//!     ..Default::default()
+//! };
+//!
+//! let mut stream = provider.stream_chat(req).await?;
+//! while let Some(event) = stream.next().await {
+//!     if let Some(text) = event?.answer() {
+//!         print!("{text}");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! # }
+//! ```
+
+pub mod auth;
+pub mod config;
+pub mod context;
+pub mod env;
+pub mod net;
+pub mod output;
+pub mod paths;
+pub mod provider;
+pub mod retry;
+pub mod state;
+
+pub use auth::{device_login, load_token, refresh_if_needed, save_token_atomic, OAuthClient, OAuthToken};
+pub use config::Config;
+pub use provider::{ApiError, ChatEvent, ChatMessage, ChatRequest, GenerationConfig, ModelInfo, Provider, Role, TokenLogprob, TokenUsage};
+
+#[cfg(feature = "google")]
+pub use provider::google::{GoogleAuth, GoogleProvider};
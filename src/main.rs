@@ -1,9 +1,18 @@
 mod app;
-mod auth;
+mod bench;
+#[cfg(feature = "google")]
+mod attach;
+#[cfg(feature = "google")]
+mod cache_content;
 mod cli;
-mod config;
-mod paths;
-mod provider;
+#[cfg(feature = "google")]
+mod credentials;
+mod doctor;
+mod last;
+#[cfg(feature = "google")]
+mod models;
+mod session;
+mod summary;
 
 #[cfg(feature = "mcp")]
 mod mcp;
@@ -12,7 +21,9 @@ mod tui;
 
 use anyhow::Context;
 use clap::Parser;
+use gemini::{auth, config, context, env, net, output, paths, provider, retry, state};
 use provider::ChatRequest;
+use tracing::Instrument;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -25,29 +36,117 @@ async fn main() -> anyhow::Result<()> {
 
     let args = cli::Args::parse();
 
+    // Needs no config or state, so works even when those are broken.
+    if let Some(cli::Command::Completions { shell }) = args.cmd {
+        use clap::CommandFactory;
+        clap_complete::generate(shell, &mut cli::Args::command(), "gemini", &mut std::io::stdout());
+        return Ok(());
+    }
+    // Reports config problems itself, or edits the file, so runs before
+    // loading can fail on them.
+    if let Some(cli::Command::Config { cmd }) = args.cmd {
+        let path = paths::config_dir()?.join("config.toml");
+        return match cmd {
+            cli::ConfigCommand::Doctor { check_network } => {
+                doctor::cmd_config_doctor(&path, check_network, args.no_trust_env).await
+            }
+            cmd => app::cmd_config(&path, cmd),
+        };
+    }
+
     // Resolve and create dirs early.
     let config_dir = paths::config_dir()?;
     let _state_dir = paths::state_dir()?;
 
-    let cfg = config::Config::load_optional(config_dir.join("config.toml"))?;
+    let cfg = config::Config::load_optional_with(config_dir.join("config.toml"), args.strict_config)?;
     tracing::debug!(?config_dir, ?cfg, "resolved config");
 
-    let http = reqwest::Client::builder()
-        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
-        .build()
-        .context("failed to build HTTP client")?;
+    let trust_env = if args.no_trust_env {
+        false
+    } else if args.trust_env {
+        true
+    } else {
+        cfg.as_ref().and_then(|c| c.trust_env).unwrap_or(true)
+    };
+    let http = app::http_client(trust_env)?;
+
+    let provider_opts = app::ProviderOptions {
+        #[cfg(feature = "google")]
+        no_refresh: args.no_refresh,
+        #[cfg(feature = "google")]
+        non_interactive: args.non_interactive,
+        #[cfg(feature = "google")]
+        api_version: args.api_version.clone(),
+        #[cfg(feature = "google")]
+        streaming_mode: args.streaming_mode,
+    };
 
     match args.cmd {
         Some(cli::Command::Login) => {
             return app::cmd_login(&http, cfg.as_ref()).await;
         }
+        Some(cli::Command::Auth { cmd }) => {
+            return app::cmd_auth(&http, cfg.as_ref(), cmd).await;
+        }
+        Some(cli::Command::Last { json }) => {
+            return last::cmd_last(json);
+        }
+        // Handled before the config is loaded.
+        Some(cli::Command::Completions { .. } | cli::Command::Config { .. }) => unreachable!(),
+        Some(cli::Command::Session { cmd }) => {
+            return session::cmd_session(cmd);
+        }
+        Some(cli::Command::Bench {
+            prompt,
+            models,
+            repeat,
+            concurrency,
+            json,
+        }) => {
+            let provider_name = app::resolve_provider_name(args.provider.clone(), cfg.as_ref());
+            let model = app::resolve_model(args.model.clone(), cfg.as_ref(), &provider_name);
+            let opts = bench::BenchOptions {
+                prompt: prompt.join(" "),
+                models,
+                repeat,
+                concurrency,
+                json,
+            };
+            return bench::cmd_bench(&http, cfg.as_ref(), &provider_opts, &provider_name, model, opts).await;
+        }
+        #[cfg(feature = "google")]
+        Some(cli::Command::Models { cmd }) => {
+            return models::cmd_models(&http, cfg.as_ref(), &provider_opts, cmd).await;
+        }
+        #[cfg(feature = "google")]
+        Some(cli::Command::CacheContent { cmd }) => {
+            let model = app::resolve_model(args.model.clone(), cfg.as_ref(), "google");
+            return cache_content::cmd_cache_content(&http, cfg.as_ref(), &provider_opts, model, cmd).await;
+        }
         #[cfg(feature = "mcp")]
         Some(cli::Command::Mcp { cmd }) => {
             return mcp::cmd_mcp(cmd).await;
         }
         #[cfg(feature = "tui")]
-        Some(cli::Command::Tui) => {
-            return tui::run_tui(cfg.as_ref(), args.model.clone()).await;
+        Some(cli::Command::Tui {
+            keep_warm,
+            message_style,
+            candidates,
+            resume,
+            read_only,
+            simple,
+        }) => {
+            let opts = tui::TuiOptions {
+                keep_warm,
+                message_style,
+                candidates,
+                resume,
+                read_only,
+                simple: simple || env::no_color(),
+                budget_tokens_total: args.budget_tokens_total,
+                system: args.system.clone(),
+            };
+            return tui::run_tui(&http, cfg.as_ref(), args.model.clone(), &provider_opts, opts).await;
         }
         None => {}
     }
@@ -57,39 +156,465 @@ async fn main() -> anyhow::Result<()> {
         anyhow::bail!("No prompt provided. Try: gemini \"Hello\" or `gemini tui` (feature flag)");
     }
 
-    let model = args
-        .model
+    let prefix = args
+        .prompt_prefix
         .clone()
-        .or_else(|| cfg.as_ref().and_then(|c| c.model.clone()))
-        .unwrap_or_else(|| "gemini-1.5-flash".to_string());
-
-    let provider_name = args
-        .provider
+        .or_else(|| cfg.as_ref().and_then(|c| c.prompt_prefix.clone()));
+    let suffix = args
+        .prompt_suffix
         .clone()
-        .or_else(|| cfg.as_ref().and_then(|c| c.provider.clone()))
-        .unwrap_or_else(|| "google".to_string());
+        .or_else(|| cfg.as_ref().and_then(|c| c.prompt_suffix.clone()));
+    let mut prompt = app::wrap_prompt(&prompt, prefix.as_deref(), suffix.as_deref());
+    let system_layers = app::system_layers(cfg.as_ref(), args.system.as_deref())?;
+    if args.echo_prompt {
+        for (source, text) in &system_layers {
+            eprintln!("--- system ({source}) ---\n{}", text.trim());
+        }
+        eprintln!("--- prompt ---\n{prompt}\n--------------");
+    }
+
+    let user_prompt = prompt.clone();
+
+    let context_format = args
+        .context_format
+        .or_else(|| cfg.as_ref().and_then(|c| c.context_format))
+        .unwrap_or_default();
+    let strip_ansi = if args.no_strip_ansi {
+        false
+    } else if args.strip_ansi {
+        true
+    } else {
+        cfg.as_ref().and_then(|c| c.strip_ansi).unwrap_or(true)
+    };
+    let untrusted = args.untrusted_context.or_else(|| cfg.as_ref().and_then(|c| c.untrusted_context));
+    // Included files, pages and tool results.
+    let mut builder = context::ContextBuilder::new(context_format)
+        .strip_control(strip_ansi)
+        .budget(
+            args.max_context_bytes.unwrap_or(usize::MAX),
+            args.context_budget_strategy,
+            args.context_manifest.clone(),
+        );
+
+    if !args.include_directories.is_empty() {
+        let filter = context::DirFilter {
+            include_ext: args.include_ext.clone(),
+            exclude_globs: args.exclude_globs.clone(),
+        };
+        let files = context::collect_dirs(&args.include_directories, &filter)?;
+        let (count, bytes) = builder.add_files(files)?;
+        for (path, size) in &builder.report().dropped {
+            eprintln!("dropped (over context budget): {path} ({size} bytes)");
+        }
+        eprintln!("Included {count} files ({bytes} bytes) from --include-directories");
+    }
+
+    if !args.context_from_url.is_empty() {
+        let timeout = std::time::Duration::from_secs(args.url_timeout);
+        let mut pages = Vec::new();
+        for url in &args.context_from_url {
+            pages.push(context::fetch_url(&http, url, timeout, builder.remaining()).await?);
+        }
+        let count = pages.len();
+        let bytes = builder.add_pages(pages);
+        for (url, size) in &builder.report().truncated {
+            eprintln!("note: {url} truncated to {size} bytes");
+        }
+        eprintln!("Included {count} pages ({bytes} bytes) from --context-from-url");
+    }
+
+    #[cfg(feature = "mcp")]
+    if let Some(spec) = &args.tool_result_from {
+        let block = mcp::tool_result_context(spec).await?;
+        builder.add_block(format!("tool result ({spec})"), block);
+    }
+
+    let (mut context_text, report) = builder.finish();
+    // (source, bytes) of everything in the prompt, for the request size check.
+    let mut sources: Vec<(String, usize)> = vec![("prompt".to_string(), prompt.len())];
+    sources.extend(report.included);
+
+    let provider_name = app::resolve_provider_name(args.provider.clone(), cfg.as_ref());
+    let model = app::resolve_model(args.model.clone(), cfg.as_ref(), &provider_name);
+    #[cfg(feature = "google")]
+    if args.streaming_mode.is_some() && provider_name != "google" {
+        anyhow::bail!("--endpoint-streaming-mode applies to the google provider (using {provider_name})");
+    }
+
+    let provider = app::build_provider(&http, cfg.as_ref(), &provider_name, &provider_opts).await?;
+
+    if let Some(summary_model) = args.context_summary.as_deref().filter(|_| !context_text.is_empty()) {
+        let before = context::estimate_tokens(context_text.len());
+        let (summary, cached) = summary::summarize(provider.as_ref(), summary_model, &context_text).await?;
+        let after = context::estimate_tokens(summary.len());
+        eprintln!(
+            "context summary ({summary_model}{}): ~{before} -> ~{after} tokens",
+            if cached { ", cached" } else { "" }
+        );
+        sources.retain(|(name, _)| name == "prompt");
+        sources.push((format!("context summary ({summary_model})"), summary.len()));
+        context_text = format!("Summary of the included context:\n\n{summary}\n\n");
+    }
+    if !context_text.is_empty() {
+        if let Some(mode) = untrusted {
+            context_text = context::frame_untrusted(&context_text, mode);
+        }
+        prompt = format!("{context_text}{prompt}");
+    }
+
+    let conversation = match &args.session {
+        Some(name) => Some(session::load(name)?.unwrap_or_default()),
+        None => None,
+    };
+    let mut history = conversation.as_ref().map(|c| c.messages.clone()).unwrap_or_default();
+
+    let input_limit =
+        app::input_token_limit(provider.as_ref(), &model, prompt.len() + app::history_bytes(&history)).await;
+    if let Some(limit) = input_limit {
+        let dropped = app::trim_history(&mut history, prompt.len(), limit);
+        if dropped > 0 {
+            eprintln!("note: left out the {dropped} oldest session messages to fit the model's input limit of {limit} tokens");
+        }
+    }
+    if !history.is_empty() {
+        sources.push((format!("session history ({} messages)", history.len()), app::history_bytes(&history)));
+    }
+    let warn_tokens = cfg
+        .as_ref()
+        .and_then(|c| c.request_token_warning)
+        .unwrap_or(app::DEFAULT_REQUEST_TOKEN_WARNING);
+    app::check_request_size(
+        prompt.len() + app::history_bytes(&history),
+        &mut sources,
+        warn_tokens,
+        args.max_request_tokens,
+        input_limit,
+    )?;
+
+    #[cfg(feature = "google")]
+    let cached_content = args
+        .cached_content
+        .as_deref()
+        .map(cache_content::resolve_name)
+        .transpose()?;
+    #[cfg(not(feature = "google"))]
+    let cached_content = None;
 
-    let provider = app::build_provider(&http, cfg.as_ref(), &provider_name).await?;
+    // Uploading is a side effect, so --dry-run describes the request without it.
+    #[cfg(feature = "google")]
+    let attachments = if args.attach.is_empty() || args.dry_run {
+        Vec::new()
+    } else {
+        if provider_name != "google" {
+            anyhow::bail!("--attach needs the google provider (using {provider_name})");
+        }
+        let google = app::build_google_provider(&http, cfg.as_ref(), &provider_opts).await?;
+        attach::attach(&google, &args.attach).await?
+    };
+    #[cfg(not(feature = "google"))]
+    let attachments = Vec::new();
+
+    if args.search && provider_name != "google" {
+        anyhow::bail!("--search needs the google provider (using {provider_name})");
+    }
+
+    let mut generation = provider::GenerationConfig {
+        temperature: args.temperature,
+        top_p: args.top_p,
+        top_k: args.top_k,
+        seed: args.seed,
+        max_output_tokens: args.max_tokens,
+        stop_sequences: args.stop.clone(),
+        presence_penalty: args.presence_penalty,
+        frequency_penalty: args.frequency_penalty,
+        response_logprobs: args.logprobs.map(|_| true),
+        logprobs: args.logprobs,
+        ..Default::default()
+    };
+    if args.deterministic {
+        generation.temperature = Some(0.0);
+        generation.seed.get_or_insert(0);
+    }
+    generation.response_mime_type = args.response_mime_type.clone();
+    if !args.enum_values.is_empty() {
+        generation.constrain_to_enum(&args.enum_values).context("invalid --enum")?;
+    }
+    generation.validate().context("invalid sampling settings")?;
+
+    let mut labels = cfg.as_ref().map(|c| c.google.labels.clone()).unwrap_or_default();
+    labels.extend(args.labels.iter().cloned());
+    #[cfg(feature = "google")]
+    provider::google::validate_labels(&labels)?;
+
+    let request_id = app::new_request_id();
+    if args.echo_request_id {
+        eprintln!("request id: {request_id}");
+    }
 
     let req = ChatRequest {
-        model,
+        model: model.clone(),
         prompt,
+        system: app::compose_system(&system_layers),
         include_directories: args.include_directories,
+        cached_content,
+        generation,
+        raw_events: args.output_format.needs_raw_events(),
+        request_id: Some(request_id.clone()),
+        history,
+        labels,
+        attachments,
+        search: args.search,
+    };
+
+    if let Some(budget) = args.budget_tokens_total {
+        let spent = conversation.as_ref().map_or(0, |c| c.total_tokens);
+        let estimate = context::estimate_tokens(req.prompt.len() + app::history_bytes(&req.history)) as u64;
+        if spent + estimate > budget {
+            anyhow::bail!(
+                "request (~{estimate} tokens) would exceed --budget-tokens-total {budget}: {} tokens left",
+                budget.saturating_sub(spent)
+            );
+        }
+    }
+
+    if args.print_url {
+        match provider.request_url(&req.model)? {
+            Some(url) => eprintln!("POST {url}"),
+            None => eprintln!("note: the {} provider has no request URL to print", provider.name()),
+        }
+    }
+
+    if args.dry_run {
+        let described = provider.describe_request(&req)?;
+        println!("{}", serde_json::to_string_pretty(&described)?);
+        return Ok(());
+    }
+
+    let fallbacks = if args.fallback_models.is_empty() {
+        cfg.as_ref().map(|c| c.model_fallbacks.clone()).unwrap_or_default()
+    } else {
+        args.fallback_models.clone()
     };
 
-    let mut stream = provider
-        .stream_chat(req)
-        .await
-        .context("provider failed to start streaming")?;
+    let backoff = retry::Backoff {
+        retries: args.retries,
+        ..Default::default()
+    };
+    let redact = args
+        .redact
+        .iter()
+        .map(|p| {
+            let re = regex::Regex::new(p).with_context(|| format!("invalid --redact pattern {p:?}"))?;
+            if re.is_match("") {
+                anyhow::bail!("--redact pattern {p:?} matches empty text");
+            }
+            Ok(re)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if !redact.is_empty() && matches!(args.output_format, output::OutputFormat::Json | output::OutputFormat::Sse) {
+        // These print the API payloads as received, which can't be masked.
+        anyhow::bail!("--redact cannot be used with --output-format json or sse");
+    }
+
+    if args.no_stream {
+        if !matches!(args.output_format, output::OutputFormat::Text | output::OutputFormat::Md) {
+            anyhow::bail!("--no-stream applies to the text and md output formats");
+        }
+        let mut requests = 0;
+        let answer = app::generate_with_fallback(provider.as_ref(), req.clone(), &fallbacks, &backoff, &mut requests)
+            .instrument(tracing::info_span!("request", id = %request_id))
+            .await
+            .map_err(|e| e.context(format!("request {request_id} failed")))?;
+        let answer = redact.iter().fold(answer, |text, re| {
+            re.replace_all(&text, provider::transform::Redact::MASK).into_owned()
+        });
+        if !args.enum_values.is_empty() && !args.enum_values.iter().any(|v| v == answer.trim()) {
+            anyhow::bail!(
+                "request {request_id}: the answer {:?} is not one of: {}",
+                answer.trim(),
+                args.enum_values.join(", ")
+            );
+        }
+        match &args.output_file {
+            // Written in full or not at all, like the streamed `.partial` file.
+            Some(path) => paths::write_atomic(path, format!("{answer}\n").as_bytes())?,
+            None => println!("{answer}"),
+        }
+        if !args.no_save_last {
+            if let Err(e) = last::save(&last::LastResponse::new(model, user_prompt, answer)) {
+                tracing::warn!("failed to save last response: {e:#}");
+            }
+        }
+        return Ok(());
+    }
 
-    use tokio_stream::StreamExt;
-    while let Some(item) = stream.next().await {
-        let chunk = item.context("stream chunk error")?;
-        print!("{}", chunk.text);
-        use std::io::Write;
-        std::io::stdout().flush().ok();
+    if args.abort_after_first_chunk {
+        use tokio_stream::StreamExt;
+        let mut requests = 0;
+        let sent = std::time::Instant::now();
+        let mut stream = app::stream_with_fallback(provider.as_ref(), req, &fallbacks, &backoff, &mut requests)
+            .await
+            .context("provider failed to start streaming")?;
+        let headers = sent.elapsed();
+        let first = stream.next().await;
+        let first_chunk = sent.elapsed();
+        // The provider stops reading and closes the connection once the
+        // receiving end is gone, so the rest is never generated or sent.
+        drop(stream);
+        match first {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.context(format!("request {request_id} failed"))),
+            None => anyhow::bail!("request {request_id}: the response ended before any chunk arrived"),
+        }
+        println!("time to first chunk: {} ms", first_chunk.as_millis());
+        println!("response headers:    {} ms", headers.as_millis());
+        if requests > 1 {
+            println!("(after {} retries)", requests - 1);
+        }
+        return Ok(());
+    }
+
+    // --output-file is written as `<path>.partial` and only renamed into place
+    // once the stream completes, so the final name never holds a truncated answer.
+    let partial = args.output_file.as_deref().map(output::partial_path);
+    let sink: Box<dyn std::io::Write + Send> = match &partial {
+        None if args.quiet_stream => Box::new(std::io::sink()),
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create output file: {}", path.display()))?,
+        ),
+        // Pacing and paging are for people watching: only text output to a terminal.
+        None => {
+            use std::io::IsTerminal;
+            let paced = matches!(args.output_format, output::OutputFormat::Text | output::OutputFormat::Md)
+                && std::io::stdout().is_terminal();
+            let pager = env::pager().unwrap_or_else(|| "less".to_string());
+            match args.typewriter.filter(|_| paced) {
+                Some(cps) => Box::new(output::Paced::typewriter(std::io::stdout(), cps)),
+                None if args.pager && paced && pager != "cat" => Box::new(output::Pager::new(pager)),
+                None => Box::new(std::io::stdout()),
+            }
+        }
+    };
+    let sink: Box<dyn std::io::Write + Send> = match args.max_tokens_per_minute {
+        Some(tpm) => Box::new(output::Paced::throttle(sink, tpm)),
+        None => sink,
+    };
+    if args.only_final && !matches!(args.output_format, output::OutputFormat::Text | output::OutputFormat::Md) {
+        anyhow::bail!("--only-final applies to the text and md output formats");
+    }
+    let mut out = output::EventFormatter::new(sink, args.output_format)
+        .with_dedup(args.dedup_stream)
+        .with_only_final(args.only_final);
+
+    // Requests sent, and the transfer stats and usage of the last one, for --stats.
+    let mut requests = 0;
+    let mut last_stats = None;
+    let mut last_usage = None;
+    // For --quiet-stream's summary.
+    let started = std::time::Instant::now();
+    let mut finish_reason = None;
+    // (answer, total tokens reported across attempts)
+    let streamed: anyhow::Result<(String, u64)> = async {
+        use tokio_stream::StreamExt;
+        let mut empty_retries = u32::from(args.retry_empty);
+        let mut tokens = 0;
+        loop {
+            let mut stream =
+                app::stream_with_fallback(provider.as_ref(), req.clone(), &fallbacks, &backoff, &mut requests)
+                    .await
+                    .context("provider failed to start streaming")?;
+            for re in &redact {
+                let re = re.clone();
+                stream = provider::transform::apply(stream, move || Box::new(provider::transform::Redact::new(re.clone())));
+            }
+            let mut check = app::EmptyCheck::default();
+            let mut attempt_tokens = 0;
+            while let Some(item) = stream.next().await {
+                let event = item.context("stream chunk error")?;
+                check.observe(&event);
+                match &event {
+                    provider::ChatEvent::Usage(usage) => {
+                        attempt_tokens = usage.total_tokens;
+                        last_usage = Some(*usage);
+                    }
+                    provider::ChatEvent::Stats(stats) => last_stats = Some(*stats),
+                    provider::ChatEvent::Finish { reason } => finish_reason = Some(reason.clone()),
+                    provider::ChatEvent::Blocked { reason } => finish_reason = Some(format!("blocked: {reason}")),
+                    _ => {}
+                }
+                out.write_event(&event).context("failed to write output")?;
+            }
+            tokens += attempt_tokens;
+            if empty_retries == 0 || !check.is_unexplained_empty() {
+                break;
+            }
+            empty_retries -= 1;
+            tracing::info!("empty response with no finish or block reason; retrying once");
+        }
+        let answer = out.answer().to_string();
+        out.finish().context("failed to write output")?;
+        Ok((answer, tokens))
+    }
+    .instrument(tracing::info_span!("request", id = %request_id))
+    .await;
+    if args.stats {
+        app::print_stats(last_stats, last_usage, requests.saturating_sub(1));
+    }
+    if args.show_usage {
+        app::print_usage(last_usage);
+    }
+    if args.quiet_stream {
+        let elapsed = started.elapsed().as_secs_f64();
+        match &streamed {
+            Ok((_, tokens)) => eprintln!(
+                "ok: {} in {elapsed:.2}s, {tokens} tokens",
+                finish_reason.as_deref().unwrap_or("no finish reason")
+            ),
+            Err(_) => eprintln!("failed after {elapsed:.2}s"),
+        }
+    }
+    let (answer, tokens) = match streamed {
+        Ok(r) => r,
+        Err(e) => {
+            if let Some(path) = &partial {
+                eprintln!("note: incomplete output kept at {}", path.display());
+            }
+            if args.show_raw_errors {
+                if let Some(api) = e.downcast_ref::<provider::ApiError>() {
+                    eprintln!("--- raw error response ---\n{}\n---", api.raw().trim_end());
+                }
+            }
+            return Err(e.context(format!("request {request_id} failed")));
+        }
+    };
+    // The API should never answer outside the enum, but a script relying on
+    // it must not be handed anything else.
+    if !args.enum_values.is_empty() && !args.enum_values.iter().any(|v| v == answer.trim()) {
+        if let Some(path) = &partial {
+            eprintln!("note: output kept at {}", path.display());
+        }
+        anyhow::bail!(
+            "request {request_id}: the answer {:?} is not one of: {}",
+            answer.trim(),
+            args.enum_values.join(", ")
+        );
+    }
+    if let (Some(from), Some(to)) = (&partial, &args.output_file) {
+        std::fs::rename(from, to).with_context(|| format!("failed to move output into place: {}", to.display()))?;
+    }
+
+    if let (Some(name), Some(mut conversation)) = (&args.session, conversation) {
+        conversation.push_turn(user_prompt.clone(), answer.clone());
+        conversation.total_tokens += tokens;
+        session::save(name, &conversation)?;
+    }
+
+    if !args.no_save_last && !args.quiet_stream {
+        if let Err(e) = last::save(&last::LastResponse::new(model, user_prompt, answer)) {
+            tracing::warn!("failed to save last response: {e:#}");
+        }
     }
-    println!();
 
     Ok(())
 }
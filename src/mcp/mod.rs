@@ -4,7 +4,7 @@ mod stdio;
 mod tools;
 
 use crate::cli::McpCommand;
-use crate::paths;
+use crate::{paths, state};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
@@ -17,6 +17,90 @@ pub struct McpServerConfig {
     pub args: Vec<String>,
     #[serde(default)]
     pub enabled: bool,
+    /// How outgoing messages are framed. Incoming messages are auto-detected.
+    #[serde(default)]
+    pub framing: Framing,
+    /// How many times to send `initialize` before giving up (default 3).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_attempts: Option<u32>,
+    /// Pause between `initialize` attempts, in milliseconds (default 500).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_retry_delay_ms: Option<u64>,
+    /// Upper bound on spawn + handshake, in seconds (default 30).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn_timeout_secs: Option<u64>,
+    /// Time allowed to finish a message once it has started, in seconds (default 30).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
+}
+
+impl McpServerConfig {
+    pub fn init_attempts(&self) -> u32 {
+        self.init_attempts.unwrap_or(3).max(1)
+    }
+
+    pub fn init_retry_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.init_retry_delay_ms.unwrap_or(500))
+    }
+
+    pub fn spawn_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.spawn_timeout_secs.unwrap_or(30))
+    }
+
+    pub fn read_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.read_timeout_secs.unwrap_or(30))
+    }
+}
+
+/// JSON-RPC message framing on the server's stdio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// `Content-Length` headers followed by the JSON body (LSP-style).
+    #[default]
+    Lsp,
+    /// One JSON value per line.
+    Ndjson,
+}
+
+/// Servers queried at once by `mcp tools`.
+const DISCOVERY_CONCURRENCY: usize = 4;
+
+/// List tools from each server concurrently. Results are sorted by server
+/// name so output doesn't depend on which server answered first.
+async fn discover_tools(servers: Vec<McpServerConfig>) -> Vec<(String, anyhow::Result<Vec<tools::McpTool>>)> {
+    let limit = std::sync::Arc::new(tokio::sync::Semaphore::new(DISCOVERY_CONCURRENCY));
+    let mut set = tokio::task::JoinSet::new();
+    for s in servers {
+        let limit = limit.clone();
+        set.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let result = stdio::list_tools(&s).await;
+            (s.name, result)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = set.join_next().await {
+        match joined {
+            Ok(r) => results.push(r),
+            Err(e) => tracing::warn!("tool discovery task failed: {e}"),
+        }
+    }
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    results
+}
+
+/// The command and leading args for `mcp add --npx/--uvx/--docker`.
+fn launcher_command(npx: Option<String>, uvx: Option<String>, docker: Option<String>) -> Option<(String, Vec<String>)> {
+    if let Some(package) = npx {
+        return Some(("npx".to_string(), vec!["-y".to_string(), package]));
+    }
+    if let Some(package) = uvx {
+        return Some(("uvx".to_string(), vec![package]));
+    }
+    // `-i` keeps stdin open; without it the server sees EOF immediately.
+    docker.map(|image| ("docker".to_string(), vec!["run".into(), "-i".into(), "--rm".into(), image]))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -27,7 +111,36 @@ struct McpServersFile {
 
 pub async fn cmd_mcp(cmd: McpCommand) -> anyhow::Result<()> {
     match cmd {
-        McpCommand::Add { name, command, args } => {
+        McpCommand::Add {
+            name,
+            command,
+            args,
+            npx,
+            uvx,
+            docker,
+            framing,
+            init_attempts,
+            init_retry_delay_ms,
+            spawn_timeout_secs,
+            read_timeout_secs,
+            strict,
+        } => {
+            let (command, args) = match launcher_command(npx, uvx, docker) {
+                Some((launcher, mut launcher_args)) => {
+                    // Positionals after the name are all arguments to the package.
+                    launcher_args.extend(command);
+                    launcher_args.extend(args);
+                    (launcher, launcher_args)
+                }
+                None => (command.context("missing command")?, args),
+            };
+            // The server may still find it at spawn time (a different PATH or
+            // working directory), so a miss is only fatal with --strict.
+            match find_command(&command) {
+                Some(path) => println!("{command}: {}", path.display()),
+                None if strict => anyhow::bail!("command not found on PATH: {command}"),
+                None => eprintln!("warning: command not found on PATH: {command} (saving anyway)"),
+            }
             let mut file = load()?;
             if file.servers.iter().any(|s| s.name == name) {
                 anyhow::bail!("server already exists: {name}");
@@ -37,6 +150,11 @@ pub async fn cmd_mcp(cmd: McpCommand) -> anyhow::Result<()> {
                 command,
                 args,
                 enabled: true,
+                framing,
+                init_attempts,
+                init_retry_delay_ms,
+                spawn_timeout_secs,
+                read_timeout_secs,
             });
             save(&file)?;
             Ok(())
@@ -68,50 +186,59 @@ pub async fn cmd_mcp(cmd: McpCommand) -> anyhow::Result<()> {
             save(&file)?;
             Ok(())
         }
-        McpCommand::Enable { name } => {
-            let mut file = load()?;
-            let mut found = false;
-            for s in &mut file.servers {
-                if s.name == name {
-                    s.enabled = true;
-                    found = true;
-                }
-            }
-            if !found {
-                anyhow::bail!("no such server: {name}");
-            }
-            save(&file)?;
-            Ok(())
-        }
-        McpCommand::Disable { name } => {
-            let mut file = load()?;
-            let mut found = false;
-            for s in &mut file.servers {
-                if s.name == name {
-                    s.enabled = false;
-                    found = true;
-                }
-            }
-            if !found {
-                anyhow::bail!("no such server: {name}");
-            }
-            save(&file)?;
-            Ok(())
-        }
-        McpCommand::Tools => {
+        McpCommand::Enable { name, all, except } => set_enabled(name, all, &except, true),
+        McpCommand::Disable { name, all, except } => set_enabled(name, all, &except, false),
+        McpCommand::Tools {
+            server,
+            declarations,
+            json,
+        } => {
             let file = load()?;
-            let enabled: Vec<_> = file.servers.iter().filter(|s| s.enabled).cloned().collect();
-            if enabled.is_empty() {
+            let servers: Vec<_> = match &server {
+                Some(name) => vec![find_server(&file, name)?],
+                None => file.servers.iter().filter(|s| s.enabled).cloned().collect(),
+            };
+            if servers.is_empty() {
                 println!("(no enabled MCP servers)");
                 return Ok(());
             }
 
             let mut reg = tools::ToolRegistry::default();
-            for s in enabled {
-                let tools = stdio::list_tools(&s)
+            if server.is_some() {
+                let s = &servers[0];
+                let tools = stdio::list_tools(s)
                     .await
                     .with_context(|| format!("failed to list tools from server {}", s.name))?;
                 reg.register_server_tools(&s.name, tools);
+            } else {
+                let total = servers.len();
+                let mut failed = 0;
+                for (name, result) in discover_tools(servers).await {
+                    match result {
+                        Ok(tools) => reg.register_server_tools(&name, tools),
+                        Err(e) => {
+                            failed += 1;
+                            eprintln!("warning: failed to list tools from server {name}: {e:#}");
+                        }
+                    }
+                }
+                if failed == total {
+                    anyhow::bail!("no MCP server could be queried for tools");
+                }
+            }
+
+            if declarations {
+                let decls = reg
+                    .list()
+                    .iter()
+                    .map(|t| t.to_function_declaration())
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                println!("{}", serde_json::to_string_pretty(&decls)?);
+                return Ok(());
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(reg.list())?);
+                return Ok(());
             }
 
             for t in reg.list() {
@@ -119,9 +246,185 @@ pub async fn cmd_mcp(cmd: McpCommand) -> anyhow::Result<()> {
             }
             Ok(())
         }
+        McpCommand::Call {
+            server,
+            tool,
+            args,
+            stdin_json,
+        } => {
+            let raw = if stdin_json {
+                std::io::read_to_string(std::io::stdin()).context("failed to read arguments from stdin")?
+            } else {
+                args.unwrap_or_else(|| "{}".to_string())
+            };
+            let arguments = parse_arguments(&raw)?;
+
+            let s = find_server(&load()?, &server)?;
+            let result = stdio::call_tool(&s, &tool, arguments)
+                .await
+                .with_context(|| format!("failed to call tool {tool} on server {}", s.name))?;
+
+            print_tool_result(&result)?;
+            if result.is_error {
+                anyhow::bail!("tool {tool} reported an error");
+            }
+            Ok(())
+        }
+        McpCommand::Ping { name, count } => {
+            let s = find_server(&load()?, &name)?;
+            let (method, times) = stdio::ping(&s, count).await?;
+            for (i, t) in times.iter().enumerate() {
+                println!("{method} {}: {:.1} ms", i + 1, t.as_secs_f64() * 1000.0);
+            }
+            let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+            let min = times.iter().copied().min().unwrap_or_default();
+            let max = times.iter().copied().max().unwrap_or_default();
+            let avg = times.iter().sum::<std::time::Duration>() / count;
+            println!("min/avg/max = {:.1}/{:.1}/{:.1} ms", ms(min), ms(avg), ms(max));
+            Ok(())
+        }
+        McpCommand::Run { name, pretty } => {
+            let s = find_server(&load()?, &name)?;
+            stdio::run_proxy(&s, pretty).await
+        }
     }
 }
 
+/// Run the tool named by a `--tool-result-from` spec (`<server> <tool> [json args]`)
+/// and render its output as a prompt context block.
+pub async fn tool_result_context(spec: &str) -> anyhow::Result<String> {
+    let mut parts = spec.trim().splitn(3, char::is_whitespace);
+    let (Some(server), Some(tool)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("--tool-result-from expects '<server> <tool> [json args]', got: {spec:?}");
+    };
+    let raw = parts.next().map(str::trim).filter(|r| !r.is_empty()).unwrap_or("{}");
+    let arguments = parse_arguments(raw)?;
+
+    let s = find_server(&load()?, server)?;
+    let result = stdio::call_tool(&s, tool, arguments)
+        .await
+        .with_context(|| format!("failed to call tool {tool} on server {}", s.name))?;
+    if result.is_error {
+        print_tool_result(&result)?;
+        anyhow::bail!("tool {tool} reported an error");
+    }
+
+    let text = result.content.iter().map(render_block).collect::<Vec<_>>().join("\n");
+    let mut out = format!("Tool result ({server}/{tool}):\n```\n{text}");
+    if !text.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str("```\n\n");
+    Ok(out)
+}
+
+fn parse_arguments(raw: &str) -> anyhow::Result<serde_json::Value> {
+    let arguments: serde_json::Value = serde_json::from_str(raw).context("tool arguments are not valid JSON")?;
+    if !arguments.is_object() {
+        anyhow::bail!("tool arguments must be a JSON object");
+    }
+    Ok(arguments)
+}
+
+/// `mcp enable`/`mcp disable`: one server by name, or with `all` every server
+/// not named in `except`. Bulk changes report how many servers changed.
+fn set_enabled(name: Option<String>, all: bool, except: &[String], enabled: bool) -> anyhow::Result<()> {
+    let mut file = load()?;
+    if let Some(unknown) = except.iter().find(|e| !file.servers.iter().any(|s| &s.name == *e)) {
+        anyhow::bail!("no such server: {unknown}");
+    }
+    let mut matched = false;
+    let mut changed = 0;
+    for s in &mut file.servers {
+        let selected = match &name {
+            Some(name) => s.name == *name,
+            None => all && !except.contains(&s.name),
+        };
+        if selected {
+            matched = true;
+            if s.enabled != enabled {
+                s.enabled = enabled;
+                changed += 1;
+            }
+        }
+    }
+    if let (Some(name), false) = (&name, matched) {
+        anyhow::bail!("no such server: {name}");
+    }
+    save(&file)?;
+    if all {
+        let verb = if enabled { "Enabled" } else { "Disabled" };
+        println!("{verb} {changed} server{}", if changed == 1 { "" } else { "s" });
+    }
+    Ok(())
+}
+
+/// Resolve `command` like a shell would: a path (anything with a separator)
+/// is checked as-is, a bare name is looked up in each `PATH` directory.
+fn find_command(command: &str) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if Path::new(command).components().count() > 1 {
+        vec![PathBuf::from(command)]
+    } else {
+        let dirs = crate::env::path().unwrap_or_default();
+        std::env::split_paths(&dirs).map(|dir| dir.join(command)).collect()
+    };
+    candidates.into_iter().find_map(|candidate| {
+        let candidate = if cfg!(windows) && candidate.extension().is_none() {
+            candidate.with_extension("exe")
+        } else {
+            candidate
+        };
+        is_executable(&candidate).then(|| candidate.canonicalize().unwrap_or(candidate))
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn find_server(file: &McpServersFile, name: &str) -> anyhow::Result<McpServerConfig> {
+    file.servers
+        .iter()
+        .find(|s| s.name == name)
+        .cloned()
+        .with_context(|| format!("no such server: {name}"))
+}
+
+/// Print each content block as it is decoded. Error results go to stderr.
+fn print_tool_result(result: &stdio::CallToolResult) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let mut out: Box<dyn Write> = if result.is_error {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+
+    for block in &result.content {
+        writeln!(out, "{}", render_block(block))?;
+        out.flush().ok();
+    }
+    Ok(())
+}
+
+fn render_block(block: &serde_json::Value) -> String {
+    match serde_json::from_value::<tools::ToolContent>(block.clone()) {
+        Ok(c) => c.render(),
+        // Unknown block types are printed verbatim.
+        Err(_) => block.to_string(),
+    }
+}
+
+/// Format history of `mcp_servers.json` (see [`state`]).
+const MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
 fn load() -> anyhow::Result<McpServersFile> {
     let path = paths::mcp_servers_path()?;
     load_from(&path)
@@ -137,7 +440,7 @@ fn load_from(path: &Path) -> anyhow::Result<McpServersFile> {
         }
     };
 
-    let parsed: McpServersFile = serde_json::from_slice(&bytes)
+    let parsed: McpServersFile = state::from_json(&bytes, MIGRATIONS)
         .with_context(|| format!("failed to parse JSON: {}", path.display()))?;
     Ok(parsed)
 }
@@ -148,23 +451,6 @@ fn save(file: &McpServersFile) -> anyhow::Result<()> {
 }
 
 fn save_to(path: &PathBuf, file: &McpServersFile) -> anyhow::Result<()> {
-    let dir = path.parent().unwrap_or_else(|| Path::new("."));
-    std::fs::create_dir_all(dir)
-        .with_context(|| format!("failed to create state dir: {}", dir.display()))?;
-
-    let tmp = {
-        let mut p = path.clone();
-        let name = path
-            .file_name()
-            .map(|s| s.to_string_lossy().to_string())
-            .unwrap_or_else(|| "mcp_servers.json".to_string());
-        p.set_file_name(format!("{name}.tmp"));
-        p
-    };
-
-    let bytes = serde_json::to_vec_pretty(file).context("failed to encode JSON")?;
-    std::fs::write(&tmp, bytes).with_context(|| format!("failed to write: {}", tmp.display()))?;
-    std::fs::rename(&tmp, path)
-        .with_context(|| format!("failed to replace: {}", path.display()))?;
-    Ok(())
+    let bytes = state::to_json(file, MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(path, &bytes)
 }
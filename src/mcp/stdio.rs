@@ -1,31 +1,14 @@
 #![cfg(feature = "mcp")]
 
-use super::McpServerConfig;
 use super::tools::McpTool;
+use super::{Framing, McpServerConfig};
 use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, ChildStdout};
 
 pub async fn list_tools(server: &McpServerConfig) -> anyhow::Result<Vec<McpTool>> {
-    let mut rpc = StdioRpc::spawn(server).await?;
-
-    // MCP initialize
-    let init = rpc
-        .request::<InitializeParams, InitializeResult>(
-            "initialize",
-            InitializeParams {
-                protocol_version: "2024-11-05".to_string(),
-                capabilities: serde_json::json!({}),
-                client_info: ClientInfo {
-                    name: env!("CARGO_PKG_NAME").to_string(),
-                    version: env!("CARGO_PKG_VERSION").to_string(),
-                },
-            },
-        )
-        .await
-        .context("initialize failed")?;
-
-    let _ = init;
+    let mut rpc = connect(server).await?;
 
     let tools = rpc
         .request::<serde_json::Value, ToolsListResult>("tools/list", serde_json::json!({}))
@@ -35,6 +18,149 @@ pub async fn list_tools(server: &McpServerConfig) -> anyhow::Result<Vec<McpTool>
     Ok(tools.tools)
 }
 
+pub async fn call_tool(
+    server: &McpServerConfig,
+    tool: &str,
+    arguments: serde_json::Value,
+) -> anyhow::Result<CallToolResult> {
+    let mut rpc = connect(server).await?;
+
+    rpc.request::<CallToolParams, CallToolResult>(
+        "tools/call",
+        CallToolParams {
+            name: tool.to_string(),
+            arguments,
+        },
+    )
+    .await
+    .with_context(|| format!("tools/call {tool} failed"))
+}
+
+/// Time `count` cheap requests to an initialized server. Uses `ping`, or
+/// `tools/list` for servers that don't implement it. Returns the method used
+/// and each round trip.
+pub async fn ping(server: &McpServerConfig, count: u32) -> anyhow::Result<(&'static str, Vec<std::time::Duration>)> {
+    let mut rpc = connect(server).await?;
+
+    let mut method = "ping";
+    let mut times = Vec::new();
+    while times.len() < count as usize {
+        let started = std::time::Instant::now();
+        let result = rpc.request::<serde_json::Value, serde_json::Value>(method, serde_json::json!({})).await;
+        match result {
+            Ok(_) => times.push(started.elapsed()),
+            Err(e) if method == "ping" && times.is_empty() => {
+                tracing::debug!(server = %server.name, error = %format!("{e:#}"), "ping unsupported; using tools/list");
+                method = "tools/list";
+            }
+            Err(e) => return Err(e.context(format!("{method} failed"))),
+        }
+    }
+    Ok((method, times))
+}
+
+/// Spawn the server and complete the MCP `initialize` handshake.
+///
+/// Slow-starting servers may close stdout or print non-JSON before they are
+/// ready, so `initialize` is retried (respawning the server if it exited) up
+/// to the server's `init_attempts`, all within its spawn timeout.
+async fn connect(server: &McpServerConfig) -> anyhow::Result<StdioRpc> {
+    let timeout = server.spawn_timeout();
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut rpc = StdioRpc::spawn(server).await?;
+
+    let mut attempt = 1;
+    loop {
+        let err = match tokio::time::timeout_at(deadline, rpc.initialize()).await {
+            Ok(Ok(())) => break,
+            Ok(Err(e)) => e,
+            Err(_) => {
+                return Err(anyhow!(
+                    "MCP server {} did not finish initializing within {}s",
+                    server.name,
+                    timeout.as_secs()
+                ))
+            }
+        };
+        if attempt >= server.init_attempts() {
+            return Err(err.context("initialize failed"));
+        }
+        tracing::debug!(server = %server.name, attempt, error = %format!("{err:#}"), "initialize failed; retrying");
+
+        let wake = (tokio::time::Instant::now() + server.init_retry_delay()).min(deadline);
+        tokio::time::sleep_until(wake).await;
+        if rpc.child.try_wait().context("failed to poll MCP server")?.is_some() {
+            rpc = StdioRpc::spawn(server).await?;
+        }
+        attempt += 1;
+    }
+
+    rpc.notify("notifications/initialized", serde_json::json!({}))
+        .await
+        .context("initialized notification failed")?;
+
+    Ok(rpc)
+}
+
+/// Spawn the server and relay raw JSON-RPC: each line typed on stdin is sent
+/// as one message (framed per the server config), and every message the
+/// server sends is printed on its own line. No handshake is performed.
+pub async fn run_proxy(server: &McpServerConfig, pretty: bool) -> anyhow::Result<()> {
+    let StdioRpc {
+        mut child,
+        mut stdin,
+        mut stdout,
+        framing,
+        ..
+    } = StdioRpc::spawn(server).await?;
+
+    // Runs until the server closes stdout; yields the error that ended it.
+    let name = server.name.clone();
+    let read_timeout = server.read_timeout();
+    let printer = tokio::spawn(async move {
+        loop {
+            let raw = match read_message(&mut stdout, &name, read_timeout).await {
+                Ok(raw) => raw,
+                Err(e) => return e,
+            };
+            let pretty_json = serde_json::from_slice::<serde_json::Value>(&raw)
+                .ok()
+                .filter(|_| pretty)
+                .and_then(|v| serde_json::to_string_pretty(&v).ok());
+            match pretty_json {
+                Some(json) => println!("{json}"),
+                None => println!("{}", String::from_utf8_lossy(&raw)),
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.context("failed to read stdin")? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // Catch typos locally instead of confusing the server.
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(line) {
+            eprintln!("not sent: invalid JSON: {e}");
+            continue;
+        }
+        write_frame(&mut stdin, framing, line.as_bytes()).await?;
+        if printer.is_finished() {
+            break;
+        }
+    }
+
+    // Closing stdin asks the server to exit; its remaining output is still printed.
+    drop(stdin);
+    let status = child.wait().await.context("failed to wait for MCP server")?;
+    if let Ok(e) = printer.await {
+        tracing::debug!("stopped reading from server: {e:#}");
+    }
+    eprintln!("server exited: {status}");
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct InitializeParams {
     #[serde(rename = "protocolVersion")]
@@ -63,12 +189,32 @@ struct ToolsListResult {
     tools: Vec<McpTool>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct CallToolParams {
+    name: String,
+    arguments: serde_json::Value,
+}
+
+/// Result of `tools/call`. Content blocks are kept as raw JSON so callers can
+/// decode them one at a time.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallToolResult {
+    #[serde(default)]
+    pub content: Vec<serde_json::Value>,
+    #[serde(default, rename = "isError")]
+    pub is_error: bool,
+}
+
 #[derive(Debug)]
 struct StdioRpc {
     child: tokio::process::Child,
-    stdin: tokio::process::ChildStdin,
-    stdout: tokio::process::ChildStdout,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    framing: Framing,
     next_id: u64,
+    /// Server name, for errors.
+    name: String,
+    read_timeout: std::time::Duration,
 }
 
 impl StdioRpc {
@@ -92,11 +238,30 @@ impl StdioRpc {
         Ok(Self {
             child,
             stdin,
-            stdout,
+            stdout: BufReader::new(stdout),
+            framing: server.framing,
             next_id: 1,
+            name: server.name.clone(),
+            read_timeout: server.read_timeout(),
         })
     }
 
+    async fn initialize(&mut self) -> anyhow::Result<()> {
+        self.request::<InitializeParams, InitializeResult>(
+            "initialize",
+            InitializeParams {
+                protocol_version: "2024-11-05".to_string(),
+                capabilities: serde_json::json!({}),
+                client_info: ClientInfo {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
     async fn request<P: Serialize, R: for<'de> Deserialize<'de>>(
         &mut self,
         method: &str,
@@ -138,56 +303,121 @@ impl StdioRpc {
         }
     }
 
+    async fn notify<P: Serialize>(&mut self, method: &str, params: P) -> anyhow::Result<()> {
+        let msg = JsonRpcNotification {
+            jsonrpc: "2.0",
+            method,
+            params,
+        };
+        self.write_message(&msg).await
+    }
+
     async fn write_message<T: Serialize>(&mut self, msg: &T) -> anyhow::Result<()> {
         let body = serde_json::to_vec(msg).context("failed to encode JSON")?;
-        let header = format!("Content-Length: {}\r\n\r\n", body.len());
-        self.stdin
-            .write_all(header.as_bytes())
-            .await
-            .context("failed to write header")?;
-        self.stdin
-            .write_all(&body)
-            .await
-            .context("failed to write body")?;
-        self.stdin.flush().await.ok();
-        Ok(())
+        write_frame(&mut self.stdin, self.framing, &body).await
     }
 
     async fn read_message(&mut self) -> anyhow::Result<Vec<u8>> {
-        // Read headers until CRLF CRLF.
-        let mut header_buf = Vec::new();
-        let mut tmp = [0u8; 1];
-        loop {
-            let n = self.stdout.read(&mut tmp).await.context("read header")?;
-            if n == 0 {
-                return Err(anyhow!("MCP server closed stdout"));
-            }
-            header_buf.push(tmp[0]);
-            if header_buf.ends_with(b"\r\n\r\n") {
-                break;
-            }
-            if header_buf.len() > 8192 {
-                return Err(anyhow!("header too large"));
-            }
+        read_message(&mut self.stdout, &self.name, self.read_timeout).await
+    }
+}
+
+async fn write_frame(stdin: &mut ChildStdin, framing: Framing, body: &[u8]) -> anyhow::Result<()> {
+    match framing {
+        Framing::Lsp => {
+            let header = format!("Content-Length: {}\r\n\r\n", body.len());
+            stdin
+                .write_all(header.as_bytes())
+                .await
+                .context("failed to write header")?;
+            stdin.write_all(body).await.context("failed to write body")?;
+        }
+        Framing::Ndjson => {
+            stdin.write_all(body).await.context("failed to write body")?;
+            stdin.write_all(b"\n").await.context("failed to write newline")?;
         }
+    }
+    stdin.flush().await.ok();
+    Ok(())
+}
 
-        let header_str = std::str::from_utf8(&header_buf).context("header not UTF-8")?;
-        let mut content_len: Option<usize> = None;
-        for line in header_str.split("\r\n") {
-            let Some((k, v)) = line.split_once(":") else { continue; };
-            if k.eq_ignore_ascii_case("content-length") {
-                content_len = Some(v.trim().parse::<usize>().context("bad Content-Length")?);
-            }
+/// Read one message, detecting the framing: a message starting with `{` is
+/// a single line of JSON, anything else is parsed as `Content-Length` headers.
+///
+/// Waiting for a message to start is unbounded (tools may run for a long
+/// time), but once it has started the rest must arrive within `timeout`.
+async fn read_message(
+    stdout: &mut BufReader<ChildStdout>,
+    server: &str,
+    timeout: std::time::Duration,
+) -> anyhow::Result<Vec<u8>> {
+    // Skip whitespace between messages (e.g. blank lines in ndjson).
+    let first = loop {
+        let buf = stdout.fill_buf().await.context("read message")?;
+        let Some(&b) = buf.first() else {
+            return Err(anyhow!("MCP server closed stdout"));
+        };
+        if !b.is_ascii_whitespace() {
+            break b;
         }
-        let len = content_len.context("missing Content-Length")?;
+        let ws = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        stdout.consume(ws);
+    };
 
-        let mut body = vec![0u8; len];
-        self.stdout
-            .read_exact(&mut body)
-            .await
-            .context("read body")?;
-        Ok(body)
+    let rest = async {
+        if first == b'{' {
+            read_line_message(stdout).await
+        } else {
+            read_lsp_message(stdout).await
+        }
+    };
+    tokio::time::timeout(timeout, rest).await.map_err(|_| {
+        anyhow!(
+            "MCP server {server} sent a partial message and then stalled (nothing more for {}s)",
+            timeout.as_secs()
+        )
+    })?
+}
+
+async fn read_line_message(stdout: &mut BufReader<ChildStdout>) -> anyhow::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    stdout.read_until(b'\n', &mut line).await.context("read line")?;
+    while line.last().is_some_and(|b| b.is_ascii_whitespace()) {
+        line.pop();
     }
+    Ok(line)
+}
+
+async fn read_lsp_message(stdout: &mut BufReader<ChildStdout>) -> anyhow::Result<Vec<u8>> {
+    // Read header lines until an empty line.
+    let mut content_len: Option<usize> = None;
+    let mut header_bytes = 0usize;
+    loop {
+        let mut line = Vec::new();
+        let n = stdout.read_until(b'\n', &mut line).await.context("read header")?;
+        if n == 0 {
+            return Err(anyhow!("MCP server closed stdout"));
+        }
+        header_bytes += n;
+        if header_bytes > 8192 {
+            return Err(anyhow!("header too large"));
+        }
+
+        let line = std::str::from_utf8(&line).context("header not UTF-8")?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let Some((k, v)) = line.split_once(':') else { continue; };
+        if k.trim().eq_ignore_ascii_case("content-length") {
+            content_len = Some(v.trim().parse::<usize>().context("bad Content-Length")?);
+        }
+    }
+    let len = content_len.context("missing Content-Length")?;
+
+    let mut body = vec![0u8; len];
+    stdout.read_exact(&mut body).await.context("read body")?;
+    Ok(body)
 }
 
 #[derive(Debug, Serialize)]
@@ -198,6 +428,13 @@ struct JsonRpcRequest<'a, P> {
     params: P,
 }
 
+#[derive(Debug, Serialize)]
+struct JsonRpcNotification<'a, P> {
+    jsonrpc: &'static str,
+    method: &'a str,
+    params: P,
+}
+
 #[derive(Debug, Deserialize)]
 struct JsonRpcOkEnvelope<R> {
     #[allow(dead_code)]
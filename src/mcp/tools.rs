@@ -1,17 +1,19 @@
 #![cfg(feature = "mcp")]
 
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpTool {
     pub name: String,
     #[serde(default)]
     pub description: Option<String>,
-    #[serde(default)]
+    #[serde(default, rename = "inputSchema", alias = "input_schema")]
     pub input_schema: serde_json::Value,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RegisteredTool {
     pub server: String,
     pub name: String,
@@ -40,3 +42,405 @@ impl ToolRegistry {
         &self.tools
     }
 }
+
+/// Gemini `FunctionDeclaration` (the shape used in `tools[].functionDeclarations`).
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionDeclaration {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// OpenAPI-subset schema; omitted for tools without parameters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+impl RegisteredTool {
+    /// Convert to a Gemini function declaration.
+    ///
+    /// Gemini accepts a restricted JSON Schema: unsupported keywords are dropped,
+    /// local `$ref`s are inlined, `["T", "null"]` types become `nullable`, and
+    /// `oneOf` becomes `anyOf`. Schemas that cannot be expressed (non-local or
+    /// recursive refs, multi-entry `allOf`, non-object roots) are rejected.
+    /// The name is sanitized to Gemini's `[A-Za-z0-9_.-]{1,64}` rule.
+    pub fn to_function_declaration(&self) -> anyhow::Result<FunctionDeclaration> {
+        let parameters = match &self.input_schema {
+            Value::Null => None,
+            schema => {
+                let converted = convert_schema(schema, schema, 0)
+                    .map_err(|e| anyhow!("tool {}: {e}", self.name))?;
+                if converted.get("type").and_then(Value::as_str) != Some("object") {
+                    return Err(anyhow!("tool {}: input schema must be an object", self.name));
+                }
+                let has_props = converted
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .is_some_and(|p| !p.is_empty());
+                has_props.then_some(converted)
+            }
+        };
+
+        Ok(FunctionDeclaration {
+            name: function_name(&self.name),
+            description: self.description.clone(),
+            parameters,
+        })
+    }
+}
+
+fn function_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') { c } else { '_' })
+        .collect();
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out.truncate(64);
+    out
+}
+
+/// Keywords Gemini's `Schema` understands.
+const SUPPORTED_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "description",
+    "nullable",
+    "enum",
+    "maxItems",
+    "minItems",
+    "properties",
+    "required",
+    "minProperties",
+    "maxProperties",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "example",
+    "anyOf",
+    "propertyOrdering",
+    "default",
+    "items",
+    "minimum",
+    "maximum",
+];
+
+const SUPPORTED_FORMATS: &[&str] = &["enum", "date-time", "int32", "int64", "float", "double"];
+
+const MAX_REF_DEPTH: usize = 16;
+
+fn convert_schema(node: &Value, root: &Value, depth: usize) -> anyhow::Result<Value> {
+    if depth > MAX_REF_DEPTH {
+        return Err(anyhow!("schema nesting too deep (recursive $ref?)"));
+    }
+    let Some(obj) = node.as_object() else {
+        // `true` / `{}`-style schemas accept anything; Gemini needs a type.
+        return Ok(serde_json::json!({ "type": "string" }));
+    };
+
+    if let Some(r) = obj.get("$ref").and_then(Value::as_str) {
+        let target = resolve_ref(root, r)?;
+        return convert_schema(target, root, depth + 1);
+    }
+
+    if let Some(all) = obj.get("allOf").and_then(Value::as_array) {
+        if all.len() != 1 {
+            return Err(anyhow!("allOf with {} entries is not supported", all.len()));
+        }
+        let mut merged = obj.clone();
+        merged.remove("allOf");
+        if let Value::Object(inner) = &all[0] {
+            for (k, v) in inner {
+                merged.entry(k.clone()).or_insert_with(|| v.clone());
+            }
+        }
+        return convert_schema(&Value::Object(merged), root, depth + 1);
+    }
+
+    let mut out = Map::new();
+    for (k, v) in obj {
+        match k.as_str() {
+            "type" => match v {
+                Value::Array(types) => {
+                    let non_null: Vec<&Value> = types.iter().filter(|t| t.as_str() != Some("null")).collect();
+                    if non_null.len() < types.len() {
+                        out.insert("nullable".into(), Value::Bool(true));
+                    }
+                    match non_null.as_slice() {
+                        [one] => {
+                            out.insert("type".into(), (*one).clone());
+                        }
+                        many => {
+                            let any: Vec<Value> =
+                                many.iter().map(|t| serde_json::json!({ "type": t })).collect();
+                            out.insert("anyOf".into(), Value::Array(any));
+                        }
+                    }
+                }
+                other => {
+                    out.insert("type".into(), other.clone());
+                }
+            },
+            "properties" => {
+                let Some(props) = v.as_object() else { continue };
+                let mut converted = Map::new();
+                for (name, schema) in props {
+                    converted.insert(name.clone(), convert_schema(schema, root, depth + 1)?);
+                }
+                out.insert("properties".into(), Value::Object(converted));
+            }
+            "items" => {
+                out.insert("items".into(), convert_schema(v, root, depth + 1)?);
+            }
+            "anyOf" | "oneOf" => {
+                let Some(variants) = v.as_array() else { continue };
+                let converted = variants
+                    .iter()
+                    .map(|s| convert_schema(s, root, depth + 1))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                out.insert("anyOf".into(), Value::Array(converted));
+            }
+            "const" => {
+                out.insert("enum".into(), Value::Array(vec![v.clone()]));
+            }
+            "enum" => {
+                // Gemini only supports string enums.
+                if v.as_array().is_some_and(|vals| vals.iter().all(Value::is_string)) {
+                    out.insert("enum".into(), v.clone());
+                }
+            }
+            "format" => {
+                if v.as_str().is_some_and(|f| SUPPORTED_FORMATS.contains(&f)) {
+                    out.insert("format".into(), v.clone());
+                }
+            }
+            k if SUPPORTED_KEYWORDS.contains(&k) => {
+                out.insert(k.to_string(), v.clone());
+            }
+            _ => {
+                tracing::debug!(keyword = %k, "dropping unsupported schema keyword");
+            }
+        }
+    }
+
+    // `const`/`enum` on strings needs an explicit type.
+    if out.contains_key("enum") && !out.contains_key("type") {
+        out.insert("type".into(), Value::String("string".into()));
+    }
+    if out.get("enum").and_then(Value::as_array).is_some_and(|e| !e.iter().all(Value::is_string)) {
+        out.remove("enum");
+    }
+
+    // Drop `required` entries that do not name a property.
+    if let Some(Value::Array(req)) = out.get("required").cloned() {
+        let props = out.get("properties").and_then(Value::as_object);
+        let kept: Vec<Value> = req
+            .into_iter()
+            .filter(|r| r.as_str().is_some_and(|n| props.is_some_and(|p| p.contains_key(n))))
+            .collect();
+        if kept.is_empty() {
+            out.remove("required");
+        } else {
+            out.insert("required".into(), Value::Array(kept));
+        }
+    }
+
+    Ok(Value::Object(out))
+}
+
+fn resolve_ref<'a>(root: &'a Value, r: &str) -> anyhow::Result<&'a Value> {
+    let Some(pointer) = r.strip_prefix('#') else {
+        return Err(anyhow!("non-local $ref is not supported: {r}"));
+    };
+    root.pointer(pointer)
+        .ok_or_else(|| anyhow!("unresolved $ref: {r}"))
+}
+
+/// A single content block from a `tools/call` result.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ToolContent {
+    Text {
+        text: String,
+    },
+    Image {
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+        data: String,
+    },
+    Resource {
+        resource: EmbeddedResource,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddedResource {
+    pub uri: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default, rename = "mimeType")]
+    pub mime_type: Option<String>,
+}
+
+impl ToolContent {
+    /// Human-readable rendering; non-text blocks are summarized.
+    pub fn render(&self) -> String {
+        match self {
+            ToolContent::Text { text } => text.clone(),
+            ToolContent::Image { mime_type, data } => {
+                format!("[image: {mime_type}, {} bytes base64]", data.len())
+            }
+            ToolContent::Resource { resource } => match &resource.text {
+                Some(text) => text.clone(),
+                None => format!(
+                    "[resource: {} ({})]",
+                    resource.uri,
+                    resource.mime_type.as_deref().unwrap_or("unknown type")
+                ),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, input_schema: Value) -> RegisteredTool {
+        RegisteredTool {
+            server: "test".into(),
+            name: name.into(),
+            description: Some("A test tool".into()),
+            input_schema,
+        }
+    }
+
+    fn parameters(schema: Value) -> Value {
+        tool("t", schema).to_function_declaration().unwrap().parameters.unwrap()
+    }
+
+    #[test]
+    fn converts_nested_objects_and_arrays() {
+        let converted = parameters(json!({
+            "type": "object",
+            "properties": {
+                "filter": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string", "minLength": 1 } },
+                    "required": ["name", "missing"],
+                },
+                "tags": { "type": "array", "items": { "type": ["string", "null"] }, "maxItems": 5 },
+            },
+            "required": ["filter"],
+        }));
+        assert_eq!(
+            converted,
+            json!({
+                "type": "object",
+                "properties": {
+                    "filter": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string", "minLength": 1 } },
+                        "required": ["name"],
+                    },
+                    "tags": { "type": "array", "items": { "type": "string", "nullable": true }, "maxItems": 5 },
+                },
+                "required": ["filter"],
+            })
+        );
+    }
+
+    #[test]
+    fn inlines_local_refs() {
+        let converted = parameters(json!({
+            "type": "object",
+            "properties": { "point": { "$ref": "#/$defs/point" } },
+            "$defs": {
+                "point": { "type": "object", "properties": { "x": { "type": "number" } } },
+            },
+        }));
+        assert_eq!(
+            converted,
+            json!({
+                "type": "object",
+                "properties": {
+                    "point": { "type": "object", "properties": { "x": { "type": "number" } } },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_remote_and_recursive_refs() {
+        let remote = json!({
+            "type": "object",
+            "properties": { "a": { "$ref": "https://example.com/schema.json" } },
+        });
+        assert!(tool("t", remote).to_function_declaration().is_err());
+
+        let recursive = json!({
+            "type": "object",
+            "properties": { "node": { "$ref": "#/$defs/node" } },
+            "$defs": { "node": { "$ref": "#/$defs/node" } },
+        });
+        assert!(tool("t", recursive).to_function_declaration().is_err());
+    }
+
+    #[test]
+    fn drops_unsupported_keywords() {
+        let converted = parameters(json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                "when": { "type": "string", "format": "date-time" },
+                "email": { "type": "string", "format": "email", "examples": ["a@b.c"] },
+            },
+        }));
+        assert_eq!(
+            converted,
+            json!({
+                "type": "object",
+                "properties": {
+                    "when": { "type": "string", "format": "date-time" },
+                    "email": { "type": "string" },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_only_string_enums() {
+        let converted = parameters(json!({
+            "type": "object",
+            "properties": {
+                "unit": { "enum": ["c", "f"] },
+                "mode": { "const": "fast" },
+                "level": { "type": "integer", "enum": [1, 2, 3] },
+            },
+        }));
+        assert_eq!(
+            converted,
+            json!({
+                "type": "object",
+                "properties": {
+                    "unit": { "type": "string", "enum": ["c", "f"] },
+                    "mode": { "type": "string", "enum": ["fast"] },
+                    "level": { "type": "integer" },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn omits_empty_parameters_and_sanitizes_names() {
+        let decl = tool("9 search/web", json!({ "type": "object", "properties": {} }))
+            .to_function_declaration()
+            .unwrap();
+        assert_eq!(decl.name, "_9_search_web");
+        assert!(decl.parameters.is_none());
+
+        assert!(tool("t", json!({ "type": "string" })).to_function_declaration().is_err());
+    }
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "google")]
+
+use crate::cli::ModelsCommand;
+use crate::{app, config};
+
+pub async fn cmd_models(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    provider_opts: &app::ProviderOptions,
+    cmd: ModelsCommand,
+) -> anyhow::Result<()> {
+    let provider = app::build_google_provider(http, cfg, provider_opts).await?;
+
+    match cmd {
+        ModelsCommand::List => {
+            let models = provider.list_models().await?;
+            if models.is_empty() {
+                println!("(no models)");
+                return Ok(());
+            }
+            for m in models {
+                println!(
+                    "{}\tin: {}\tout: {}\t{}",
+                    m.name,
+                    limit(m.input_token_limit),
+                    limit(m.output_token_limit),
+                    m.display_name.as_deref().unwrap_or("")
+                );
+            }
+            Ok(())
+        }
+        ModelsCommand::Show { name, json } => {
+            let m = provider.get_model(&name).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&m)?);
+                return Ok(());
+            }
+
+            println!("name: {}", m.name);
+            if let Some(v) = &m.display_name {
+                println!("display_name: {v}");
+            }
+            if let Some(v) = &m.description {
+                println!("description: {v}");
+            }
+            if let Some(v) = &m.version {
+                println!("version: {v}");
+            }
+            if let Some(v) = &m.base_model_id {
+                println!("base_model_id: {v}");
+            }
+            println!("input_token_limit: {}", limit(m.input_token_limit));
+            println!("output_token_limit: {}", limit(m.output_token_limit));
+            println!("supported_methods: {}", m.supported_generation_methods.join(", "));
+            if let Some(v) = m.temperature {
+                println!("temperature: {v}");
+            }
+            if let Some(v) = m.max_temperature {
+                println!("max_temperature: {v}");
+            }
+            if let Some(v) = m.top_p {
+                println!("top_p: {v}");
+            }
+            if let Some(v) = m.top_k {
+                println!("top_k: {v}");
+            }
+            if let Some(v) = m.thinking {
+                println!("thinking: {v}");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn limit(v: Option<u64>) -> String {
+    v.map(|n| n.to_string()).unwrap_or_else(|| "-".to_string())
+}
@@ -0,0 +1,75 @@
+//! Classification of connection-level HTTP failures into actionable hints.
+
+use std::error::Error as _;
+
+/// Common reasons a request never got an HTTP response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetErrorKind {
+    Dns,
+    Tls,
+    ConnectionRefused,
+    Proxy,
+    Timeout,
+}
+
+impl NetErrorKind {
+    pub fn hint(self) -> &'static str {
+        match self {
+            NetErrorKind::Dns => "could not resolve the host name; check your network connection and DNS settings",
+            NetErrorKind::Tls => {
+                "TLS handshake failed; check that the system clock is correct and that no proxy or firewall is intercepting HTTPS"
+            }
+            NetErrorKind::ConnectionRefused => {
+                "connection refused; check your network connection and any firewall or VPN"
+            }
+            NetErrorKind::Proxy => "proxy error; check HTTPS_PROXY / HTTP_PROXY / NO_PROXY",
+            NetErrorKind::Timeout => "the request timed out; check your network connection or try again",
+        }
+    }
+}
+
+/// Classify `err` by walking its source chain. `None` for errors that are
+/// not connection problems (or that we don't recognize).
+pub fn classify(err: &reqwest::Error) -> Option<NetErrorKind> {
+    if err.is_timeout() {
+        return Some(NetErrorKind::Timeout);
+    }
+
+    let mut messages = Vec::new();
+    let mut source = err.source();
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::ConnectionRefused {
+                return Some(NetErrorKind::ConnectionRefused);
+            }
+        }
+        messages.push(e.to_string().to_ascii_lowercase());
+        source = e.source();
+    }
+    let chain = messages.join(": ");
+
+    if chain.contains("proxy") {
+        Some(NetErrorKind::Proxy)
+    } else if chain.contains("dns error")
+        || chain.contains("failed to lookup address")
+        || chain.contains("name or service not known")
+        || chain.contains("nodename nor servname")
+    {
+        Some(NetErrorKind::Dns)
+    } else if chain.contains("certificate") || chain.contains("tls") || chain.contains("handshake") {
+        Some(NetErrorKind::Tls)
+    } else if chain.contains("connection refused") {
+        Some(NetErrorKind::ConnectionRefused)
+    } else {
+        None
+    }
+}
+
+/// Convert `err` into an error that carries a hint when it is a recognized
+/// connection problem. Use with `map_err` before adding the usual context.
+pub fn hinted(err: reqwest::Error) -> anyhow::Error {
+    match classify(&err) {
+        Some(kind) => anyhow::Error::new(err).context(kind.hint()),
+        None => anyhow::Error::new(err),
+    }
+}
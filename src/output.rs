@@ -0,0 +1,491 @@
+use crate::provider::dedup::StreamDedup;
+use crate::provider::{ChatEvent, Grounding, TokenLogprob, TokenUsage};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// How a streamed response is written out (`--output-format`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The answer text as it streams.
+    #[default]
+    #[value(alias = "plain")]
+    Text,
+    /// The answer text unchanged, for piping into a markdown renderer.
+    #[value(alias = "markdown")]
+    Md,
+    /// One JSON object per event: `{"type":"text"|"thought","text":...}` or
+    /// `{"type":"logprobs","tokens":[...]}`, then `{"type":"citations",...}`
+    /// for grounded answers, `{"type":"usage",...}` and `{"type":"done"}`.
+    #[value(alias = "jsonl")]
+    Ndjson,
+    /// Gemini's own response JSON, one document per line.
+    #[value(alias = "stream-json")]
+    Json,
+    /// The response re-encoded as server-sent events (`data: ...`).
+    #[value(alias = "raw")]
+    Sse,
+}
+
+impl OutputFormat {
+    /// Whether the provider must pass through undecoded payloads.
+    pub fn needs_raw_events(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Sse)
+    }
+}
+
+/// Writes [`ChatEvent`]s in one [`OutputFormat`].
+pub struct EventFormatter<W: Write> {
+    out: Utf8Writer<W>,
+    format: OutputFormat,
+    dedup: Option<StreamDedup>,
+    /// Collected for the stderr table printed by `finish` in text formats.
+    logprobs: Vec<TokenLogprob>,
+    /// Answer text written so far (after dedup), in every format.
+    answer: String,
+    /// Latest token counts; written as one `usage` line by `finish` (ndjson).
+    usage: Option<TokenUsage>,
+    /// Latest grounding sources; written by `finish` as a references list
+    /// (text formats) or a `citations` line (ndjson).
+    grounding: Option<Grounding>,
+    /// Hold text back until `finish`, keeping only what follows the last
+    /// function call (text and md formats).
+    only_final: bool,
+}
+
+impl<W: Write> EventFormatter<W> {
+    pub fn new(inner: W, format: OutputFormat) -> Self {
+        Self {
+            out: Utf8Writer::new(inner),
+            format,
+            dedup: None,
+            logprobs: Vec::new(),
+            answer: String::new(),
+            usage: None,
+            grounding: None,
+            only_final: false,
+        }
+    }
+
+    /// Print only the final answer once the stream ends, dropping text that
+    /// preceded a function call. Has no effect on the JSON formats.
+    pub fn with_only_final(mut self, enabled: bool) -> Self {
+        self.only_final = enabled && matches!(self.format, OutputFormat::Text | OutputFormat::Md);
+        self
+    }
+
+    /// Drop replayed answer text (see [`StreamDedup`]).
+    pub fn with_dedup(mut self, enabled: bool) -> Self {
+        self.dedup = enabled.then(StreamDedup::new);
+        self
+    }
+
+    /// The answer text received so far.
+    pub fn answer(&self) -> &str {
+        &self.answer
+    }
+
+    pub fn write_event(&mut self, event: &ChatEvent) -> io::Result<()> {
+        if let ChatEvent::Text(t) = event {
+            let t = self.dedupe(t);
+            self.answer.push_str(t);
+            if self.only_final {
+                return Ok(());
+            }
+            return self.write_text(t);
+        }
+        if self.only_final && matches!(event, ChatEvent::FunctionCall { .. }) {
+            // Whatever came before the call was intermediate.
+            self.answer.clear();
+            return Ok(());
+        }
+        match (self.format, event) {
+            (_, ChatEvent::Usage(usage)) => {
+                self.usage = Some(*usage);
+                return Ok(());
+            }
+            (_, ChatEvent::Grounding(grounding)) => {
+                self.grounding = Some(grounding.clone());
+                return Ok(());
+            }
+            (OutputFormat::Ndjson, ChatEvent::Alternate { index, text }) => {
+                self.json_line(&serde_json::json!({ "type": "text", "candidate": index, "text": text }))?;
+            }
+            (OutputFormat::Ndjson, ChatEvent::Thought(t)) => {
+                self.json_line(&serde_json::json!({ "type": "thought", "text": t }))?;
+            }
+            (OutputFormat::Ndjson, ChatEvent::Logprobs(tokens)) => {
+                self.json_line(&serde_json::json!({ "type": "logprobs", "tokens": tokens }))?;
+            }
+            (OutputFormat::Ndjson, ChatEvent::FunctionCall { name, args }) => {
+                self.json_line(&serde_json::json!({ "type": "function_call", "name": name, "args": args }))?;
+            }
+            (OutputFormat::Ndjson, ChatEvent::Finish { reason }) => {
+                self.json_line(&serde_json::json!({ "type": "finish", "reason": reason }))?;
+            }
+            (OutputFormat::Ndjson, ChatEvent::Blocked { reason }) => {
+                self.json_line(&serde_json::json!({ "type": "blocked", "reason": reason }))?;
+            }
+            (OutputFormat::Text | OutputFormat::Md, ChatEvent::Logprobs(tokens)) => {
+                self.logprobs.extend(tokens.iter().cloned());
+                return Ok(());
+            }
+            (OutputFormat::Json, ChatEvent::Raw(data)) => {
+                writeln!(self.out, "{}", data.trim())?;
+            }
+            (OutputFormat::Sse, ChatEvent::Raw(data)) => {
+                for line in data.lines() {
+                    writeln!(self.out, "data: {line}")?;
+                }
+                writeln!(self.out)?;
+            }
+            _ => return Ok(()),
+        }
+        self.out.flush()
+    }
+
+    fn write_text(&mut self, text: &str) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text | OutputFormat::Md => self.out.write_all(text.as_bytes())?,
+            OutputFormat::Ndjson if !text.is_empty() => {
+                self.json_line(&serde_json::json!({ "type": "text", "text": text }))?
+            }
+            // Raw formats carry the text inside the payloads.
+            _ => return Ok(()),
+        }
+        self.out.flush()
+    }
+
+    /// Write the end-of-stream marker for the format and flush. In text
+    /// formats, collected log probabilities are printed to stderr.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.logprobs.is_empty() {
+            print_logprobs_table(&self.logprobs, &mut io::stderr())?;
+        }
+        if self.only_final {
+            self.out.write_all(self.answer.as_bytes())?;
+        }
+        let grounding = self.grounding.take().filter(|g| !g.sources.is_empty());
+        match self.format {
+            OutputFormat::Text | OutputFormat::Md => {
+                writeln!(self.out)?;
+                if let Some(g) = &grounding {
+                    write_references(g, &mut self.out)?;
+                }
+            }
+            OutputFormat::Ndjson => {
+                if let Some(g) = &grounding {
+                    self.json_line(&citations_json(g))?;
+                }
+                if let Some(usage) = self.usage {
+                    self.json_line(&serde_json::json!({ "type": "usage", "usage": usage }))?;
+                }
+                self.json_line(&serde_json::json!({ "type": "done" }))?
+            }
+            OutputFormat::Json | OutputFormat::Sse => {}
+        }
+        self.out.finish()
+    }
+
+    fn dedupe<'a>(&mut self, text: &'a str) -> &'a str {
+        match self.dedup.as_mut() {
+            Some(d) => d.push(text),
+            None => text,
+        }
+    }
+
+    fn json_line(&mut self, value: &serde_json::Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, value)?;
+        writeln!(self.out)
+    }
+}
+
+/// The numbered sources after a grounded answer, in the order the API
+/// listed them.
+fn write_references(grounding: &Grounding, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "\nReferences:")?;
+    for (i, source) in grounding.sources.iter().enumerate() {
+        match &source.title {
+            Some(title) => writeln!(out, "[{}] {title} - {}", i + 1, source.uri)?,
+            None => writeln!(out, "[{}] {}", i + 1, source.uri)?,
+        }
+    }
+    Ok(())
+}
+
+/// The ndjson `citations` line: each cited span of the answer with its
+/// sources spelled out, so consumers don't have to resolve indices.
+fn citations_json(grounding: &Grounding) -> serde_json::Value {
+    let citations: Vec<serde_json::Value> = grounding
+        .citations
+        .iter()
+        .map(|c| {
+            let sources: Vec<_> = c.sources.iter().filter_map(|&i| grounding.sources.get(i)).collect();
+            serde_json::json!({ "start": c.start, "end": c.end, "text": c.text, "sources": sources })
+        })
+        .collect();
+    serde_json::json!({
+        "type": "citations",
+        "citations": citations,
+        "sources": grounding.sources,
+        "queries": grounding.queries,
+    })
+}
+
+/// Where `--output-file <path>` is written until the response is complete.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_else(|| "output".into());
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// One row per token: log probability, token, then the top alternatives.
+fn print_logprobs_table(tokens: &[TokenLogprob], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "{:>9}  {:<20}  top alternatives", "logprob", "token")?;
+    for t in tokens {
+        let top = t
+            .top
+            .iter()
+            .map(|a| format!("{:?} {:.3}", a.token, a.logprob))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(out, "{:>9.3}  {:<20}  {top}", t.logprob, format!("{:?}", t.token))?;
+    }
+    Ok(())
+}
+
+/// Byte writer that never emits a partial UTF-8 sequence.
+///
+/// A trailing incomplete code point is held back until a later write
+/// completes it; `finish` writes whatever remains. Invalid bytes are passed
+/// through unchanged.
+pub struct Utf8Writer<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Utf8Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Write any held-back bytes, flush, and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.pending)?;
+        self.pending.clear();
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Utf8Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let n = complete_prefix_len(&self.pending);
+        self.inner.write_all(&self.pending[..n])?;
+        self.pending.drain(..n);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Length of the prefix of `buf` that does not end in an incomplete code point.
+fn complete_prefix_len(buf: &[u8]) -> usize {
+    match std::str::from_utf8(buf) {
+        Ok(_) => buf.len(),
+        // error_len() == None means the input ended mid-sequence.
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => buf.len(),
+    }
+}
+
+/// Characters per token assumed by [`Paced::throttle`]; close enough for English text.
+pub const CHARS_PER_TOKEN: u32 = 4;
+
+/// Writes text to `inner` at a steady rate, `batch` characters at a time.
+///
+/// Writes only queue the text, so the producer (the network stream) is never
+/// slowed down; a background thread releases it, and a slow reader blocks the
+/// release rather than the stream. Dropping the writer waits until everything
+/// queued has been written.
+pub struct Paced {
+    tx: Option<std::sync::mpsc::Sender<Vec<u8>>>,
+    writer: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Paced {
+    /// `--typewriter`: one character at a time, `chars_per_sec` of them.
+    pub fn typewriter<W: Write + Send + 'static>(inner: W, chars_per_sec: u32) -> Self {
+        Self::new(inner, f64::from(chars_per_sec.max(1)), 1)
+    }
+
+    /// `--max-tokens-per-minute`: no more than `tokens_per_min`, for
+    /// downstream consumers that can't take a whole answer at once. Text is
+    /// released in batches of about a tenth of a second's worth.
+    pub fn throttle<W: Write + Send + 'static>(inner: W, tokens_per_min: u32) -> Self {
+        let chars_per_sec = f64::from(tokens_per_min.max(1)) * f64::from(CHARS_PER_TOKEN) / 60.0;
+        Self::new(inner, chars_per_sec, ((chars_per_sec / 10.0) as usize).max(1))
+    }
+
+    fn new<W: Write + Send + 'static>(mut inner: W, chars_per_sec: f64, batch: usize) -> Self {
+        let (tx, rx) = std::sync::mpsc::channel::<Vec<u8>>();
+        let writer = std::thread::spawn(move || {
+            let mut next = std::time::Instant::now();
+            for chunk in rx {
+                let text = String::from_utf8_lossy(&chunk);
+                let mut rest = text.as_ref();
+                while !rest.is_empty() {
+                    let (cut, chars) = match rest.char_indices().nth(batch) {
+                        Some((i, _)) => (i, batch),
+                        None => (rest.len(), rest.chars().count()),
+                    };
+                    if inner.write_all(&rest.as_bytes()[..cut]).and_then(|_| inner.flush()).is_err() {
+                        return;
+                    }
+                    rest = &rest[cut..];
+                    // Pace from a running deadline, but don't burst to catch
+                    // up after a stall in the stream.
+                    let now = std::time::Instant::now();
+                    next = next.max(now) + std::time::Duration::from_secs_f64(chars as f64 / chars_per_sec);
+                    std::thread::sleep(next.saturating_duration_since(now));
+                }
+            }
+        });
+        Self {
+            tx: Some(tx),
+            writer: Some(writer),
+        }
+    }
+}
+
+impl Write for Paced {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(tx) = &self.tx {
+            tx.send(buf.to_vec()).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "paced output closed"))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Paced {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain the queue and exit.
+        self.tx.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Collects all output, then hands it to a pager (`less` by default) when
+/// dropped, like git does for long output. Nothing is shown until then.
+///
+/// The command is run through `sh -c` on Unix so `PAGER="less -S"` works.
+/// `LESS=FRX` is set unless already present, so short answers are printed
+/// without waiting for `q`. If the pager can't be started the text goes to
+/// stdout instead.
+pub struct Pager {
+    command: String,
+    buf: Vec<u8>,
+}
+
+impl Pager {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            buf: Vec::new(),
+        }
+    }
+
+    fn spawn(&self) -> io::Result<std::process::Child> {
+        #[cfg(unix)]
+        let mut cmd = {
+            let mut cmd = std::process::Command::new("sh");
+            cmd.arg("-c").arg(&self.command);
+            cmd
+        };
+        #[cfg(not(unix))]
+        let mut cmd = {
+            let mut parts = self.command.split_whitespace();
+            let mut cmd = std::process::Command::new(parts.next().unwrap_or("more"));
+            cmd.args(parts);
+            cmd
+        };
+        if crate::env::less().is_none() {
+            cmd.env(crate::env::LESS, "FRX");
+        }
+        cmd.stdin(std::process::Stdio::piped()).spawn()
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        match self.spawn() {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // The user may quit before reading everything.
+                    let _ = stdin.write_all(&self.buf);
+                }
+                let _ = child.wait();
+            }
+            Err(e) => {
+                tracing::warn!("failed to start pager `{}`: {e}", self.command);
+                let mut out = io::stdout();
+                let _ = out.write_all(&self.buf).and_then(|()| out.flush());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_writer_holds_back_split_code_point() {
+        let mut w = Utf8Writer::new(Vec::new());
+        // "a😀b": the emoji is F0 9F 98 80, split after its second byte.
+        w.write_all(b"a\xF0\x9F").unwrap();
+        assert_eq!(w.inner, b"a");
+        w.write_all(b"\x98\x80b").unwrap();
+        assert_eq!(w.inner, b"a\xF0\x9F\x98\x80b");
+        assert_eq!(w.finish().unwrap(), "a😀b".as_bytes());
+    }
+
+    #[test]
+    fn utf8_writer_finish_writes_pending_bytes() {
+        let mut w = Utf8Writer::new(Vec::new());
+        w.write_all(b"x\xF0\x9F\x98").unwrap();
+        assert_eq!(w.finish().unwrap(), b"x\xF0\x9F\x98");
+    }
+
+    #[test]
+    fn complete_prefix_len_passes_invalid_bytes_through() {
+        assert_eq!(complete_prefix_len(b"ok"), 2);
+        assert_eq!(complete_prefix_len(b"ok\xF0\x9F\x98"), 2);
+        assert_eq!(complete_prefix_len(b"ok\xFF"), 3);
+    }
+}
@@ -1,11 +1,11 @@
+use crate::env;
 use anyhow::Context;
-use std::env;
 use std::path::{Path, PathBuf};
 
 fn home_dir() -> anyhow::Result<PathBuf> {
     // Minimal cross-platform-ish fallback without extra deps.
     // On Unix, HOME is standard. (Windows support can be expanded later.)
-    let home = env::var_os("HOME").context("HOME is not set")?;
+    let home = env::var_os(env::HOME).context("HOME is not set")?;
     Ok(PathBuf::from(home))
 }
 
@@ -15,8 +15,65 @@ fn ensure_dir(path: &Path) -> anyhow::Result<PathBuf> {
     Ok(path.to_path_buf())
 }
 
+/// Replace `path` with `bytes` so that readers see either the old or the new
+/// contents, never a partial write: the data goes to `<path>.tmp`, is synced
+/// to disk, then renamed over `path`. Missing parent directories are created.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    write_atomic_impl(path, bytes, false)
+}
+
+/// Like [`write_atomic`], but the file is only readable by the owner (Unix),
+/// for files that may hold secrets.
+pub fn write_private_atomic(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    write_atomic_impl(path, bytes, true)
+}
+
+fn write_atomic_impl(path: &Path, bytes: &[u8], private: bool) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    ensure_dir(dir)?;
+
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    // A stale temp file from an interrupted write would keep its old
+    // permissions, so start from a fresh one.
+    match std::fs::remove_file(&tmp) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("failed to remove stale: {}", tmp.display())),
+    }
+
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = opts.open(&tmp).with_context(|| format!("failed to write: {}", tmp.display()))?;
+    file.write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write: {}", tmp.display()))?;
+    drop(file);
+
+    std::fs::rename(&tmp, path).with_context(|| format!("failed to replace: {}", path.display()))?;
+
+    // Persist the rename itself; not all platforms allow opening a directory.
+    #[cfg(unix)]
+    if let Ok(d) = std::fs::File::open(dir) {
+        d.sync_all().ok();
+    }
+    Ok(())
+}
+
 fn gemini_home() -> Option<PathBuf> {
-    env::var_os("GEMINI_HOME").map(PathBuf::from)
+    env::var_os(env::GEMINI_HOME).map(PathBuf::from)
 }
 
 pub fn config_dir() -> anyhow::Result<PathBuf> {
@@ -24,7 +81,7 @@ pub fn config_dir() -> anyhow::Result<PathBuf> {
         return ensure_dir(&base.join("config"));
     }
 
-    if let Some(xdg) = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+    if let Some(xdg) = env::var_os(env::XDG_CONFIG_HOME).map(PathBuf::from) {
         return ensure_dir(&xdg.join("gemini"));
     }
 
@@ -36,7 +93,7 @@ pub fn state_dir() -> anyhow::Result<PathBuf> {
         return ensure_dir(&base.join("state"));
     }
 
-    if let Some(xdg) = env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+    if let Some(xdg) = env::var_os(env::XDG_STATE_HOME).map(PathBuf::from) {
         return ensure_dir(&xdg.join("gemini"));
     }
 
@@ -47,7 +104,111 @@ pub fn google_token_path() -> anyhow::Result<PathBuf> {
     Ok(state_dir()?.join("google_oauth_token.json"))
 }
 
+pub fn last_response_path() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join("last_response.json"))
+}
+
+/// Cached access token minted for a service account.
+#[cfg(feature = "google")]
+pub fn service_account_token_path() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join("google_service_account_token.json"))
+}
+
+/// gcloud's application default credentials file (written by
+/// `gcloud auth application-default login`). Not created if missing.
+#[cfg(feature = "google")]
+pub fn gcloud_adc_path() -> anyhow::Result<PathBuf> {
+    let dir = match env::var_os(env::CLOUDSDK_CONFIG) {
+        Some(dir) => PathBuf::from(dir),
+        None => home_dir()?.join(".config").join("gcloud"),
+    };
+    Ok(dir.join("application_default_credentials.json"))
+}
+
+#[cfg(feature = "google")]
+pub fn cached_contents_path() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join("cached_contents.json"))
+}
+
+/// Files uploaded for `--attach`, reused until they expire.
+#[cfg(feature = "google")]
+pub fn uploaded_files_path() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join("uploaded_files.json"))
+}
+
+/// Summaries made by `--context-summary`, one file per model and context.
+pub fn context_summaries_dir() -> anyhow::Result<PathBuf> {
+    ensure_dir(&state_dir()?.join("context_summaries"))
+}
+
+/// Conversations continued with `--session <name>`.
+pub fn sessions_dir() -> anyhow::Result<PathBuf> {
+    ensure_dir(&state_dir()?.join("sessions"))
+}
+
+/// Saved TUI conversations (`/save`, `gemini tui --resume`).
+#[cfg(feature = "tui")]
+pub fn transcripts_dir() -> anyhow::Result<PathBuf> {
+    ensure_dir(&state_dir()?.join("transcripts"))
+}
+
 #[cfg(feature = "mcp")]
 pub fn mcp_servers_path() -> anyhow::Result<PathBuf> {
     Ok(state_dir()?.join("mcp_servers.json"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh, empty directory under the system temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gemini-paths-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_atomic_replaces_stale_temp_file() {
+        let dir = scratch("stale");
+        let path = dir.join("state.json");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::write(dir.join("state.json.tmp"), "leftover from a crash, longer than the new data").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join("state.json.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_leaves_target_unchanged() {
+        let dir = scratch("fail");
+        let path = dir.join("state.json");
+        std::fs::write(&path, "old").unwrap();
+        // The temp file can't be created where a non-empty directory is.
+        std::fs::create_dir_all(dir.join("state.json.tmp").join("x")).unwrap();
+
+        assert!(write_atomic(&path, b"new").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_atomic_ignores_stale_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("private");
+        let path = dir.join("token.json");
+        let tmp = dir.join("token.json.tmp");
+        std::fs::write(&tmp, "stale").unwrap();
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private_atomic(&path, b"secret").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+/// Suppresses text that is re-sent across overlapping stream events.
+///
+/// Workaround for intermittent SSE replays where an event repeats the end of
+/// the previous one. It is deliberately conservative: only an overlap of at
+/// least `MIN_OVERLAP` bytes between the tail of the emitted text and the head
+/// of the incoming chunk is dropped, so short legitimate repeats pass through.
+#[derive(Debug, Default)]
+pub struct StreamDedup {
+    tail: String,
+}
+
+const WINDOW: usize = 1024;
+const MIN_OVERLAP: usize = 32;
+
+impl StreamDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the part of `chunk` that has not been emitted yet.
+    pub fn push<'a>(&mut self, chunk: &'a str) -> &'a str {
+        let overlap = self.overlap(chunk);
+        if overlap > 0 {
+            tracing::debug!(overlap, "suppressed replayed stream text");
+        }
+        let fresh = &chunk[overlap..];
+        self.remember(fresh);
+        fresh
+    }
+
+    /// Longest prefix of `chunk` (>= MIN_OVERLAP bytes) that the tail ends with.
+    fn overlap(&self, chunk: &str) -> usize {
+        let max = self.tail.len().min(chunk.len());
+        (MIN_OVERLAP..=max)
+            .rev()
+            .filter(|&k| chunk.is_char_boundary(k))
+            .find(|&k| self.tail.ends_with(&chunk[..k]))
+            .unwrap_or(0)
+    }
+
+    fn remember(&mut self, text: &str) {
+        self.tail.push_str(text);
+        if self.tail.len() > WINDOW {
+            let mut cut = self.tail.len() - WINDOW;
+            while !self.tail.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.tail.drain(..cut);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "The quick brown fox jumps over the lazy dog. ";
+    const SECOND: &str = "Pack my box with five dozen liquor jugs.";
+
+    #[test]
+    fn drops_repeated_prefix() {
+        let mut d = StreamDedup::new();
+        assert_eq!(d.push(FIRST), FIRST);
+        let replay = format!("{FIRST}{SECOND}");
+        assert_eq!(d.push(&replay), SECOND);
+    }
+
+    #[test]
+    fn drops_full_duplicate() {
+        let mut d = StreamDedup::new();
+        assert_eq!(d.push(FIRST), FIRST);
+        assert_eq!(d.push(FIRST), "");
+        assert_eq!(d.push(SECOND), SECOND);
+    }
+
+    #[test]
+    fn passes_chunks_without_overlap() {
+        let mut d = StreamDedup::new();
+        assert_eq!(d.push(FIRST), FIRST);
+        assert_eq!(d.push(SECOND), SECOND);
+        // Repeats shorter than MIN_OVERLAP are legitimate text.
+        assert_eq!(d.push("jugs."), "jugs.");
+    }
+
+    #[test]
+    fn overlap_respects_char_boundaries() {
+        let mut d = StreamDedup::new();
+        let text = "é".repeat(MIN_OVERLAP);
+        assert_eq!(d.push(&text), text);
+        assert_eq!(d.push(&format!("{text}!")), "!");
+    }
+}
@@ -1,20 +1,58 @@
-use super::{ChatChunk, ChatRequest, Provider};
+use super::json_stream::JsonArrayParser;
+use super::sse::{SseEvent, SseParser};
+use super::{ApiError, ChatEvent, ChatRequest, Citation, FileAttachment, GenerationConfig, Grounding, GroundingSource, ModelInfo, Provider, Role, StreamStats, TokenLogprob, TokenUsage};
 use anyhow::{anyhow, Context};
 use futures_core::stream::BoxStream;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, OnceCell};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use tracing::Instrument;
 
 #[derive(Debug, Clone)]
 pub struct GoogleProvider {
     http: reqwest::Client,
     auth: GoogleAuth,
     api_base: Url,
+    /// API version path segment for generation and model requests.
+    api_version: &'static str,
+    /// Extra headers from `[providers.google] headers`.
+    extra_headers: HeaderMap,
+    /// ListModels results, fetched once per provider.
+    models: Arc<OnceCell<Vec<ModelInfo>>>,
+    streaming_mode: StreamingMode,
 }
 
+/// Which endpoint generation requests use. Responses are decoded by their
+/// content type either way, so every mode works through any gateway that
+/// passes it through unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum StreamingMode {
+    /// `streamGenerateContent?alt=sse`: Server-Sent Events.
+    #[default]
+    Sse,
+    /// `streamGenerateContent` without `alt=sse`: a streamed JSON array.
+    Chunked,
+    /// `generateContent`: the whole response in one piece.
+    Oneshot,
+}
+
+/// Response headers that may carry the server's id for a request.
+const SERVER_REQUEST_ID_HEADERS: &[&str] = &["x-request-id", "x-goog-request-id"];
+
+/// API versions accepted by [`GoogleProvider::with_api_version`].
+pub const API_VERSIONS: &[&str] = &["v1", "v1beta", "v1alpha"];
+
+pub const DEFAULT_API_VERSION: &str = "v1beta";
+
+/// Public Generative Language API endpoint, unless `providers.google.base_url` is set.
+pub const DEFAULT_API_BASE: &str = "https://generativelanguage.googleapis.com/";
+
 #[derive(Debug, Clone)]
 pub enum GoogleAuth {
     ApiKey(String),
@@ -26,16 +64,72 @@ impl GoogleProvider {
         Ok(Self {
             http,
             auth,
-            api_base: Url::parse("https://generativelanguage.googleapis.com/")?,
+            api_base: Url::parse(DEFAULT_API_BASE)?,
+            api_version: DEFAULT_API_VERSION,
+            extra_headers: HeaderMap::new(),
+            models: Arc::default(),
+            streaming_mode: StreamingMode::default(),
         })
     }
 
-    fn build_url(&self, model: &str) -> anyhow::Result<Url> {
-        // v1beta:streamGenerateContent supports Server-Sent Events with alt=sse.
-        // Docs: https://ai.google.dev/api/rest/v1beta/models/streamGenerateContent
-        let mut url = self
-            .api_base
-            .join(&format!("v1beta/models/{model}:streamGenerateContent"))?;
+    /// Send generation requests with `mode` instead of SSE streaming.
+    pub fn with_streaming_mode(mut self, mode: StreamingMode) -> Self {
+        self.streaming_mode = mode;
+        self
+    }
+
+    /// Use API version `version` (one of [`API_VERSIONS`]) instead of `v1beta`.
+    pub fn with_api_version(mut self, version: &str) -> anyhow::Result<Self> {
+        self.api_version = API_VERSIONS
+            .iter()
+            .find(|v| **v == version)
+            .copied()
+            .with_context(|| format!("unknown API version {version:?} (expected one of: {})", API_VERSIONS.join(", ")))?;
+        Ok(self)
+    }
+
+    fn request_body(&self, req: ChatRequest) -> anyhow::Result<StreamGenerateContentRequest> {
+        // Only Vertex AI takes labels, and requests here always use the
+        // Gemini API's `models/{model}` paths, so they could never be sent.
+        if !req.labels.is_empty() {
+            anyhow::bail!(
+                "billing labels are only accepted by Vertex AI, which the google provider does not support; \
+                 remove --label and google.labels"
+            );
+        }
+        Ok(StreamGenerateContentRequest::from(req))
+    }
+
+    /// Send requests to `base` (e.g. a gateway) instead of the public endpoint.
+    pub fn with_base_url(mut self, mut base: Url) -> Self {
+        // `Url::join` replaces the last segment unless the path ends in '/'.
+        if !base.path().ends_with('/') {
+            let path = format!("{}/", base.path());
+            base.set_path(&path);
+        }
+        self.api_base = base;
+        self
+    }
+
+    /// Add these headers to every request.
+    pub fn with_headers<'a>(mut self, headers: impl IntoIterator<Item = (&'a String, &'a String)>) -> anyhow::Result<Self> {
+        for (name, value) in headers {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name: {name}"))?;
+            let value = HeaderValue::from_str(value).with_context(|| format!("invalid value for header {name}"))?;
+            self.extra_headers.insert(name, value);
+        }
+        Ok(self)
+    }
+
+    /// [`Self::endpoint`] under the configured API version.
+    fn versioned(&self, path: &str) -> anyhow::Result<Url> {
+        self.endpoint(&format!("{}/{path}", self.api_version))
+    }
+
+    /// Join `path` onto the API base, adding the API key query param when used.
+    fn endpoint(&self, path: &str) -> anyhow::Result<Url> {
+        let mut url = self.api_base.join(path)?;
 
         match &self.auth {
             GoogleAuth::ApiKey(key) => {
@@ -46,12 +140,39 @@ impl GoogleProvider {
             }
         }
 
-        url.query_pairs_mut().append_pair("alt", "sse");
+        Ok(url)
+    }
+
+    fn build_url(&self, model: &str) -> anyhow::Result<Url> {
+        // streamGenerateContent supports Server-Sent Events with alt=sse.
+        // Docs: https://ai.google.dev/api/rest/v1beta/models/streamGenerateContent
+        let method = match self.streaming_mode {
+            StreamingMode::Sse | StreamingMode::Chunked => "streamGenerateContent",
+            StreamingMode::Oneshot => "generateContent",
+        };
+        let mut url = self.versioned(&format!("models/{model}:{method}"))?;
+        if self.streaming_mode == StreamingMode::Sse {
+            url.query_pairs_mut().append_pair("alt", "sse");
+        }
+        Ok(url)
+    }
+
+    /// [`Self::build_url`] with the API key replaced, for printing.
+    fn redacted_url(&self, model: &str) -> anyhow::Result<Url> {
+        let mut url = self.build_url(model)?;
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| {
+                let v = if k == "key" { "REDACTED".to_string() } else { v.into_owned() };
+                (k.into_owned(), v)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
         Ok(url)
     }
 
     fn headers(&self) -> anyhow::Result<HeaderMap> {
-        let mut h = HeaderMap::new();
+        let mut h = self.extra_headers.clone();
         h.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
         if let GoogleAuth::BearerToken(tok) = &self.auth {
             let v = HeaderValue::from_str(&format!("Bearer {tok}"))
@@ -62,6 +183,369 @@ impl GoogleProvider {
     }
 }
 
+/// Context caching (`cachedContents`) API. Only served under `v1beta`, so
+/// these paths ignore the configured API version.
+/// Docs: https://ai.google.dev/api/caching
+impl GoogleProvider {
+    pub async fn create_cached_content(
+        &self,
+        req: &CreateCachedContent,
+    ) -> anyhow::Result<CachedContent> {
+        let mut contents = Vec::new();
+        if !req.texts.is_empty() {
+            contents.push(Content {
+                role: Some("user".to_string()),
+                parts: req.texts.iter().map(|t| Part::from_text(t.clone())).collect(),
+            });
+        }
+
+        let body = CreateCachedContentBody {
+            model: format!("models/{}", req.model),
+            display_name: req.display_name.clone(),
+            contents,
+            system_instruction: req.system.as_ref().map(|s| Content {
+                role: None,
+                parts: vec![Part::from_text(s.clone())],
+            }),
+            ttl: format!("{}s", req.ttl_secs),
+        };
+
+        let resp = self
+            .http
+            .post(self.endpoint("v1beta/cachedContents")?)
+            .headers(self.headers()?)
+            .json(&body)
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to create cached content")?;
+        read_json(resp, "create cached content").await
+    }
+
+    pub async fn list_cached_contents(&self) -> anyhow::Result<Vec<CachedContent>> {
+        let mut out = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.endpoint("v1beta/cachedContents")?;
+            if let Some(tok) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", tok);
+            }
+            let resp = self
+                .http
+                .get(url)
+                .headers(self.headers()?)
+                .send()
+                .await
+                .map_err(crate::net::hinted)
+                .context("failed to list cached contents")?;
+            let page: ListCachedContentsResponse = read_json(resp, "list cached contents").await?;
+            out.extend(page.cached_contents);
+            match page.next_page_token {
+                Some(tok) if !tok.is_empty() => page_token = Some(tok),
+                _ => return Ok(out),
+            }
+        }
+    }
+
+    pub async fn delete_cached_content(&self, name: &str) -> anyhow::Result<()> {
+        let resp = self
+            .http
+            .delete(self.endpoint(&format!("v1beta/{name}"))?)
+            .headers(self.headers()?)
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to delete cached content")?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("delete cached content failed: HTTP {status}: {text}"));
+        }
+        Ok(())
+    }
+}
+
+/// Files API, for inputs too large to send inline. Uploaded files are kept
+/// for 48 hours. Like caching, only served under `v1beta`.
+/// Docs: https://ai.google.dev/api/files
+impl GoogleProvider {
+    /// Upload `bytes` with the resumable protocol (start, then a single
+    /// upload-and-finalize request). The file may still be `PROCESSING`;
+    /// see [`Self::wait_for_file`].
+    pub async fn upload_file(&self, bytes: Vec<u8>, mime_type: &str, display_name: &str) -> anyhow::Result<UploadedFile> {
+        let mut headers = self.headers()?;
+        headers.insert("x-goog-upload-protocol", HeaderValue::from_static("resumable"));
+        headers.insert("x-goog-upload-command", HeaderValue::from_static("start"));
+        headers.insert("x-goog-upload-header-content-length", HeaderValue::from(bytes.len()));
+        headers.insert(
+            "x-goog-upload-header-content-type",
+            HeaderValue::from_str(mime_type).with_context(|| format!("invalid MIME type: {mime_type}"))?,
+        );
+        let resp = self
+            .http
+            .post(self.endpoint("upload/v1beta/files")?)
+            .headers(headers)
+            .json(&serde_json::json!({ "file": { "displayName": display_name } }))
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to start file upload")?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("start file upload failed: HTTP {status}: {text}"));
+        }
+        let upload_url = resp
+            .headers()
+            .get("x-goog-upload-url")
+            .and_then(|v| v.to_str().ok())
+            .context("file upload response has no x-goog-upload-url header")?
+            .to_string();
+
+        let mut headers = self.headers()?;
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str(mime_type)?);
+        headers.insert("x-goog-upload-offset", HeaderValue::from_static("0"));
+        headers.insert("x-goog-upload-command", HeaderValue::from_static("upload, finalize"));
+        let resp = self
+            .http
+            .post(upload_url)
+            .headers(headers)
+            .body(bytes)
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to upload file")?;
+
+        #[derive(Deserialize)]
+        struct Uploaded {
+            file: UploadedFile,
+        }
+        let uploaded: Uploaded = read_json(resp, "upload file").await?;
+        Ok(uploaded.file)
+    }
+
+    /// `name` is `files/...`.
+    pub async fn get_file(&self, name: &str) -> anyhow::Result<UploadedFile> {
+        let resp = self
+            .http
+            .get(self.endpoint(&format!("v1beta/{name}"))?)
+            .headers(self.headers()?)
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to get file")?;
+        read_json(resp, "get file").await
+    }
+
+    /// Poll until the file leaves `PROCESSING` (video can take a while).
+    /// Errors if processing failed or `timeout` passes.
+    pub async fn wait_for_file(&self, mut file: UploadedFile, timeout: std::time::Duration) -> anyhow::Result<UploadedFile> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.state.as_deref() {
+                Some("PROCESSING") => {}
+                Some("FAILED") => anyhow::bail!("processing of {} failed", file.name),
+                _ => return Ok(file),
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!("{} still processing after {}s", file.name, timeout.as_secs());
+            }
+            tokio::time::sleep(FILE_POLL_INTERVAL).await;
+            file = self.get_file(&file.name).await?;
+        }
+    }
+}
+
+const FILE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Models API.
+impl GoogleProvider {
+    /// One model's metadata (GetModel).
+    pub async fn get_model(&self, model: &str) -> anyhow::Result<ModelDetails> {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        let resp = self
+            .http
+            .get(self.versioned(&format!("models/{model}"))?)
+            .headers(self.headers()?)
+            .send()
+            .await
+            .map_err(crate::net::hinted)
+            .context("failed to get model")?;
+        read_json(resp, "get model").await
+    }
+
+    /// All models available to the caller (ListModels, all pages).
+    pub async fn list_models(&self) -> anyhow::Result<Vec<ModelInfo>> {
+        let mut out = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut url = self.versioned("models")?;
+            url.query_pairs_mut().append_pair("pageSize", "1000");
+            if let Some(tok) = &page_token {
+                url.query_pairs_mut().append_pair("pageToken", tok);
+            }
+            let resp = self
+                .http
+                .get(url)
+                .headers(self.headers()?)
+                .send()
+                .await
+                .map_err(crate::net::hinted)
+                .context("failed to list models")?;
+            let page: ListModelsResponse = read_json(resp, "list models").await?;
+            out.extend(page.models.into_iter().map(|m| ModelInfo {
+                name: m.name.strip_prefix("models/").unwrap_or(&m.name).to_string(),
+                display_name: m.display_name,
+                input_token_limit: m.input_token_limit,
+                output_token_limit: m.output_token_limit,
+            }));
+            match page.next_page_token {
+                Some(tok) if !tok.is_empty() => page_token = Some(tok),
+                _ => return Ok(out),
+            }
+        }
+    }
+}
+
+async fn read_json<T: for<'de> Deserialize<'de>>(
+    resp: reqwest::Response,
+    what: &str,
+) -> anyhow::Result<T> {
+    let status = resp.status();
+    let body = resp
+        .bytes()
+        .await
+        .with_context(|| format!("failed to read {what} response"))?;
+    if !status.is_success() {
+        return Err(anyhow!(
+            "{what} failed: HTTP {status}: {}",
+            String::from_utf8_lossy(&body)
+        ));
+    }
+    serde_json::from_slice(&body).with_context(|| format!("failed to parse {what} JSON"))
+}
+
+/// Inputs for a new cached content handle.
+#[derive(Debug, Clone)]
+pub struct CreateCachedContent {
+    pub model: String,
+    pub display_name: Option<String>,
+    pub system: Option<String>,
+    /// Each entry becomes one text part of a single user turn.
+    pub texts: Vec<String>,
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateCachedContentBody {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    ttl: String,
+}
+
+/// A file stored by the Files API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    /// `files/...`
+    pub name: String,
+    /// What requests reference the file by.
+    pub uri: String,
+    pub mime_type: String,
+    /// `PROCESSING`, `ACTIVE` or `FAILED`.
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub expiration_time: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContent {
+    pub name: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub expire_time: Option<String>,
+    #[serde(default)]
+    pub usage_metadata: Option<CachedContentUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContentUsage {
+    #[serde(default)]
+    pub total_token_count: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListCachedContentsResponse {
+    #[serde(default)]
+    cached_contents: Vec<CachedContent>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListModelsResponse {
+    #[serde(default)]
+    models: Vec<ApiModel>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+/// A model as returned by GetModel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelDetails {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_model_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_token_limit: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_token_limit: Option<u64>,
+    #[serde(default)]
+    pub supported_generation_methods: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiModel {
+    name: String,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    input_token_limit: Option<u64>,
+    #[serde(default)]
+    output_token_limit: Option<u64>,
+}
+
 impl Provider for GoogleProvider {
     fn name(&self) -> &'static str {
         "google"
@@ -72,7 +556,7 @@ impl Provider for GoogleProvider {
         req: ChatRequest,
     ) -> std::pin::Pin<
         Box<
-            dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatChunk>>>>
+            dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatEvent>>>>
                 + Send,
         >,
     > {
@@ -83,34 +567,73 @@ impl Provider for GoogleProvider {
             let url = this.build_url(&req.model)?;
             let headers = this.headers()?;
 
-            let body = StreamGenerateContentRequest {
-                contents: vec![Content {
-                    role: Some("user".to_string()),
-                    parts: vec![Part { text: Some(req.prompt) }],
-                }],
-            };
+            let raw_events = req.raw_events;
+            let span = tracing::debug_span!("stream", request_id = req.request_id.as_deref().unwrap_or("-"));
+            let body = this.request_body(req)?;
 
+            let started = Instant::now();
             let resp = http
                 .post(url)
                 .headers(headers)
                 .json(&body)
                 .send()
                 .await
+                .map_err(crate::net::hinted)
                 .context("failed to start Gemini request")?;
 
+            let server_request_id = SERVER_REQUEST_ID_HEADERS
+                .iter()
+                .find_map(|h| resp.headers().get(*h)?.to_str().ok())
+                .map(str::to_string);
+            if let Some(id) = &server_request_id {
+                span.in_scope(|| tracing::debug!(server_request_id = %id, "response received"));
+            }
+
             let status = resp.status();
             if !status.is_success() {
-                let text = resp.text().await.unwrap_or_default();
-                return Err(anyhow!("Gemini API error: HTTP {status}: {text}"));
+                let headers = resp.headers().clone();
+                let body = resp.text().await.unwrap_or_default();
+                let context = match &server_request_id {
+                    Some(id) => format!("Gemini API error (server request id {id})"),
+                    None => "Gemini API error".to_string(),
+                };
+                return Err(anyhow::Error::new(ApiError::new(status.as_u16(), body, &headers)).context(context));
             }
 
-            let (tx, rx) = mpsc::channel::<anyhow::Result<ChatChunk>>(64);
+            // `alt=sse` is requested, but gateways may rewrite the response
+            // into the plain JSON-array stream; decode whichever arrived.
+            let sse = resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.trim_start().starts_with("text/event-stream"));
+            if !sse {
+                tracing::debug!("response is not text/event-stream; parsing as a JSON array stream");
+            }
+
+            let (tx, rx) = mpsc::channel::<anyhow::Result<ChatEvent>>(64);
 
             tokio::spawn(async move {
                 let mut stream = resp.bytes_stream();
-                let mut parser = SseParser::new();
+                let mut sse_parser = SseParser::new();
+                let mut json_parser = JsonArrayParser::new();
+                let mut stats = StreamStats::default();
 
-                while let Some(item) = stream.next().await {
+                loop {
+                    // Stop as soon as the consumer drops the stream instead of
+                    // draining the body; dropping `stream` releases the connection.
+                    let item = tokio::select! {
+                        _ = tx.closed() => {
+                            tracing::debug!("stream dropped by consumer; aborting read");
+                            return;
+                        }
+                        item = stream.next() => item,
+                    };
+                    let Some(item) = item else {
+                        stats.duration = started.elapsed();
+                        let _ = tx.send(Ok(ChatEvent::Stats(stats))).await;
+                        break;
+                    };
                     let bytes = match item {
                         Ok(b) => b,
                         Err(e) => {
@@ -118,45 +641,88 @@ impl Provider for GoogleProvider {
                             return;
                         }
                     };
+                    if stats.chunks == 0 {
+                        stats.time_to_first_byte = started.elapsed();
+                    }
+                    stats.chunks += 1;
+                    stats.bytes += bytes.len() as u64;
 
-                    for ev in parser.push(&bytes) {
-                        match ev {
-                            Ok(SseEvent::Data(data)) => {
-                                // Some events are "[DONE]" in other APIs; Gemini uses JSON always.
-                                if data.trim().is_empty() {
-                                    continue;
-                                }
-
-                                let parsed: Result<StreamGenerateContentResponse, _> =
-                                    serde_json::from_str(&data);
-                                match parsed {
-                                    Ok(r) => {
-                                        if let Some(text) = extract_text(&r) {
-                                            if tx.send(Ok(ChatChunk { text })).await.is_err() {
-                                                return;
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        let _ = tx
-                                            .send(Err(anyhow!(e).context("failed to parse SSE JSON")))
-                                            .await;
-                                        return;
-                                    }
-                                }
-                            }
-                            Ok(SseEvent::Other) => {}
-                            Err(e) => {
-                                let _ = tx.send(Err(e)).await;
-                                return;
-                            }
+                    let payloads = if sse {
+                        sse_payloads(sse_parser.push(&bytes))
+                    } else {
+                        json_parser.push(&bytes).into_iter().collect()
+                    };
+                    let payloads = match payloads {
+                        Ok(p) => p,
+                        Err(e) => {
+                            let _ = tx.send(Err(e)).await;
+                            return;
+                        }
+                    };
+                    for data in payloads {
+                        if !forward_payload(&tx, data, raw_events).await {
+                            return;
                         }
                     }
                 }
-            });
+            }
+            .instrument(span));
 
             let out = ReceiverStream::new(rx).map(|x| x);
-            Ok(Box::pin(out) as BoxStream<'static, anyhow::Result<ChatChunk>>)
+            Ok(Box::pin(out) as BoxStream<'static, anyhow::Result<ChatEvent>>)
+        })
+    }
+
+    /// Uses `generateContent` whatever the streaming mode, so the answer
+    /// arrives in one response.
+    fn generate(&self, req: ChatRequest) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send>> {
+        let started = self.clone().with_streaming_mode(StreamingMode::Oneshot).stream_chat(req);
+        Box::pin(async move { super::types::collect_text(started.await?).await })
+    }
+
+    fn describe_request(&self, req: &ChatRequest) -> anyhow::Result<serde_json::Value> {
+        let url = self.redacted_url(&req.model)?;
+        Ok(serde_json::json!({
+            "method": "POST",
+            "url": url.as_str(),
+            "body": self.request_body(req.clone())?,
+        }))
+    }
+
+    fn request_url(&self, model: &str) -> anyhow::Result<Option<Url>> {
+        self.redacted_url(model).map(Some)
+    }
+
+    fn models(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Vec<ModelInfo>>> + Send>> {
+        let this = self.clone();
+        Box::pin(async move { Ok(this.models.get_or_try_init(|| this.list_models()).await?.clone()) })
+    }
+
+    fn model_info(
+        &self,
+        model: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Option<ModelInfo>>> + Send>> {
+        let this = self.clone();
+        let model = model.strip_prefix("models/").unwrap_or(model).to_string();
+        Box::pin(async move {
+            let models = this.models.get_or_try_init(|| this.list_models()).await?;
+            Ok(models.iter().find(|m| m.name == model).cloned())
+        })
+    }
+
+    fn keep_warm(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<()>> + Send>> {
+        let this = self.clone();
+        Box::pin(async move {
+            // HEAD on the models collection: no generation, just a round trip
+            // on the pooled connection. The status is irrelevant.
+            let url = this.versioned("models")?;
+            this.http
+                .head(url)
+                .headers(this.headers()?)
+                .send()
+                .await
+                .context("keep-warm request failed")?;
+            Ok(())
         })
     }
 }
@@ -164,124 +730,454 @@ impl Provider for GoogleProvider {
 #[derive(Debug, Clone, Serialize)]
 struct StreamGenerateContentRequest {
     contents: Vec<Content>,
+    #[serde(rename = "systemInstruction", skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    #[serde(rename = "cachedContent", skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "GenerationConfig::is_empty")]
+    generation_config: GenerationConfig,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+}
+
+impl From<ChatRequest> for StreamGenerateContentRequest {
+    fn from(req: ChatRequest) -> Self {
+        // Gemini has no system role in `contents`; system messages join the
+        // system instruction, after `req.system`.
+        let mut system: Vec<Part> = req.system.into_iter().map(Part::from_text).collect();
+        let mut contents: Vec<Content> = Vec::new();
+        for m in req.history {
+            let role = match m.role {
+                Role::User => "user",
+                Role::Model => "model",
+                Role::System => {
+                    system.push(Part::from_text(m.text));
+                    continue;
+                }
+            };
+            contents.push(Content {
+                role: Some(role.to_string()),
+                parts: vec![Part::from_text(m.text)],
+            });
+        }
+        let mut parts: Vec<Part> = req.attachments.into_iter().map(Part::from_file).collect();
+        parts.push(Part::from_text(req.prompt));
+        contents.push(Content {
+            role: Some("user".to_string()),
+            parts,
+        });
+        Self {
+            contents,
+            system_instruction: (!system.is_empty()).then_some(Content {
+                role: None,
+                parts: system,
+            }),
+            cached_content: req.cached_content,
+            generation_config: req.generation,
+            tools: if req.search {
+                vec![serde_json::json!({ "googleSearch": {} })]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// Check labels against Google Cloud's rules: at most 64; keys of 1-63 and
+/// values of up to 63 lowercase letters, digits, `_` or `-`; keys start with
+/// a letter.
+pub fn validate_labels(labels: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    if labels.len() > 64 {
+        anyhow::bail!("too many labels ({}; at most 64)", labels.len());
+    }
+    let allowed = |s: &str| s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    for (key, value) in labels {
+        if key.is_empty() || key.len() > 63 || !allowed(key) || !key.starts_with(|c: char| c.is_ascii_lowercase()) {
+            anyhow::bail!(
+                "invalid label key {key:?}: use 1-63 lowercase letters, digits, '_' or '-', starting with a letter"
+            );
+        }
+        if value.len() > 63 || !allowed(value) {
+            anyhow::bail!("invalid value for label {key}: {value:?} (use up to 63 lowercase letters, digits, '_' or '-')");
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct StreamGenerateContentResponse {
     #[serde(default)]
     candidates: Vec<Candidate>,
+    #[serde(default, rename = "promptFeedback")]
+    prompt_feedback: Option<PromptFeedback>,
+    #[serde(default, rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    #[serde(default)]
+    total_token_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PromptFeedback {
+    #[serde(default, rename = "blockReason")]
+    block_reason: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Candidate {
+    #[serde(default)]
+    index: u32,
     #[serde(default)]
     content: Option<Content>,
+    #[serde(default, rename = "logprobsResult")]
+    logprobs_result: Option<LogprobsResult>,
+    #[serde(default, rename = "finishReason")]
+    finish_reason: Option<String>,
+    #[serde(default, rename = "groundingMetadata")]
+    grounding_metadata: Option<GroundingMetadata>,
 }
 
+/// Every field may be missing, depending on the model and on whether the
+/// search found anything.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Content {
+#[serde(rename_all = "camelCase")]
+struct GroundingMetadata {
     #[serde(default)]
-    role: Option<String>,
+    grounding_chunks: Vec<GroundingChunk>,
     #[serde(default)]
-    parts: Vec<Part>,
+    grounding_supports: Vec<GroundingSupport>,
+    #[serde(default)]
+    web_search_queries: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Part {
+struct GroundingChunk {
     #[serde(default)]
-    text: Option<String>,
+    web: Option<WebChunk>,
 }
 
-fn extract_text(r: &StreamGenerateContentResponse) -> Option<String> {
-    // Concatenate all text parts of the first candidate.
-    let cand = r.candidates.first()?;
-    let content = cand.content.as_ref()?;
-    let mut out = String::new();
-    for p in &content.parts {
-        if let Some(t) = &p.text {
-            out.push_str(t);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WebChunk {
+    #[serde(default)]
+    uri: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroundingSupport {
+    #[serde(default)]
+    segment: Option<Segment>,
+    #[serde(default)]
+    grounding_chunk_indices: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Segment {
+    #[serde(default)]
+    start_index: usize,
+    #[serde(default)]
+    end_index: usize,
+    #[serde(default)]
+    text: String,
+}
+
+impl GroundingMetadata {
+    /// Web sources only, renumbered to skip chunks without a URI; supports
+    /// left with no source or no span are dropped.
+    fn to_grounding(&self) -> Grounding {
+        let mut sources = Vec::new();
+        let index: Vec<Option<usize>> = self
+            .grounding_chunks
+            .iter()
+            .map(|c| {
+                let web = c.web.as_ref()?;
+                sources.push(GroundingSource {
+                    uri: web.uri.clone()?,
+                    title: web.title.clone(),
+                });
+                Some(sources.len() - 1)
+            })
+            .collect();
+        let citations = self
+            .grounding_supports
+            .iter()
+            .filter_map(|s| {
+                let segment = s.segment.as_ref().filter(|seg| seg.end_index > seg.start_index)?;
+                let cited: Vec<usize> = s
+                    .grounding_chunk_indices
+                    .iter()
+                    .filter_map(|&i| index.get(i).copied().flatten())
+                    .collect();
+                (!cited.is_empty()).then(|| Citation {
+                    start: segment.start_index,
+                    end: segment.end_index,
+                    text: segment.text.clone(),
+                    sources: cited,
+                })
+            })
+            .collect();
+        Grounding {
+            sources,
+            citations,
+            queries: self.web_search_queries.clone(),
         }
     }
-    if out.is_empty() { None } else { Some(out) }
 }
 
-#[derive(Debug, Clone)]
-enum SseEvent {
-    Data(String),
-    Other,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LogprobsResult {
+    /// Alternatives per position, aligned with `chosenCandidates`.
+    #[serde(default, rename = "topCandidates")]
+    top_candidates: Vec<TopCandidates>,
+    #[serde(default, rename = "chosenCandidates")]
+    chosen_candidates: Vec<LogprobCandidate>,
 }
 
-/// Minimal SSE parser.
-///
-/// - Collects UTF-8 lines
-/// - Emits Data events when a blank line ends an event
-struct SseParser {
-    buf: Vec<u8>,
-    cur_data: String,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TopCandidates {
+    #[serde(default)]
+    candidates: Vec<LogprobCandidate>,
 }
 
-impl SseParser {
-    fn new() -> Self {
-        Self {
-            buf: Vec::new(),
-            cur_data: String::new(),
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LogprobCandidate {
+    #[serde(default)]
+    token: String,
+    #[serde(default, rename = "logProbability")]
+    log_probability: f32,
+}
+
+impl LogprobCandidate {
+    fn to_token(&self) -> TokenLogprob {
+        TokenLogprob {
+            token: self.token.clone(),
+            logprob: self.log_probability,
+            top: Vec::new(),
         }
     }
+}
 
-    fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<SseEvent>> {
-        self.buf.extend_from_slice(chunk);
-        let mut out = Vec::new();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Content {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
 
-        loop {
-            let Some(pos) = memchr::memchr(b'\n', &self.buf) else {
-                break;
-            };
-            let mut line = self.buf.drain(..=pos).collect::<Vec<u8>>();
-            if line.ends_with(&[b'\n']) {
-                line.pop();
-            }
-            if line.ends_with(&[b'\r']) {
-                line.pop();
-            }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Part {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// Set on reasoning parts when `includeThoughts` was requested.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    thought: bool,
+    #[serde(default, rename = "functionCall", skip_serializing_if = "Option::is_none")]
+    function_call: Option<FunctionCall>,
+    #[serde(default, rename = "fileData", skip_serializing_if = "Option::is_none")]
+    file_data: Option<FileData>,
+}
 
-            if line.is_empty() {
-                if !self.cur_data.is_empty() {
-                    // Remove trailing newline from data field accumulation.
-                    if self.cur_data.ends_with('\n') {
-                        self.cur_data.pop();
-                    }
-                    let data = std::mem::take(&mut self.cur_data);
-                    out.push(Ok(SseEvent::Data(data)));
-                }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileData {
+    mime_type: String,
+    file_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+impl Part {
+    fn from_text(text: String) -> Self {
+        Self {
+            text: Some(text),
+            thought: false,
+            function_call: None,
+            file_data: None,
+        }
+    }
+
+    fn from_file(file: FileAttachment) -> Self {
+        Self {
+            text: None,
+            thought: false,
+            function_call: None,
+            file_data: Some(FileData {
+                mime_type: file.mime_type,
+                file_uri: file.uri,
+            }),
+        }
+    }
+}
+
+/// The JSON payloads among parsed SSE events. An `error` event ends the
+/// stream with an error; other named events are ignored.
+fn sse_payloads(events: Vec<anyhow::Result<SseEvent>>) -> anyhow::Result<Vec<String>> {
+    let mut out = Vec::new();
+    for ev in events {
+        let SseEvent::Data { event, data } = ev?;
+        match event.as_deref() {
+            None | Some("message") => {}
+            Some("error") => return Err(anyhow!("Gemini stream error event: {data}")),
+            Some(other) => {
+                tracing::debug!(event = other, "ignoring named SSE event");
                 continue;
             }
+        }
+        // Some events are "[DONE]" in other APIs; Gemini uses JSON always.
+        if !data.trim().is_empty() {
+            out.push(data);
+        }
+    }
+    Ok(out)
+}
 
-            let s = match std::str::from_utf8(&line) {
-                Ok(s) => s,
-                Err(e) => {
-                    out.push(Err(anyhow!(e).context("SSE line is not valid UTF-8")));
-                    continue;
+/// Decode one response payload and send its events. Returns `false` once
+/// the stream should stop (consumer gone or undecodable payload).
+async fn forward_payload(tx: &mpsc::Sender<anyhow::Result<ChatEvent>>, data: String, raw_events: bool) -> bool {
+    let parsed: Result<StreamGenerateContentResponse, _> = serde_json::from_str(&data);
+    if raw_events && tx.send(Ok(ChatEvent::Raw(data))).await.is_err() {
+        return false;
+    }
+    match parsed {
+        Ok(r) => {
+            for event in extract_events(&r) {
+                if tx.send(Ok(event)).await.is_err() {
+                    return false;
                 }
-            };
-
-            if let Some(rest) = s.strip_prefix("data:") {
-                // Spec allows optional leading space.
-                let rest = rest.strip_prefix(' ').unwrap_or(rest);
-                self.cur_data.push_str(rest);
-                self.cur_data.push('\n');
-            } else {
-                // Ignore other fields: event:, id:, retry:, comments
-                out.push(Ok(SseEvent::Other));
             }
+            true
+        }
+        Err(e) => {
+            let _ = tx.send(Err(anyhow!(e).context("failed to parse stream JSON"))).await;
+            false
+        }
+    }
+}
+
+fn extract_events(r: &StreamGenerateContentResponse) -> Vec<ChatEvent> {
+    // Text parts of the first candidate, with adjacent parts of the same kind
+    // merged, then its log probabilities and finish reason if any. Other
+    // candidates only contribute their answer text.
+    let mut out: Vec<ChatEvent> = Vec::new();
+    if let Some(reason) = r.prompt_feedback.as_ref().and_then(|f| f.block_reason.clone()) {
+        out.push(ChatEvent::Blocked { reason });
+    }
+    if let Some(u) = &r.usage_metadata {
+        out.push(ChatEvent::Usage(TokenUsage {
+            prompt_tokens: u.prompt_token_count,
+            output_tokens: u.candidates_token_count,
+            total_tokens: u.total_token_count,
+        }));
+    }
+    for alt in r.candidates.iter().filter(|c| c.index > 0) {
+        let text: String = alt
+            .content
+            .iter()
+            .flat_map(|c| &c.parts)
+            .filter(|p| !p.thought)
+            .filter_map(|p| p.text.as_deref())
+            .collect();
+        if !text.is_empty() {
+            out.push(ChatEvent::Alternate { index: alt.index, text });
         }
+    }
+    let Some(cand) = r.candidates.iter().find(|c| c.index == 0) else {
+        return out;
+    };
+    let parts = cand.content.as_ref().map(|c| c.parts.as_slice()).unwrap_or_default();
+    for p in parts {
+        if let Some(call) = &p.function_call {
+            out.push(ChatEvent::FunctionCall {
+                name: call.name.clone(),
+                args: call.args.clone(),
+            });
+        }
+        let Some(t) = p.text.as_deref().filter(|t| !t.is_empty()) else {
+            continue;
+        };
+        match (out.last_mut(), p.thought) {
+            (Some(ChatEvent::Thought(prev)), true) | (Some(ChatEvent::Text(prev)), false) => prev.push_str(t),
+            (_, true) => out.push(ChatEvent::Thought(t.to_string())),
+            (_, false) => out.push(ChatEvent::Text(t.to_string())),
+        }
+    }
 
-        out
+    if let Some(lp) = cand.logprobs_result.as_ref().filter(|lp| !lp.chosen_candidates.is_empty()) {
+        let tokens = lp
+            .chosen_candidates
+            .iter()
+            .enumerate()
+            .map(|(i, chosen)| TokenLogprob {
+                top: lp
+                    .top_candidates
+                    .get(i)
+                    .map(|t| t.candidates.iter().map(LogprobCandidate::to_token).collect())
+                    .unwrap_or_default(),
+                ..chosen.to_token()
+            })
+            .collect();
+        out.push(ChatEvent::Logprobs(tokens));
+    }
+    if let Some(grounding) = &cand.grounding_metadata {
+        out.push(ChatEvent::Grounding(grounding.to_grounding()));
     }
+    if let Some(reason) = cand.finish_reason.clone().filter(|r| r != "FINISH_REASON_UNSPECIFIED") {
+        out.push(ChatEvent::Finish { reason });
+    }
+    out
 }
 
-// memchr is tiny and speeds up newline search; keep it internal to this module.
-mod memchr {
-    pub fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
-        haystack.iter().position(|&b| b == needle)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> GoogleProvider {
+        GoogleProvider::new(reqwest::Client::new(), GoogleAuth::ApiKey("key".into())).unwrap()
+    }
+
+    #[test]
+    fn labels_fail_the_request() {
+        let mut req = ChatRequest {
+            model: "gemini-1.5-flash".into(),
+            prompt: "hi".into(),
+            ..Default::default()
+        };
+        assert!(provider().describe_request(&req).is_ok());
+
+        req.labels.insert("team".into(), "search".into());
+        let err = provider().describe_request(&req).unwrap_err();
+        assert!(err.to_string().contains("Vertex AI"), "{err}");
+    }
+
+    #[test]
+    fn sse_payloads_follow_event_types() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"data: {\"a\":1}\n\nevent: message\ndata: {\"b\":2}\n\nevent: ping\ndata: {}\n\n");
+        assert_eq!(sse_payloads(events).unwrap(), [r#"{"a":1}"#, r#"{"b":2}"#]);
+    }
+
+    #[test]
+    fn sse_error_event_ends_stream() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"data: {\"a\":1}\n\nevent: error\ndata: {\"code\":503}\n\n");
+        let err = sse_payloads(events).unwrap_err();
+        assert!(err.to_string().contains(r#"{"code":503}"#), "{err}");
     }
 }
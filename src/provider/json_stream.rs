@@ -0,0 +1,148 @@
+/// Incremental parser for a streamed JSON array of objects, as returned by
+/// `streamGenerateContent` without `alt=sse` (or by gateways that drop the
+/// SSE framing).
+///
+/// - Emits each top-level element as soon as its closing brace arrives
+/// - Commas, whitespace and the enclosing `[`/`]` are skipped, so
+///   concatenated or newline-delimited objects are accepted too
+/// - Strings are tracked so braces inside them don't count
+pub struct JsonArrayParser {
+    buf: Vec<u8>,
+    /// Next byte of `buf` to scan.
+    pos: usize,
+    /// Start of the element being read, if inside one.
+    start: Option<usize>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonArrayParser {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            pos: 0,
+            start: None,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<String>> {
+        self.buf.extend_from_slice(chunk);
+        let mut out = Vec::new();
+
+        while self.pos < self.buf.len() {
+            let b = self.buf[self.pos];
+            self.pos += 1;
+
+            if self.in_string {
+                match (self.escaped, b) {
+                    (true, _) => self.escaped = false,
+                    (false, b'\\') => self.escaped = true,
+                    (false, b'"') => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match b {
+                b'"' if self.depth > 0 => self.in_string = true,
+                b'{' | b'[' if self.depth > 0 => self.depth += 1,
+                b'}' | b']' if self.depth > 0 => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        let start = self.start.take().unwrap_or(0);
+                        let element = String::from_utf8(self.buf[start..self.pos].to_vec())
+                            .map_err(|_| anyhow::anyhow!("invalid UTF-8 in JSON stream"));
+                        out.push(element);
+                    }
+                }
+                _ if self.depth > 0 => {}
+                b'{' => {
+                    self.start = Some(self.pos - 1);
+                    self.depth = 1;
+                }
+                b'[' | b']' | b',' => {}
+                b if b.is_ascii_whitespace() => {}
+                other => {
+                    out.push(Err(anyhow::anyhow!(
+                        "unexpected {:?} in JSON stream (expected an array of objects)",
+                        other as char
+                    )));
+                    self.buf.clear();
+                    self.pos = 0;
+                    return out;
+                }
+            }
+        }
+
+        // Drop everything before the element in progress.
+        let keep = self.start.unwrap_or(self.pos);
+        self.buf.drain(..keep);
+        self.pos -= keep;
+        if let Some(start) = self.start.as_mut() {
+            *start = 0;
+        }
+        out
+    }
+}
+
+impl Default for JsonArrayParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(parser: &mut JsonArrayParser, chunk: &str) -> Vec<String> {
+        parser.push(chunk.as_bytes()).into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn object_split_across_chunks() {
+        let mut p = JsonArrayParser::new();
+        assert!(elements(&mut p, "[{\"a\": {\"b\"").is_empty());
+        assert!(elements(&mut p, ": [1, 2]").is_empty());
+        assert_eq!(elements(&mut p, "}}\n,{\"c\":3}"), [r#"{"a": {"b": [1, 2]}}"#, r#"{"c":3}"#]);
+        assert!(elements(&mut p, "]").is_empty());
+    }
+
+    #[test]
+    fn brackets_inside_strings_are_ignored() {
+        let mut p = JsonArrayParser::new();
+        let text = r#"[{"text": "} ] { [ not structure"}]"#;
+        assert_eq!(elements(&mut p, text), [r#"{"text": "} ] { [ not structure"}"#]);
+    }
+
+    #[test]
+    fn escaped_quotes_stay_in_string() {
+        let mut p = JsonArrayParser::new();
+        // Split right after the backslash, so the escape spans chunks.
+        assert!(elements(&mut p, r#"[{"text": "say \"}\"\"#).is_empty());
+        assert_eq!(elements(&mut p, r#"" and \\"}]"#), [r#"{"text": "say \"}\"\" and \\"}"#]);
+    }
+
+    #[test]
+    fn concatenated_and_newline_delimited_objects() {
+        let mut p = JsonArrayParser::new();
+        assert_eq!(elements(&mut p, "{\"a\":1}{\"b\":2}\n{\"c\":3}\n"), [r#"{"a":1}"#, r#"{"b":2}"#, r#"{"c":3}"#]);
+    }
+
+    #[test]
+    fn non_object_element_is_an_error() {
+        let mut p = JsonArrayParser::new();
+        let out = p.push(b"[{\"a\":1}, 2, {\"b\":2}]");
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].as_ref().unwrap(), r#"{"a":1}"#);
+        let err = out[1].as_ref().unwrap_err().to_string();
+        assert!(err.contains("unexpected '2'"), "{err}");
+
+        let mut p = JsonArrayParser::new();
+        assert!(p.push(b"[\"text\"]")[0].is_err());
+    }
+}
@@ -1,6 +1,10 @@
+pub mod dedup;
 pub mod stub;
 #[cfg(feature = "google")]
 pub mod google;
+pub mod json_stream;
+pub mod sse;
+pub mod transform;
 mod types;
 
-pub use types::{ChatChunk, ChatRequest, Provider};
+pub use types::{ApiError, ChatEvent, ChatMessage, ChatRequest, Citation, FileAttachment, GenerationConfig, Grounding, GroundingSource, ModelInfo, Provider, Role, StreamStats, ThinkingConfig, TokenLogprob, TokenUsage};
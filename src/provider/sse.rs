@@ -0,0 +1,141 @@
+use anyhow::anyhow;
+
+/// A dispatched Server-Sent Event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SseEvent {
+    /// `event` is the `event:` field, if one preceded the data.
+    Data { event: Option<String>, data: String },
+}
+
+/// Minimal SSE parser.
+///
+/// - Collects UTF-8 lines
+/// - Tracks `event:` and `data:` fields; `id:`, `retry:` and comments are ignored
+/// - Emits a Data event when a blank line ends an event that carried data
+pub struct SseParser {
+    buf: Vec<u8>,
+    cur_event: Option<String>,
+    cur_data: String,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            cur_event: None,
+            cur_data: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<anyhow::Result<SseEvent>> {
+        self.buf.extend_from_slice(chunk);
+        let mut out = Vec::new();
+
+        loop {
+            let Some(pos) = memchr::memchr(b'\n', &self.buf) else {
+                break;
+            };
+            let mut line = self.buf.drain(..=pos).collect::<Vec<u8>>();
+            if line.ends_with(&[b'\n']) {
+                line.pop();
+            }
+            if line.ends_with(&[b'\r']) {
+                line.pop();
+            }
+
+            if line.is_empty() {
+                // Blank line: dispatch. An event type without data is dropped.
+                let event = self.cur_event.take();
+                if !self.cur_data.is_empty() {
+                    // Remove trailing newline from data field accumulation.
+                    if self.cur_data.ends_with('\n') {
+                        self.cur_data.pop();
+                    }
+                    let data = std::mem::take(&mut self.cur_data);
+                    out.push(Ok(SseEvent::Data { event, data }));
+                }
+                continue;
+            }
+
+            let s = match std::str::from_utf8(&line) {
+                Ok(s) => s,
+                Err(e) => {
+                    out.push(Err(anyhow!(e).context("SSE line is not valid UTF-8")));
+                    continue;
+                }
+            };
+
+            // Spec allows one optional leading space after the colon.
+            if let Some(rest) = s.strip_prefix("data:") {
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                self.cur_data.push_str(rest);
+                self.cur_data.push('\n');
+            } else if let Some(rest) = s.strip_prefix("event:") {
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                self.cur_event = Some(rest.to_string());
+            }
+            // Ignore other fields: id:, retry:, comments
+        }
+
+        out
+    }
+}
+
+impl Default for SseParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// memchr is tiny and speeds up newline search; keep it internal to this module.
+mod memchr {
+    pub fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+        haystack.iter().position(|&b| b == needle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(event: Option<&str>, data: &str) -> SseEvent {
+        SseEvent::Data {
+            event: event.map(String::from),
+            data: data.to_string(),
+        }
+    }
+
+    fn events(parser: &mut SseParser, chunk: &str) -> Vec<SseEvent> {
+        parser.push(chunk.as_bytes()).into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn event_field_applies_to_next_data_and_resets() {
+        let mut p = SseParser::new();
+        let out = events(&mut p, "event: error\ndata: {\"a\":1}\n\ndata: {\"b\":2}\n\n");
+        assert_eq!(out, [data(Some("error"), "{\"a\":1}"), data(None, "{\"b\":2}")]);
+    }
+
+    #[test]
+    fn event_without_data_is_dropped() {
+        let mut p = SseParser::new();
+        let out = events(&mut p, "event: ping\n\ndata: x\n\n");
+        // The dropped event's type doesn't leak into the next one.
+        assert_eq!(out, [data(None, "x")]);
+    }
+
+    #[test]
+    fn multi_line_data_is_joined() {
+        let mut p = SseParser::new();
+        let out = events(&mut p, "data: one\r\ndata:two\r\n: comment\r\nid: 7\r\n\r\n");
+        assert_eq!(out, [data(None, "one\ntwo")]);
+    }
+
+    #[test]
+    fn lines_split_across_chunks() {
+        let mut p = SseParser::new();
+        assert!(events(&mut p, "event: mess").is_empty());
+        assert!(events(&mut p, "age\ndata: hel").is_empty());
+        assert_eq!(events(&mut p, "lo\n\n"), [data(Some("message"), "hello")]);
+    }
+}
@@ -1,15 +1,88 @@
-use super::{ChatChunk, ChatRequest, Provider};
+use super::{ChatEvent, ChatRequest, Provider, TokenUsage};
 use futures_core::stream::BoxStream;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 
 #[derive(Debug, Default, Clone)]
-pub struct StubProvider;
+pub struct StubProvider {
+    simulation: Option<Arc<Simulation>>,
+}
+
+/// How [`StubProvider::simulate`] streams its text.
+#[derive(Debug, Clone)]
+pub struct SimulateOptions {
+    /// Pause before each word.
+    pub delay: Duration,
+    /// Fail the stream with an error after this many words.
+    pub fail_after: Option<usize>,
+    /// Sent as [`ChatEvent::Finish`] once all words are out; `None` ends the
+    /// stream without one.
+    pub finish_reason: Option<String>,
+}
+
+impl Default for SimulateOptions {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(40),
+            fail_after: None,
+            finish_reason: Some("STOP".to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Simulation {
+    text: String,
+    opts: SimulateOptions,
+}
 
 impl StubProvider {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// A stub that answers every request with `text`, streamed word by word
+    /// like a real model, for exercising output paths (spinners,
+    /// cancellation, partial output) without network access.
+    pub fn simulate(text: impl Into<String>, opts: SimulateOptions) -> Self {
+        Self {
+            simulation: Some(Arc::new(Simulation {
+                text: text.into(),
+                opts,
+            })),
+        }
+    }
+}
+
+impl Simulation {
+    async fn run(&self, tx: mpsc::Sender<anyhow::Result<ChatEvent>>) {
+        let mut words = 0;
+        for word in self.text.split_inclusive(char::is_whitespace) {
+            if self.opts.fail_after == Some(words) {
+                let _ = tx.send(Err(anyhow::anyhow!("simulated stream error after {words} words"))).await;
+                return;
+            }
+            tokio::time::sleep(self.opts.delay).await;
+            if tx.send(Ok(ChatEvent::Text(word.to_string()))).await.is_err() {
+                return;
+            }
+            words += 1;
+        }
+        // One token per word is close enough for budgets and throughput.
+        let usage = TokenUsage {
+            prompt_tokens: 0,
+            output_tokens: words as u64,
+            total_tokens: words as u64,
+        };
+        if tx.send(Ok(ChatEvent::Usage(usage))).await.is_err() {
+            return;
+        }
+        if let Some(reason) = &self.opts.finish_reason {
+            let _ = tx.send(Ok(ChatEvent::Finish { reason: reason.clone() })).await;
+        }
     }
 }
 
@@ -18,28 +91,42 @@ impl Provider for StubProvider {
         "stub"
     }
 
+    fn describe_request(&self, req: &ChatRequest) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "model": req.model,
+            "prompt": req.prompt,
+            "generationConfig": req.generation,
+        }))
+    }
+
     fn stream_chat(
         &self,
         req: ChatRequest,
     ) -> std::pin::Pin<
         Box<
-            dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatChunk>>>>
+            dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatEvent>>>>
                 + Send,
         >,
     > {
+        let simulation = self.simulation.clone();
         Box::pin(async move {
+            if let Some(simulation) = simulation {
+                let (tx, rx) = mpsc::channel::<anyhow::Result<ChatEvent>>(32);
+                tokio::spawn(async move { simulation.run(tx).await });
+                let stream = ReceiverStream::new(rx).map(|x| x);
+                return Ok(Box::pin(stream) as BoxStream<'static, anyhow::Result<ChatEvent>>);
+            }
+
             // In a real provider, this would perform an HTTP request and parse streaming chunks.
             // Here we just drip a few chunks with delays.
-            let (tx, rx) = mpsc::channel::<anyhow::Result<ChatChunk>>(32);
+            let (tx, rx) = mpsc::channel::<anyhow::Result<ChatEvent>>(32);
 
             tokio::spawn(async move {
                 let _ = tx
-                    .send(Ok(ChatChunk {
-                        text: format!(
-                            "[stub provider]\nmodel: {}\ninclude_directories: {:?}\n\n",
-                            req.model, req.include_directories
-                        ),
-                    }))
+                    .send(Ok(ChatEvent::Text(format!(
+                        "[stub provider]\nmodel: {}\ninclude_directories: {:?}\n\n",
+                        req.model, req.include_directories
+                    ))))
                     .await;
 
                 let parts = [
@@ -51,14 +138,14 @@ impl Provider for StubProvider {
 
                 for p in parts {
                     tokio::time::sleep(std::time::Duration::from_millis(120)).await;
-                    if tx.send(Ok(ChatChunk { text: p.to_string() })).await.is_err() {
+                    if tx.send(Ok(ChatEvent::Text(p.to_string()))).await.is_err() {
                         break;
                     }
                 }
             });
 
             let stream = ReceiverStream::new(rx).map(|x| x);
-            Ok(Box::pin(stream) as BoxStream<'static, anyhow::Result<ChatChunk>>)
+            Ok(Box::pin(stream) as BoxStream<'static, anyhow::Result<ChatEvent>>)
         })
     }
 }
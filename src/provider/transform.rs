@@ -0,0 +1,231 @@
+//! Post-processing of streamed text, applied between the provider and the
+//! output so every output format (and the saved answer) sees the result.
+
+use super::ChatEvent;
+use futures_core::stream::BoxStream;
+use regex::Regex;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+/// Rewrites answer text as it streams. Chunk boundaries are arbitrary, so a
+/// transform may hold text back until it knows more and release it later.
+pub trait TextTransform: Send {
+    /// Returns the text that is ready to emit after `chunk`.
+    fn push(&mut self, chunk: &str) -> String;
+
+    /// Returns whatever is still held back. Called before any non-text event
+    /// and at the end of the stream.
+    fn flush(&mut self) -> String;
+}
+
+/// Run the text of `stream` through transforms made by `make`: one for the
+/// answer ([`ChatEvent::Text`]), one for [`ChatEvent::Thought`] and one per
+/// [`ChatEvent::Alternate`] candidate, so held-back text never crosses from
+/// one to another. Held text is released before any other event and at the
+/// end of the stream; other events pass through unchanged, in order.
+pub fn apply<F>(
+    mut stream: BoxStream<'static, anyhow::Result<ChatEvent>>,
+    mut make: F,
+) -> BoxStream<'static, anyhow::Result<ChatEvent>>
+where
+    F: FnMut() -> Box<dyn TextTransform> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel::<anyhow::Result<ChatEvent>>(64);
+    tokio::spawn(async move {
+        let mut transforms: Vec<(Channel, Box<dyn TextTransform>)> = Vec::new();
+        while let Some(item) = stream.next().await {
+            let item = match item.map(Channel::split) {
+                Ok(Ok((channel, text))) => {
+                    let transform = match transforms.iter().position(|(c, _)| *c == channel) {
+                        Some(i) => &mut transforms[i].1,
+                        None => {
+                            transforms.push((channel, make()));
+                            &mut transforms.last_mut().expect("just pushed").1
+                        }
+                    };
+                    let text = transform.push(&text);
+                    if !text.is_empty() && tx.send(Ok(channel.join(text))).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                Ok(Err(event)) => Ok(event),
+                Err(e) => Err(e),
+            };
+            if !flush_all(&mut transforms, &tx).await || tx.send(item).await.is_err() {
+                return;
+            }
+        }
+        flush_all(&mut transforms, &tx).await;
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+/// Which text an event carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Answer,
+    Thought,
+    Alternate(u32),
+}
+
+impl Channel {
+    /// The channel and text of a text event, or the event itself.
+    fn split(event: ChatEvent) -> Result<(Channel, String), ChatEvent> {
+        match event {
+            ChatEvent::Text(t) => Ok((Channel::Answer, t)),
+            ChatEvent::Thought(t) => Ok((Channel::Thought, t)),
+            ChatEvent::Alternate { index, text } => Ok((Channel::Alternate(index), text)),
+            other => Err(other),
+        }
+    }
+
+    fn join(self, text: String) -> ChatEvent {
+        match self {
+            Channel::Answer => ChatEvent::Text(text),
+            Channel::Thought => ChatEvent::Thought(text),
+            Channel::Alternate(index) => ChatEvent::Alternate { index, text },
+        }
+    }
+}
+
+/// Send whatever each transform holds back. Returns false once the receiver is gone.
+async fn flush_all(
+    transforms: &mut [(Channel, Box<dyn TextTransform>)],
+    tx: &mpsc::Sender<anyhow::Result<ChatEvent>>,
+) -> bool {
+    for (channel, transform) in transforms {
+        let held = transform.flush();
+        if !held.is_empty() && tx.send(Ok(channel.join(held))).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// `--redact`: replaces every match of a pattern with [`Redact::MASK`].
+///
+/// The last [`Redact::HOLD`] bytes are held back, since a match may start
+/// there and continue in the next chunk; a match longer than that can slip
+/// through unmasked when it straddles a chunk boundary.
+pub struct Redact {
+    pattern: Regex,
+    pending: String,
+}
+
+impl Redact {
+    pub const MASK: &'static str = "[REDACTED]";
+    pub const HOLD: usize = 256;
+
+    pub fn new(pattern: Regex) -> Self {
+        Self {
+            pattern,
+            pending: String::new(),
+        }
+    }
+}
+
+impl TextTransform for Redact {
+    fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+        let mut safe = self.pending.len().saturating_sub(Self::HOLD);
+        while !self.pending.is_char_boundary(safe) {
+            safe -= 1;
+        }
+
+        let mut out = String::new();
+        let mut cursor = 0;
+        for m in self.pattern.find_iter(&self.pending) {
+            // A match in the held tail, or one reaching the end of the text
+            // so far, may still change with the next chunk.
+            if m.start() >= safe || m.end() == self.pending.len() {
+                safe = safe.min(m.start());
+                break;
+            }
+            out.push_str(&self.pending[cursor..m.start()]);
+            out.push_str(Self::MASK);
+            cursor = m.end();
+        }
+        if safe > cursor {
+            out.push_str(&self.pending[cursor..safe]);
+            cursor = safe;
+        }
+        self.pending.drain(..cursor);
+        out
+    }
+
+    fn flush(&mut self) -> String {
+        let out = self.pattern.replace_all(&self.pending, Self::MASK).into_owned();
+        self.pending.clear();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(pattern: &str) -> Redact {
+        Redact::new(Regex::new(pattern).unwrap())
+    }
+
+    #[test]
+    fn redact_masks_match_split_across_pushes() {
+        let mut r = redact(r"sk-[a-z0-9]{8}");
+        let pad = "x".repeat(Redact::HOLD);
+        let first = r.push(&format!("{pad}key: sk-abc"));
+        // Enough text to release some, but not the start of the key.
+        assert_eq!(first, "x".repeat("key: sk-abc".len()));
+        let mut out = first + &r.push("12345 is secret. ");
+        out += &r.flush();
+        assert_eq!(out, format!("{pad}key: [REDACTED] is secret. "));
+    }
+
+    #[test]
+    fn redact_holds_tail_until_flush() {
+        let mut r = redact(r"\d{3}-\d{4}");
+        // The number could still continue, so it stays held.
+        assert_eq!(r.push("call 555-1234"), "");
+        assert_eq!(r.flush(), "call [REDACTED]");
+        assert_eq!(r.flush(), "");
+
+        let mut r = redact("secret");
+        assert_eq!(r.push("no secrets here"), "");
+        assert_eq!(r.flush(), "no [REDACTED]s here");
+    }
+
+    #[test]
+    fn redact_releases_text_beyond_hold() {
+        let mut r = redact("secret");
+        let long = "x".repeat(Redact::HOLD + 10);
+        assert_eq!(r.push(&long), "x".repeat(10));
+        assert_eq!(r.flush(), "x".repeat(Redact::HOLD));
+    }
+
+    #[tokio::test]
+    async fn apply_transforms_each_text_stream_separately() {
+        let events = vec![
+            Ok(ChatEvent::Thought("the user's pin is 12".into())),
+            Ok(ChatEvent::Text("pin 34".into())),
+            Ok(ChatEvent::Thought("34".into())),
+            Ok(ChatEvent::Alternate { index: 1, text: "1234".into() }),
+            Ok(ChatEvent::Text("56".into())),
+            Ok(ChatEvent::Finish { reason: "STOP".into() }),
+        ];
+        let stream: BoxStream<'static, anyhow::Result<ChatEvent>> = Box::pin(tokio_stream::iter(events));
+        let out: Vec<_> = apply(stream, || Box::new(redact(r"\d{4}")))
+            .map(|e| format!("{:?}", e.unwrap()))
+            .collect()
+            .await;
+        assert_eq!(
+            out,
+            [
+                r#"Thought("the user's pin is [REDACTED]")"#,
+                r#"Text("pin [REDACTED]")"#,
+                r#"Alternate { index: 1, text: "[REDACTED]" }"#,
+                r#"Finish { reason: "STOP" }"#,
+            ]
+        );
+    }
+}
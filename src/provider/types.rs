@@ -1,19 +1,393 @@
 use futures_core::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ChatRequest {
     pub model: String,
     pub prompt: String,
 
-    /// Phase A placeholder for passing directory context.
+    /// System instruction sent ahead of the conversation.
+    pub system: Option<String>,
+
+    /// Directories whose files were included as context in `prompt`.
     pub include_directories: Vec<std::path::PathBuf>,
+
+    /// Context cache handle (`cachedContents/...`) to reference, if any.
+    pub cached_content: Option<String>,
+
+    /// Sampling settings; unset fields use the server defaults.
+    pub generation: GenerationConfig,
+
+    /// Also emit each undecoded response payload as [`ChatEvent::Raw`].
+    pub raw_events: bool,
+
+    /// Client-side id used to correlate log lines and errors for this request.
+    pub request_id: Option<String>,
+
+    /// Earlier turns of the conversation, oldest first; `prompt` follows them.
+    pub history: Vec<ChatMessage>,
+
+    /// Billing labels. Only Vertex AI accepts them; providers that can't send
+    /// them fail the request rather than drop them.
+    pub labels: std::collections::BTreeMap<String, String>,
+
+    /// Uploaded files sent with `prompt`, ahead of its text.
+    pub attachments: Vec<FileAttachment>,
+
+    /// Ground the answer in web search results; the sources come back as
+    /// [`ChatEvent::Grounding`].
+    pub search: bool,
 }
 
-#[derive(Debug, Clone)]
-pub struct ChatChunk {
+/// A file the provider already holds, referenced by URI instead of inlined.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileAttachment {
+    pub uri: String,
+    pub mime_type: String,
+}
+
+/// One turn of a conversation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
     pub text: String,
 }
 
+impl ChatMessage {
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            text: text.into(),
+        }
+    }
+
+    pub fn model(text: impl Into<String>) -> Self {
+        Self {
+            role: Role::Model,
+            text: text.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Model,
+    /// Instructions rather than a turn. Providers without a place for them
+    /// in the conversation send them as the system instruction.
+    System,
+}
+
+/// Sampling settings, serialized as Gemini's `generationConfig`.
+///
+/// Unset fields are omitted so they don't override server defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+
+    /// Upper bound on generated tokens; the answer stops with `MAX_TOKENS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+
+    /// Strings that end the answer when generated (not included in it).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_config: Option<ThinkingConfig>,
+
+    /// Penalty (-2.0-2.0) on tokens that already appeared, regardless of count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+
+    /// Penalty (-2.0-2.0) on tokens in proportion to how often they appeared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+
+    /// Number of candidates to generate; candidates after the first stream as
+    /// [`ChatEvent::Alternate`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+
+    /// Return per-token log probabilities as [`ChatEvent::Logprobs`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_logprobs: Option<bool>,
+
+    /// Number of top alternatives to report per token (0-20).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<u32>,
+
+    /// MIME type of the answer, e.g. `application/json` or `text/x.enum`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+
+    /// OpenAPI-style schema the answer must follow (with a structured
+    /// `response_mime_type`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+/// Thinking-model settings (`generationConfig.thinkingConfig`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingConfig {
+    /// Stream the model's reasoning as [`ChatEvent::Thought`]s.
+    pub include_thoughts: bool,
+}
+
+impl GenerationConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Reject out-of-range values before they reach the API (which would
+    /// answer with a less helpful HTTP 400).
+    pub fn validate(&self) -> anyhow::Result<()> {
+        check_range("temperature", self.temperature, 0.0, 2.0)?;
+        check_range("top_p", self.top_p, 0.0, 1.0)?;
+        check_range("presence_penalty", self.presence_penalty, -2.0, 2.0)?;
+        check_range("frequency_penalty", self.frequency_penalty, -2.0, 2.0)?;
+        if self.top_k == Some(0) {
+            anyhow::bail!("top_k must be at least 1");
+        }
+        if self.max_output_tokens == Some(0) {
+            anyhow::bail!("max output tokens must be at least 1");
+        }
+        if self.stop_sequences.len() > 5 {
+            anyhow::bail!("at most 5 stop sequences (got {})", self.stop_sequences.len());
+        }
+        if self.stop_sequences.iter().any(|s| s.is_empty()) {
+            anyhow::bail!("stop sequences must not be empty");
+        }
+        if self.candidate_count.is_some_and(|n| !(1..=8).contains(&n)) {
+            anyhow::bail!("candidate count must be between 1 and 8");
+        }
+        if self.logprobs.is_some_and(|n| n > 20) {
+            anyhow::bail!("logprobs must be between 0 and 20");
+        }
+        Ok(())
+    }
+
+    /// Constrain the answer to exactly one of `values` (`text/x.enum`).
+    pub fn constrain_to_enum(&mut self, values: &[String]) -> anyhow::Result<()> {
+        if values.is_empty() || values.iter().any(|v| v.trim().is_empty()) {
+            anyhow::bail!("enum values must not be empty");
+        }
+        if let Some(dup) = values.iter().enumerate().find_map(|(i, v)| values[..i].contains(v).then_some(v)) {
+            anyhow::bail!("enum value {dup:?} is listed twice");
+        }
+        self.response_mime_type = Some("text/x.enum".to_string());
+        self.response_schema = Some(serde_json::json!({ "type": "STRING", "enum": values }));
+        Ok(())
+    }
+}
+
+fn check_range(name: &str, value: Option<f32>, min: f32, max: f32) -> anyhow::Result<()> {
+    match value {
+        // NaN fails `contains`, so it is rejected here too.
+        Some(v) if !(min..=max).contains(&v) => {
+            anyhow::bail!("{name} must be between {min} and {max} (got {v})")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// One item of a streamed response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatEvent {
+    /// Answer text.
+    Text(String),
+    /// Reasoning from a thinking model; only sent when requested with
+    /// [`ThinkingConfig::include_thoughts`].
+    Thought(String),
+    /// Answer text of an additional candidate (`index` >= 1) when
+    /// [`GenerationConfig::candidate_count`] is above 1.
+    Alternate { index: u32, text: String },
+    /// Log probabilities of the tokens in the preceding text, when requested
+    /// with [`GenerationConfig::response_logprobs`].
+    Logprobs(Vec<TokenLogprob>),
+    /// Undecoded response payload (one SSE `data:` JSON document), sent
+    /// before the events decoded from it when [`ChatRequest::raw_events`] is set.
+    Raw(String),
+    /// The model asked for a function (tool) call instead of, or besides,
+    /// answering in text.
+    FunctionCall { name: String, args: serde_json::Value },
+    /// Why generation stopped (e.g. `STOP`, `MAX_TOKENS`, `SAFETY`).
+    Finish { reason: String },
+    /// The prompt was rejected before generation (e.g. `SAFETY`).
+    Blocked { reason: String },
+    /// Token counts for the response so far. Sent repeatedly as the stream
+    /// progresses; the last one covers the whole exchange.
+    Usage(TokenUsage),
+    /// Transfer timings, sent once when the response body has been read to
+    /// the end.
+    Stats(StreamStats),
+    /// Sources of a grounded answer ([`ChatRequest::search`]). May be sent
+    /// more than once; each covers the answer so far and replaces the last.
+    Grounding(Grounding),
+}
+
+/// Web sources an answer was grounded in, and which parts of the answer
+/// each supports.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Grounding {
+    pub sources: Vec<GroundingSource>,
+    pub citations: Vec<Citation>,
+    /// Search queries the model ran.
+    pub queries: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroundingSource {
+    pub uri: String,
+    pub title: Option<String>,
+}
+
+/// A span of the answer text and the sources backing it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Citation {
+    /// Byte offsets into the answer text.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// Indices into [`Grounding::sources`].
+    pub sources: Vec<usize>,
+}
+
+/// Token counts reported by the API for one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub output_tokens: u64,
+    /// Everything billed for the request, including thinking tokens.
+    pub total_tokens: u64,
+}
+
+/// How the response body arrived, measured from just before the request
+/// was sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    pub time_to_first_byte: Duration,
+    pub duration: Duration,
+    /// Body chunks read from the connection (not decoded events).
+    pub chunks: u64,
+    pub bytes: u64,
+}
+
+impl ChatEvent {
+    /// The answer text, or `None` for other events.
+    pub fn answer(&self) -> Option<&str> {
+        match self {
+            ChatEvent::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// Log probability of one generated token.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    /// Most likely candidates at this position, best first (empty for the
+    /// alternatives themselves).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top: Vec<TokenLogprob>,
+}
+
+/// A model's limits as reported by the provider.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelInfo {
+    /// Model id without the `models/` prefix.
+    pub name: String,
+    pub display_name: Option<String>,
+    pub input_token_limit: Option<u64>,
+    pub output_token_limit: Option<u64>,
+}
+
+/// Non-success HTTP response from a provider API.
+///
+/// Returned (possibly wrapped in context) so callers can `downcast_ref` and
+/// decide whether to retry or fall back.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: u16,
+    pub body: String,
+    /// Response headers, minus any that could carry credentials.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Headers never copied into an [`ApiError`].
+const SECRET_HEADERS: &[&str] = &["authorization", "cookie", "set-cookie", "proxy-authorization", "x-goog-api-key"];
+
+impl ApiError {
+    pub fn new(status: u16, body: String, headers: &reqwest::header::HeaderMap) -> Self {
+        let headers = headers
+            .iter()
+            .filter(|(name, _)| !SECRET_HEADERS.contains(&name.as_str()))
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect();
+        Self { status, body, headers }
+    }
+
+    /// `error.status` and `error.message` from a Google-style JSON error
+    /// body (also accepted wrapped in a one-element array, as the streaming
+    /// endpoint sends it).
+    pub fn summary(&self) -> Option<(Option<String>, String)> {
+        let v: serde_json::Value = serde_json::from_str(&self.body).ok()?;
+        let err = match &v {
+            serde_json::Value::Array(items) => items.first()?.get("error")?,
+            other => other.get("error")?,
+        };
+        let message = err.get("message")?.as_str()?.to_string();
+        let status = err.get("status").and_then(|s| s.as_str()).map(str::to_string);
+        Some((status, message))
+    }
+
+    /// Status line, headers and the unmodified body, for `--show-raw-errors`.
+    pub fn raw(&self) -> String {
+        let mut out = format!("HTTP {}\n", self.status);
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{name}: {value}\n"));
+        }
+        out.push('\n');
+        out.push_str(&self.body);
+        out
+    }
+
+    /// The model is overloaded, rate limited, or not served right now.
+    pub fn is_unavailable(&self) -> bool {
+        self.status == 404 || self.is_transient()
+    }
+
+    /// Rate limited or a server-side failure that may succeed on retry.
+    pub fn is_transient(&self) -> bool {
+        matches!(self.status, 429 | 500 | 502 | 503 | 504)
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.summary() {
+            Some((Some(status), message)) => write!(f, "HTTP {} {status}: {message}", self.status),
+            Some((None, message)) => write!(f, "HTTP {}: {message}", self.status),
+            None => write!(f, "HTTP {}: {}", self.status, self.body.trim()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 /// Provider interface.
 ///
 /// Phase A: only a streaming chat method.
@@ -24,5 +398,65 @@ pub trait Provider {
     fn stream_chat(
         &self,
         req: ChatRequest,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatChunk>>>> + Send>>;
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<BoxStream<'static, anyhow::Result<ChatEvent>>>> + Send>>;
+
+    /// The whole answer text at once, for callers that don't stream. The
+    /// default reads `stream_chat` to the end.
+    fn generate(&self, req: ChatRequest) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<String>> + Send>> {
+        let started = self.stream_chat(req);
+        Box::pin(async move { collect_text(started.await?).await })
+    }
+
+    /// The request that `stream_chat(req)` would send, for `--dry-run`.
+    /// Secrets are redacted.
+    fn describe_request(&self, req: &ChatRequest) -> anyhow::Result<serde_json::Value> {
+        let _ = req;
+        anyhow::bail!("--dry-run is not supported by the {} provider", self.name())
+    }
+
+    /// The endpoint `stream_chat` would call for `model`, with secrets
+    /// redacted, for `--print-url`. `Ok(None)` if the provider has no URL to
+    /// show (the default).
+    fn request_url(&self, model: &str) -> anyhow::Result<Option<reqwest::Url>> {
+        let _ = model;
+        Ok(None)
+    }
+
+    /// Look up `model`'s limits. `Ok(None)` when the provider doesn't know the
+    /// model or can't report limits (the default).
+    fn model_info(
+        &self,
+        model: &str,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Option<ModelInfo>>> + Send>> {
+        let _ = model;
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Models available to the caller, for completion in interactive
+    /// sessions. Empty when the provider can't list them (the default).
+    fn models(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<Vec<ModelInfo>>> + Send>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    /// Send a cheap request that keeps pooled connections open.
+    ///
+    /// Used by idle interactive sessions; the default does nothing.
+    fn keep_warm(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = anyhow::Result<()>> + Send>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Concatenate the answer text of `stream`. A blocked prompt is an error,
+/// so an empty answer always means the model said nothing.
+pub(crate) async fn collect_text(mut stream: BoxStream<'static, anyhow::Result<ChatEvent>>) -> anyhow::Result<String> {
+    use tokio_stream::StreamExt;
+    let mut text = String::new();
+    while let Some(event) = stream.next().await {
+        match event? {
+            ChatEvent::Text(t) => text.push_str(&t),
+            ChatEvent::Blocked { reason } => anyhow::bail!("the prompt was blocked: {reason}"),
+            _ => {}
+        }
+    }
+    Ok(text)
 }
@@ -0,0 +1,139 @@
+//! Exponential backoff with jitter for retrying transient failures.
+
+use std::future::Future;
+use std::time::Duration;
+
+/// Retry policy: the delay doubles from `base` up to `max`, and each delay is
+/// randomly stretched or shrunk by up to `jitter` (a fraction, 0.0-1.0) so
+/// concurrent clients don't retry in lockstep.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+    /// Retries after the first attempt; 0 disables retrying.
+    pub retries: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(8),
+            jitter: 0.2,
+            retries: 3,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before retry `attempt` (0-based), without jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Delay before retry `attempt` with jitter applied; `unit` is a random
+    /// value in `[0, 1)`.
+    pub fn jittered_delay(&self, attempt: u32, unit: f64) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let scale = 1.0 - jitter + 2.0 * jitter * unit;
+        self.delay(attempt).mul_f64(scale)
+    }
+}
+
+/// Run `op` until it succeeds, fails with an error `retryable` rejects, or the
+/// policy's retries are used up. The last error is returned.
+pub async fn retry_with<T, Op, Fut, P>(policy: &Backoff, mut retryable: P, mut op: Op) -> anyhow::Result<T>
+where
+    Op: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+    P: FnMut(&anyhow::Error) -> bool,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < policy.retries && retryable(&e) => {
+                let delay = policy.jittered_delay(attempt, fastrand::f64());
+                tracing::debug!(attempt, ?delay, "retrying after error: {e:#}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether `err` is a provider response worth retrying (429 or 5xx).
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<crate::provider::ApiError>()
+        .is_some_and(|a| a.is_transient())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Backoff {
+        Backoff {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            jitter: 0.5,
+            retries: 3,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_until_capped() {
+        let p = policy();
+        let delays: Vec<_> = (0..6).map(|a| p.delay(a).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(p.delay(u32::MAX), p.max);
+    }
+
+    #[test]
+    fn jittered_delay_stays_within_bounds() {
+        let p = policy();
+        assert_eq!(p.jittered_delay(1, 0.0), Duration::from_millis(100));
+        assert_eq!(p.jittered_delay(1, 0.5), Duration::from_millis(200));
+        let mut rng = fastrand::Rng::with_seed(7);
+        for attempt in 0..8 {
+            let base = p.delay(attempt);
+            for _ in 0..100 {
+                let d = p.jittered_delay(attempt, rng.f64());
+                assert!(d >= base.mul_f64(0.5) && d < base.mul_f64(1.5), "{d:?} for {base:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_is_clamped() {
+        let p = Backoff { jitter: 3.0, ..policy() };
+        assert_eq!(p.jittered_delay(0, 0.0), Duration::ZERO);
+        let p = Backoff { jitter: -1.0, ..policy() };
+        assert_eq!(p.jittered_delay(0, 0.9), p.delay(0));
+    }
+
+    #[tokio::test]
+    async fn retry_with_stops_after_retries() {
+        let p = Backoff { base: Duration::ZERO, ..policy() };
+        let mut calls = 0;
+        let res: anyhow::Result<()> = retry_with(&p, |_| true, || {
+            calls += 1;
+            async { anyhow::bail!("down") }
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let res: anyhow::Result<()> = retry_with(&p, |_| false, || {
+            calls += 1;
+            async { anyhow::bail!("fatal") }
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::cli::SessionCommand;
+use crate::provider::ChatMessage;
+use crate::{auth, paths, state};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A conversation continued across invocations with `--session <name>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// All turns so far, oldest first.
+    pub messages: Vec<ChatMessage>,
+    /// Seconds since UNIX epoch.
+    pub updated_at: u64,
+    /// Tokens billed for all turns, as reported by the API.
+    #[serde(default)]
+    pub total_tokens: u64,
+}
+
+impl Session {
+    /// Record one exchange.
+    pub fn push_turn(&mut self, prompt: String, answer: String) {
+        self.messages.push(ChatMessage::user(prompt));
+        self.messages.push(ChatMessage::model(answer));
+        self.updated_at = auth::now_secs();
+    }
+}
+
+pub fn cmd_session(cmd: SessionCommand) -> anyhow::Result<()> {
+    match cmd {
+        SessionCommand::List => {
+            let mut sessions = Vec::new();
+            for entry in std::fs::read_dir(paths::sessions_dir()?).context("failed to list sessions")? {
+                let path = entry?.path();
+                let Some(name) = path.file_stem().and_then(|s| s.to_str()).filter(|_| {
+                    path.extension().is_some_and(|e| e == "json")
+                }) else {
+                    continue;
+                };
+                let session = load(name)?.unwrap_or_default();
+                sessions.push((name.to_string(), session));
+            }
+            if sessions.is_empty() {
+                println!("(no sessions)");
+                return Ok(());
+            }
+            sessions.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, session) in sessions {
+                println!(
+                    "{name}\t{} turns\t{} tokens\tupdated_at: {}",
+                    session.messages.len() / 2,
+                    session.total_tokens,
+                    session.updated_at
+                );
+            }
+            Ok(())
+        }
+        SessionCommand::Clear { name } => {
+            let path = path_for(&name)?;
+            match std::fs::remove_file(&path) {
+                Ok(()) => println!("Cleared session {name}"),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => anyhow::bail!("no such session: {name}"),
+                Err(e) => {
+                    return Err(anyhow::Error::new(e)).with_context(|| format!("failed to remove: {}", path.display()))
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Session names become file names, so keep them to a safe character set.
+fn path_for(name: &str) -> anyhow::Result<PathBuf> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid || name.starts_with('.') {
+        anyhow::bail!("invalid session name {name:?} (use letters, digits, '-', '_' and '.')");
+    }
+    Ok(paths::sessions_dir()?.join(format!("{name}.json")))
+}
+
+/// Format history of session files (see [`state`]).
+const MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
+pub fn load(name: &str) -> anyhow::Result<Option<Session>> {
+    let path = path_for(name)?;
+    let bytes = match std::fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(anyhow::Error::new(e)).with_context(|| format!("failed to read: {}", path.display()))
+        }
+    };
+    let session =
+        state::from_json(&bytes, MIGRATIONS).with_context(|| format!("failed to parse JSON: {}", path.display()))?;
+    Ok(Some(session))
+}
+
+pub fn save(name: &str, session: &Session) -> anyhow::Result<()> {
+    let path = path_for(name)?;
+    let bytes = state::to_json(session, MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(&path, &bytes)
+}
@@ -0,0 +1,118 @@
+//! Versioned JSON for files kept in the state directory.
+//!
+//! Every state file carries a top-level `schema_version`. Each file type
+//! keeps a list of [`Migration`]s: entry `i` upgrades version `i` to `i + 1`,
+//! so the current version is the list's length. Files written before
+//! versioning have no `schema_version` and count as version 0. A file from a
+//! newer version of the CLI is refused rather than misread or overwritten.
+
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Upgrades a file's JSON by one version, in place.
+pub type Migration = fn(&mut Value) -> anyhow::Result<()>;
+
+/// Version 0 to 1: the unversioned layout is unchanged; loading it only adds
+/// the version.
+pub fn adopt_versioning(_: &mut Value) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Parse a state file, upgrading it through `migrations` first.
+pub fn from_json<T: DeserializeOwned>(bytes: &[u8], migrations: &[Migration]) -> anyhow::Result<T> {
+    let mut value: Value = serde_json::from_slice(bytes)?;
+    let object = value.as_object_mut().context("expected a JSON object")?;
+    let version = match object.remove(SCHEMA_VERSION_KEY) {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| usize::try_from(v).ok())
+            .with_context(|| format!("invalid {SCHEMA_VERSION_KEY}: {v}"))?,
+    };
+    if version > migrations.len() {
+        anyhow::bail!(
+            "{SCHEMA_VERSION_KEY} {version} is newer than this version of gemini supports ({}); upgrade gemini",
+            migrations.len()
+        );
+    }
+    for (from, migrate) in migrations.iter().enumerate().skip(version) {
+        migrate(&mut value).with_context(|| format!("failed to upgrade from {SCHEMA_VERSION_KEY} {from}"))?;
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Serialize a state file (pretty-printed) at the current version.
+pub fn to_json<T: Serialize>(data: &T, migrations: &[Migration]) -> anyhow::Result<Vec<u8>> {
+    let mut value = serde_json::to_value(data)?;
+    let object = value.as_object_mut().context("state files must be JSON objects")?;
+    object.insert(SCHEMA_VERSION_KEY.to_string(), Value::from(migrations.len()));
+    Ok(serde_json::to_vec_pretty(&value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        name: String,
+        #[serde(default)]
+        count: u32,
+    }
+
+    /// Version 1 to 2: `n` was renamed to `count`.
+    fn rename_n(value: &mut Value) -> anyhow::Result<()> {
+        let object = value.as_object_mut().context("expected a JSON object")?;
+        if let Some(n) = object.remove("n") {
+            object.insert("count".into(), n);
+        }
+        Ok(())
+    }
+
+    const MIGRATIONS: &[Migration] = &[adopt_versioning, rename_n];
+
+    #[test]
+    fn unversioned_file_migrates_to_current_version() {
+        let sample: Sample = from_json(br#"{"name": "a", "n": 3}"#, MIGRATIONS).unwrap();
+        assert_eq!(sample, Sample { name: "a".into(), count: 3 });
+
+        // Migrations before the file's version are skipped.
+        let sample: Sample = from_json(br#"{"schema_version": 2, "name": "a", "n": 3}"#, MIGRATIONS).unwrap();
+        assert_eq!(sample, Sample { name: "a".into(), count: 0 });
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let err = from_json::<Sample>(br#"{"schema_version": 3, "name": "a"}"#, MIGRATIONS).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{err}");
+    }
+
+    #[test]
+    fn invalid_version_is_refused() {
+        for version in [r#""1""#, "1.5", "-1", "null"] {
+            let json = format!(r#"{{"schema_version": {version}, "name": "a"}}"#);
+            let err = from_json::<Sample>(json.as_bytes(), MIGRATIONS).unwrap_err();
+            assert!(err.to_string().contains("invalid schema_version"), "{version}: {err}");
+        }
+    }
+
+    #[test]
+    fn non_object_is_refused() {
+        assert!(from_json::<Sample>(b"[]", MIGRATIONS).is_err());
+        assert!(to_json(&vec![1, 2], MIGRATIONS).is_err());
+    }
+
+    #[test]
+    fn round_trip_writes_current_version() {
+        let sample = Sample { name: "a".into(), count: 7 };
+        let bytes = to_json(&sample, MIGRATIONS).unwrap();
+        let value: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(value[SCHEMA_VERSION_KEY], 2);
+        assert_eq!(from_json::<Sample>(&bytes, MIGRATIONS).unwrap(), sample);
+    }
+}
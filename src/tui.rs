@@ -1,50 +1,360 @@
 #![cfg(feature = "tui")]
 
-use crate::{app, config};
+use crate::provider::ChatEvent;
+use crate::{app, auth, config, paths, state};
 use anyhow::Context;
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{execute, terminal};
 use ratatui::backend::CrosstermBackend;
-use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Modifier, Style};
-use ratatui::text::{Line, Text};
-use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
 use ratatui::Terminal;
+use serde::{Deserialize, Serialize};
 use std::io;
+use std::path::PathBuf;
 use tokio::sync::mpsc;
 
 #[derive(Debug, Clone)]
 struct ChatLine {
     role: &'static str,
     text: String,
+    /// Streamed reasoning (assistant lines only); shown in the thoughts pane.
+    thought: String,
+    /// Text of candidates 1.. (assistant lines only, with `--candidates`).
+    alternates: Vec<String>,
+    /// The stream failed before this assistant reply was complete.
+    partial: bool,
+}
+
+impl ChatLine {
+    fn new(role: &'static str, text: impl Into<String>) -> Self {
+        Self {
+            role,
+            text: text.into(),
+            thought: String::new(),
+            alternates: Vec::new(),
+            partial: false,
+        }
+    }
+
+    /// The role as shown in the chat.
+    fn label(&self) -> String {
+        if self.partial {
+            format!("{} (partial)", self.role)
+        } else {
+            self.role.to_string()
+        }
+    }
+}
+
+/// Options for `gemini tui`.
+#[derive(Debug, Clone, Default)]
+pub struct TuiOptions {
+    /// Ping the provider while idle so the next turn reuses a warm connection.
+    pub keep_warm: bool,
+    pub message_style: MessageStyle,
+    /// Candidates to request per turn; more than one are shown side by side.
+    pub candidates: u32,
+    /// Transcript to load at startup (name under the state dir, or a path).
+    pub resume: Option<String>,
+    /// Browse the resumed transcript only: no input, no provider.
+    pub read_only: bool,
+    /// Accessible layout: no borders or styling, spelled-out speaker labels,
+    /// and streamed text redrawn at most every [`SIMPLE_STREAM_REDRAW`].
+    pub simple: bool,
+    /// Stop sending once the chat has used this many tokens.
+    pub budget_tokens_total: Option<u64>,
+    /// `--system`, added to the configured system instructions.
+    pub system: Option<String>,
+}
+
+/// Conversation search (Ctrl-F, or `/` in read-only sessions).
+#[derive(Debug, Clone, Default)]
+struct Search {
+    query: String,
+    mode: SearchMode,
+    /// Index of the match to show; clamped when drawn.
+    current: usize,
+    /// Scroll to `current` on the next draw.
+    jump: bool,
+    /// Matching rows as of the last draw.
+    count: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SearchMode {
+    #[default]
+    Off,
+    /// Typing the query in the input line.
+    Editing,
+    /// Query set; `n`/`N` move between matches.
+    Browsing,
+}
+
+/// How the chat is shown: the layout options, toggles and overlays.
+#[derive(Debug, Clone, Default)]
+struct ViewState {
+    style: MessageStyle,
+    /// Candidates per turn, shown side by side.
+    candidates: u32,
+    simple: bool,
+    read_only: bool,
+    /// A turn is streaming.
+    streaming: bool,
+    /// Kept for the whole session (survives /clear).
+    show_thoughts: bool,
+    show_help: bool,
+    /// Rows scrolled up from the bottom; 0 follows new output.
+    scroll: usize,
+    search: Search,
+    picker: Option<Picker>,
+}
+
+/// A conversation saved with `/save`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Transcript {
+    model: String,
+    /// Seconds since UNIX epoch.
+    saved_at: u64,
+    lines: Vec<TranscriptLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TranscriptLine {
+    role: String,
+    text: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    thought: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    alternates: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+}
+
+impl From<&ChatLine> for TranscriptLine {
+    fn from(l: &ChatLine) -> Self {
+        Self {
+            role: l.role.to_string(),
+            text: l.text.clone(),
+            thought: l.thought.clone(),
+            alternates: l.alternates.clone(),
+            partial: l.partial,
+        }
+    }
+}
+
+impl From<TranscriptLine> for ChatLine {
+    fn from(l: TranscriptLine) -> Self {
+        let role = match l.role.as_str() {
+            "user" => "user",
+            "assistant" => "assistant",
+            "error" => "error",
+            _ => "system",
+        };
+        Self {
+            role,
+            text: l.text,
+            thought: l.thought,
+            alternates: l.alternates,
+            partial: l.partial,
+        }
+    }
+}
+
+/// Format history of saved transcripts (see [`state`]).
+const TRANSCRIPT_MIGRATIONS: &[state::Migration] = &[state::adopt_versioning];
+
+/// `name` as given if it looks like a path, else `<transcripts dir>/<name>.json`.
+fn transcript_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.contains(std::path::MAIN_SEPARATOR) || name.ends_with(".json") {
+        return Ok(PathBuf::from(name));
+    }
+    Ok(paths::transcripts_dir()?.join(format!("{name}.json")))
+}
+
+fn load_transcript(name: &str) -> anyhow::Result<Transcript> {
+    let path = transcript_path(name)?;
+    let bytes = std::fs::read(&path).with_context(|| format!("failed to read transcript: {}", path.display()))?;
+    state::from_json(&bytes, TRANSCRIPT_MIGRATIONS)
+        .with_context(|| format!("failed to parse transcript: {}", path.display()))
+}
+
+fn save_transcript(name: &str, model: &str, lines: &[ChatLine]) -> anyhow::Result<PathBuf> {
+    let path = transcript_path(name)?;
+    let transcript = Transcript {
+        model: model.to_string(),
+        saved_at: auth::now_secs(),
+        lines: lines.iter().filter(|l| l.role != "system").map(TranscriptLine::from).collect(),
+    };
+    let bytes = state::to_json(&transcript, TRANSCRIPT_MIGRATIONS).context("failed to encode JSON")?;
+    paths::write_atomic(&path, &bytes)?;
+    Ok(path)
+}
+
+/// The `/sessions` popup: saved conversations, newest first.
+#[derive(Debug, Clone, Default)]
+struct Picker {
+    entries: Vec<SavedChat>,
+    selected: usize,
+    /// Loading would drop this many unsaved messages; waiting for Enter
+    /// again to confirm.
+    confirm: Option<usize>,
 }
 
+#[derive(Debug, Clone)]
+struct SavedChat {
+    name: String,
+    model: String,
+    saved_at: u64,
+    messages: usize,
+}
+
+/// Saved transcripts, newest first. Files that don't parse are skipped.
+fn list_transcripts() -> anyhow::Result<Vec<SavedChat>> {
+    let dir = paths::transcripts_dir()?;
+    let mut out = Vec::new();
+    for entry in std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        match load_transcript(name) {
+            Ok(t) => out.push(SavedChat {
+                name: name.to_string(),
+                model: t.model,
+                saved_at: t.saved_at,
+                messages: t.lines.len(),
+            }),
+            Err(e) => tracing::debug!("skipping transcript {}: {e:#}", path.display()),
+        }
+    }
+    out.sort_by_key(|c| std::cmp::Reverse(c.saved_at));
+    Ok(out)
+}
+
+/// User messages added since the chat was last saved or loaded, when it
+/// had `saved_len` lines.
+fn unsaved_messages(lines: &[ChatLine], saved_len: usize) -> usize {
+    lines.get(saved_len..).unwrap_or_default().iter().filter(|l| l.role == "user").count()
+}
+
+/// The turns of the chat so far, sent with the next message. Notes and
+/// errors are local to the UI, and a partial reply is left out since the
+/// model never finished it.
+fn conversation_history(lines: &[ChatLine]) -> Vec<crate::provider::ChatMessage> {
+    lines
+        .iter()
+        .filter_map(|l| match l.role {
+            "user" => Some(crate::provider::ChatMessage::user(l.text.clone())),
+            "assistant" if !l.partial && !l.text.is_empty() => Some(crate::provider::ChatMessage::model(l.text.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// How chat messages are laid out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageStyle {
+    /// Role label line followed by the message text.
+    #[default]
+    Compact,
+    /// Each message in its own bordered block with a role badge.
+    Blocks,
+}
+
+/// Entries of the help overlay (F1, or `?` on an empty input line).
+const HELP: &[(&str, &str)] = &[
+    ("/quit", "exit (also Esc, Ctrl-C)"),
+    ("/clear", "clear the chat"),
+    ("/model <name>", "switch model"),
+    ("/temp <value>", "set temperature for the next turns (/temp reset: model default)"),
+    ("/save [name]", "save the conversation (resume with `gemini tui --resume <name>`)"),
+    ("/sessions", "pick a saved conversation to load (↑/↓, Enter; Esc to cancel)"),
+    ("/retry, Ctrl-R", "resend the last message after an error"),
+    ("/regenerate", "ask again for a new answer to the last message, replacing the current one"),
+    ("Enter", "send the message"),
+    ("Tab", "complete a /command or a model name after /model"),
+    ("F1, ?", "show this help"),
+    ("F3", "show/hide the thoughts pane"),
+    ("PgUp/PgDn, ↑/↓", "scroll the conversation (Home: top, End: follow)"),
+    ("Ctrl-F", "search the conversation (n/N: next/previous match, Esc: clear)"),
+];
+
+/// How often streamed text is redrawn with `--simple`, so screen readers
+/// aren't flooded with partial updates. Keys still redraw immediately.
+const SIMPLE_STREAM_REDRAW: std::time::Duration = std::time::Duration::from_millis(750);
+
+/// Rows the input box grows to for multi-line (pasted) input; beyond this
+/// it shows the last rows.
+const MAX_INPUT_ROWS: u16 = 8;
+
+/// Idle time after which `--keep-warm` starts pinging, and the ping period.
+const KEEP_WARM_AFTER: std::time::Duration = std::time::Duration::from_secs(45);
+
 #[derive(Debug, Clone)]
 enum StreamMsg {
     Chunk(String),
+    Thought(String),
+    Alternate(u32, String),
+    /// Total tokens of the turn so far.
+    Usage(u64),
     Done,
     Error(String),
 }
 
-pub async fn run_tui(cfg: Option<&config::Config>, model_override: Option<String>) -> anyhow::Result<()> {
-    let http = reqwest::Client::builder()
-        .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
-        .build()
-        .context("failed to build HTTP client")?;
+pub async fn run_tui(
+    http: &reqwest::Client,
+    cfg: Option<&config::Config>,
+    model_override: Option<String>,
+    provider_opts: &app::ProviderOptions,
+    opts: TuiOptions,
+) -> anyhow::Result<()> {
+    let transcript = opts.resume.as_deref().map(load_transcript).transpose()?;
 
-    let provider_name = cfg
-        .and_then(|c| c.provider.clone())
-        .unwrap_or_else(|| "google".to_string());
-    let provider = app::build_provider(&http, cfg, &provider_name).await?;
+    // Read-only sessions never talk to the provider, so need no credentials.
+    let provider_name = app::resolve_provider_name(None, cfg);
+    let provider = if opts.read_only {
+        None
+    } else {
+        Some(app::build_provider(http, cfg, &provider_name, provider_opts).await?)
+    };
 
-    let mut model = model_override
-        .or_else(|| cfg.and_then(|c| c.model.clone()))
-        .unwrap_or_else(|| "gemini-1.5-flash".to_string());
+    // Model names for `/model` completion, fetched in the background.
+    let model_names = std::sync::Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+    if let Some(provider) = &provider {
+        let fetch = provider.models();
+        let names = model_names.clone();
+        tokio::spawn(async move {
+            match fetch.await {
+                Ok(models) => {
+                    if let Ok(mut names) = names.lock() {
+                        *names = models.into_iter().map(|m| m.name).collect();
+                    }
+                }
+                Err(e) => tracing::debug!("listing models for completion failed: {e:#}"),
+            }
+        });
+    }
+
+    let system = app::compose_system(&app::system_layers(cfg, opts.system.as_deref())?);
+
+    let mut model = match (&model_override, &transcript) {
+        (None, Some(t)) => t.model.clone(),
+        _ => app::resolve_model(model_override, cfg, &provider_name),
+    };
 
     enable_raw_mode().context("enable raw mode")?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen).context("enter alt screen")?;
+    // Bracketed paste delivers a paste as one event, so its newlines don't
+    // act as Enter and submit each line. Not every terminal supports it.
+    execute!(stdout, EnableBracketedPaste).ok();
     terminal::enable_raw_mode().ok();
 
     let backend = CrosstermBackend::new(stdout);
@@ -65,29 +375,169 @@ pub async fn run_tui(cfg: Option<&config::Config>, model_override: Option<String
     });
 
     let mut input = String::new();
-    let mut lines: Vec<ChatLine> = vec![ChatLine {
-        role: "system",
-        text: "Type a message and press Enter. Commands: /quit, /clear, /model <name>".to_string(),
-    }];
+    let mut lines: Vec<ChatLine> = match transcript {
+        Some(t) => {
+            let mut lines: Vec<ChatLine> = t.lines.into_iter().map(ChatLine::from).collect();
+            let name = opts.resume.as_deref().unwrap_or_default();
+            lines.push(ChatLine::new("system", format!("resumed {name} ({} messages)", lines.len())));
+            lines
+        }
+        None => vec![ChatLine::new(
+            "system",
+            "Type a message and press Enter. F1 or ? for commands and keys.",
+        )],
+    };
+    let mut view = ViewState {
+        style: opts.message_style,
+        candidates: opts.candidates,
+        simple: opts.simple,
+        read_only: opts.read_only,
+        ..Default::default()
+    };
+    // Length of `lines` when last saved or loaded, to warn before /sessions
+    // replaces unsaved messages.
+    let mut saved_len = lines.len();
+
+    // Sampling overrides set with slash commands; kept for the whole session
+    // (survives /clear).
+    let mut generation = crate::provider::GenerationConfig::default();
 
     let mut active_stream: Option<mpsc::UnboundedReceiver<StreamMsg>> = None;
+    // The request of the turn in flight, then of the last failed one.
+    let mut last_request: Option<crate::provider::ChatRequest> = None;
+    let mut can_retry = false;
+    // Tokens of finished turns, and the latest count for the turn in flight.
+    let mut tokens_used: u64 = 0;
+    let mut turn_tokens: u64 = 0;
 
     let mut ticker = tokio::time::interval(std::time::Duration::from_millis(33));
+    let mut last_activity = std::time::Instant::now();
+    // With --simple, only redraw what changed: input right away, streamed
+    // text at most every SIMPLE_STREAM_REDRAW.
+    let mut input_changed = true;
+    let mut stream_changed = false;
+    let mut last_draw = std::time::Instant::now();
 
     let res = loop {
         tokio::select! {
             _ = ticker.tick() => {
-                if let Err(e) = draw(&mut terminal, &model, &lines, &input) {
-                    break Err(e);
+                let due = !opts.simple
+                    || input_changed
+                    || (stream_changed && last_draw.elapsed() >= SIMPLE_STREAM_REDRAW);
+                if due {
+                    view.streaming = active_stream.is_some();
+                    if let Err(e) = draw(&mut terminal, &model, generation.temperature, &lines, &input, &mut view) {
+                        break Err(e);
+                    }
+                    input_changed = false;
+                    stream_changed = false;
+                    last_draw = std::time::Instant::now();
+                }
+                if let Some(provider) = provider.as_ref().filter(|_| opts.keep_warm && active_stream.is_none() && last_activity.elapsed() >= KEEP_WARM_AFTER) {
+                    last_activity = std::time::Instant::now();
+                    let ping = provider.keep_warm();
+                    tokio::spawn(async move {
+                        let started = std::time::Instant::now();
+                        match ping.await {
+                            Ok(()) => tracing::debug!(elapsed = ?started.elapsed(), "keep-warm ping"),
+                            Err(e) => tracing::debug!("keep-warm ping failed: {e:#}"),
+                        }
+                    });
                 }
             }
             Some(ev) = ev_rx.recv() => {
+                input_changed = true;
                 match ev {
                     Event::Key(key) => {
-                        if handle_key(key, &mut input, &mut lines, &mut model, &provider, &mut active_stream).await? {
+                        last_activity = std::time::Instant::now();
+                        // Any key dismisses the overlay and is otherwise ignored.
+                        if view.show_help {
+                            view.show_help = false;
+                            continue;
+                        }
+                        if let Some(p) = view.picker.as_mut() {
+                            match key.code {
+                                KeyCode::Up => {
+                                    p.selected = p.selected.saturating_sub(1);
+                                    p.confirm = None;
+                                }
+                                KeyCode::Down => {
+                                    p.selected = (p.selected + 1).min(p.entries.len() - 1);
+                                    p.confirm = None;
+                                }
+                                KeyCode::Enter if p.confirm.is_none() && unsaved_messages(&lines, saved_len) > 0 => {
+                                    p.confirm = Some(unsaved_messages(&lines, saved_len));
+                                }
+                                KeyCode::Enter => {
+                                    let name = p.entries[p.selected].name.clone();
+                                    view.picker = None;
+                                    match load_transcript(&name) {
+                                        Ok(t) => {
+                                            lines = t.lines.into_iter().map(ChatLine::from).collect();
+                                            let note = format!("loaded {name} ({} messages, model {})", lines.len(), t.model);
+                                            lines.push(ChatLine::new("system", note));
+                                            saved_len = lines.len();
+                                            model = t.model;
+                                            last_request = None;
+                                            can_retry = false;
+                                            view.scroll = 0;
+                                            view.search = Search::default();
+                                        }
+                                        Err(e) => lines.push(ChatLine::new("system", format!("load failed: {e:#}"))),
+                                    }
+                                }
+                                KeyCode::Esc => view.picker = None,
+                                _ => {}
+                            }
+                            continue;
+                        }
+                        if search_key(key, view.read_only, &mut view.search) {
+                            continue;
+                        }
+                        if key.code == KeyCode::F(1) || (key.code == KeyCode::Char('?') && input.is_empty()) {
+                            view.show_help = true;
+                            continue;
+                        }
+                        if scroll_key(key.code, view.read_only, &mut view.scroll) {
+                            continue;
+                        }
+                        let Some(provider) = &provider else {
+                            let quit = matches!(key.code, KeyCode::Esc | KeyCode::Char('q'))
+                                || (key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c'));
+                            if quit {
+                                break Ok(());
+                            }
+                            continue;
+                        };
+                        let mut turn = Turn {
+                            active_stream: &mut active_stream,
+                            last_request: &mut last_request,
+                            can_retry: &mut can_retry,
+                            tokens_used,
+                            budget: opts.budget_tokens_total,
+                            system: system.as_deref(),
+                        };
+                        let chat = Chat {
+                            input: &mut input,
+                            lines: &mut lines,
+                            model: &mut model,
+                            generation: &mut generation,
+                            saved_len: &mut saved_len,
+                        };
+                        if handle_key(key, chat, &mut view, provider.as_ref(), &model_names, &mut turn).await? {
                             break Ok(());
                         }
                     }
+                    Event::Paste(text) => {
+                        last_activity = std::time::Instant::now();
+                        view.show_help = false;
+                        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                        if view.search.mode == SearchMode::Editing {
+                            view.search.query.push_str(text.lines().next().unwrap_or_default());
+                        } else if provider.is_some() {
+                            input.push_str(&text);
+                        }
+                    }
                     Event::Resize(_, _) => {}
                     _ => {}
                 }
@@ -98,6 +548,7 @@ pub async fn run_tui(cfg: Option<&config::Config>, model_override: Option<String
                     None => None,
                 }
             } => {
+                stream_changed = true;
                 match msg {
                     StreamMsg::Chunk(t) => {
                         if let Some(last) = lines.last_mut() {
@@ -106,12 +557,36 @@ pub async fn run_tui(cfg: Option<&config::Config>, model_override: Option<String
                             }
                         }
                     }
+                    StreamMsg::Alternate(index, t) => {
+                        if let Some(last) = lines.last_mut().filter(|l| l.role == "assistant") {
+                            let i = index as usize - 1;
+                            if last.alternates.len() <= i {
+                                last.alternates.resize(i + 1, String::new());
+                            }
+                            last.alternates[i].push_str(&t);
+                        }
+                    }
+                    StreamMsg::Thought(t) => {
+                        if let Some(last) = lines.last_mut() {
+                            if last.role == "assistant" {
+                                last.thought.push_str(&t);
+                            }
+                        }
+                    }
+                    StreamMsg::Usage(total) => turn_tokens = total,
                     StreamMsg::Done => {
                         active_stream = None;
+                        last_activity = std::time::Instant::now();
+                        tokens_used += std::mem::take(&mut turn_tokens);
+                        note_budget(&mut lines, tokens_used, opts.budget_tokens_total);
                     }
                     StreamMsg::Error(e) => {
                         active_stream = None;
-                        lines.push(ChatLine{role:"error", text: e});
+                        last_activity = std::time::Instant::now();
+                        tokens_used += std::mem::take(&mut turn_tokens);
+                        report_failure(&mut lines, e);
+                        can_retry = true;
+                        note_budget(&mut lines, tokens_used, opts.budget_tokens_total);
                     }
                 }
             }
@@ -119,26 +594,99 @@ pub async fn run_tui(cfg: Option<&config::Config>, model_override: Option<String
     };
 
     disable_raw_mode().ok();
-    execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
+    execute!(terminal.backend_mut(), DisableBracketedPaste, LeaveAlternateScreen).ok();
     terminal.show_cursor().ok();
 
     res
 }
 
+/// The conversation and the settings its next turn uses.
+struct Chat<'a> {
+    input: &'a mut String,
+    lines: &'a mut Vec<ChatLine>,
+    model: &'a mut String,
+    generation: &'a mut crate::provider::GenerationConfig,
+    /// Length of `lines` when last saved or loaded.
+    saved_len: &'a mut usize,
+}
+
+/// Streaming state of the current (or last) turn.
+struct Turn<'a> {
+    active_stream: &'a mut Option<mpsc::UnboundedReceiver<StreamMsg>>,
+    last_request: &'a mut Option<crate::provider::ChatRequest>,
+    /// The last turn failed, so `/retry` may resend `last_request`.
+    can_retry: &'a mut bool,
+    /// Tokens used by the chat so far, against `--budget-tokens-total`.
+    tokens_used: u64,
+    budget: Option<u64>,
+    /// System instruction sent with every turn.
+    system: Option<&'a str>,
+}
+
+/// Announce that the token budget is spent; no further turns are sent.
+fn note_budget(lines: &mut Vec<ChatLine>, used: u64, budget: Option<u64>) {
+    if let Some(budget) = budget.filter(|b| used >= *b) {
+        lines.push(ChatLine::new(
+            "system",
+            format!("token budget reached ({used} of {budget} tokens used); input disabled, /save and /quit still work"),
+        ));
+    }
+}
+
+/// Mark the reply being streamed as partial (or drop it if nothing arrived),
+/// then show the error and how to retry.
+fn report_failure(lines: &mut Vec<ChatLine>, error: String) {
+    if let Some(last) = lines.last_mut().filter(|l| l.role == "assistant") {
+        if last.text.is_empty() && last.alternates.is_empty() {
+            lines.pop();
+        } else {
+            last.partial = true;
+        }
+    }
+    lines.push(ChatLine::new("error", error));
+    lines.push(ChatLine::new("system", "type /retry or press Ctrl-R to resend the last message"));
+}
+
 async fn handle_key(
     key: KeyEvent,
-    input: &mut String,
-    lines: &mut Vec<ChatLine>,
-    model: &mut String,
-    provider: &Box<dyn crate::provider::Provider + Send + Sync>,
-    active_stream: &mut Option<mpsc::UnboundedReceiver<StreamMsg>>,
+    chat: Chat<'_>,
+    view: &mut ViewState,
+    provider: &(dyn crate::provider::Provider + Send + Sync),
+    model_names: &std::sync::Mutex<Vec<String>>,
+    turn: &mut Turn<'_>,
 ) -> anyhow::Result<bool> {
+    let Chat {
+        input,
+        lines,
+        model,
+        generation,
+        saved_len,
+    } = chat;
     if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
         return Ok(true);
     }
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('r') {
+        retry(lines, provider, turn).await;
+        return Ok(false);
+    }
 
     match key.code {
         KeyCode::Esc => return Ok(true),
+        KeyCode::F(3) => {
+            view.show_thoughts = !view.show_thoughts;
+            let state = if view.show_thoughts { "shown (requested from the next turn)" } else { "hidden" };
+            lines.push(ChatLine::new("system", format!("thoughts {state}")));
+        }
+        KeyCode::Tab => {
+            let names = model_names.lock().map(|n| n.clone()).unwrap_or_default();
+            match complete(input, &names) {
+                Completion::Replace(text) => *input = text,
+                Completion::Ambiguous(options) => {
+                    lines.push(ChatLine::new("system", format!("completions: {}", options.join("  "))));
+                }
+                Completion::None => {}
+            }
+        }
         KeyCode::Char(c) => input.push(c),
         KeyCode::Backspace => {
             input.pop();
@@ -155,99 +703,751 @@ async fn handle_key(
             }
             if msg == "/clear" {
                 lines.clear();
+                *saved_len = 0;
+                *turn.can_retry = false;
+                return Ok(false);
+            }
+            if msg == "/sessions" {
+                if turn.active_stream.is_some() {
+                    lines.push(ChatLine::new("system", "(streaming in progress; wait for completion)"));
+                    return Ok(false);
+                }
+                match list_transcripts() {
+                    Ok(entries) if entries.is_empty() => {
+                        lines.push(ChatLine::new("system", "no saved conversations yet (save one with /save)"));
+                    }
+                    Ok(entries) => {
+                        view.picker = Some(Picker {
+                            entries,
+                            ..Default::default()
+                        })
+                    }
+                    Err(e) => lines.push(ChatLine::new("system", format!("listing saved conversations failed: {e:#}"))),
+                }
+                return Ok(false);
+            }
+            if msg == "/retry" {
+                retry(lines, provider, turn).await;
+                return Ok(false);
+            }
+            if msg == "/regenerate" {
+                regenerate(lines, model, generation.temperature, provider, turn).await;
+                return Ok(false);
+            }
+            if let Some(rest) = msg.strip_prefix("/save").filter(|r| r.is_empty() || r.starts_with(' ')) {
+                let name = match rest.trim() {
+                    "" => format!("chat-{}", auth::now_secs()),
+                    name => name.to_string(),
+                };
+                let note = match save_transcript(&name, model, lines) {
+                    Ok(path) => {
+                        *saved_len = lines.len();
+                        format!("saved to: {}", path.display())
+                    }
+                    Err(e) => format!("save failed: {e:#}"),
+                };
+                lines.push(ChatLine::new("system", note));
                 return Ok(false);
             }
             if let Some(rest) = msg.strip_prefix("/model ") {
                 *model = rest.trim().to_string();
-                lines.push(ChatLine{role:"system", text: format!("model set to: {}", model)});
+                lines.push(ChatLine::new("system", format!("model set to: {}", model)));
+                return Ok(false);
+            }
+            if let Some(rest) = msg.strip_prefix("/temp") {
+                let note = match rest.trim() {
+                    "reset" => {
+                        generation.temperature = None;
+                        "temperature reset to the model default".to_string()
+                    }
+                    value => match value.parse::<f32>() {
+                        Ok(t) => {
+                            let candidate = crate::provider::GenerationConfig {
+                                temperature: Some(t),
+                                ..generation.clone()
+                            };
+                            match candidate.validate() {
+                                Ok(()) => {
+                                    *generation = candidate;
+                                    format!("temperature set to: {t}")
+                                }
+                                Err(e) => format!("{e}"),
+                            }
+                        }
+                        Err(_) => "usage: /temp <0.0-2.0> or /temp reset".to_string(),
+                    },
+                };
+                lines.push(ChatLine::new("system", note));
                 return Ok(false);
             }
 
-            if active_stream.is_some() {
-                lines.push(ChatLine{role:"system", text: "(streaming in progress; wait for completion)".to_string()});
+            if turn.active_stream.is_some() {
+                lines.push(ChatLine::new("system", "(streaming in progress; wait for completion)"));
                 return Ok(false);
             }
 
-            lines.push(ChatLine{role:"user", text: msg.clone()});
-            lines.push(ChatLine{role:"assistant", text: String::new()});
+            let history = conversation_history(lines);
+            lines.push(ChatLine::new("user", msg.clone()));
 
-            let req = crate::provider::ChatRequest {
+            let mut req = crate::provider::ChatRequest {
                 model: model.clone(),
                 prompt: msg,
-                include_directories: Vec::new(),
+                system: turn.system.map(str::to_string),
+                history,
+                generation: generation.clone(),
+                ..Default::default()
             };
+            if view.candidates > 1 {
+                req.generation.candidate_count = Some(view.candidates);
+            }
+            if view.show_thoughts {
+                req.generation.thinking_config = Some(crate::provider::ThinkingConfig { include_thoughts: true });
+            }
+            start_turn(lines, provider, req, turn).await;
+        }
+        _ => {}
+    }
 
-            let mut stream = provider
-                .stream_chat(req)
-                .await
-                .context("failed to start stream")?;
-
-            let (tx, rx) = mpsc::unbounded_channel::<StreamMsg>();
-            *active_stream = Some(rx);
-
-            tokio::spawn(async move {
-                use tokio_stream::StreamExt;
-                while let Some(item) = stream.next().await {
-                    match item {
-                        Ok(chunk) => {
-                            if tx.send(StreamMsg::Chunk(chunk.text)).is_err() {
-                                return;
-                            }
-                        }
-                        Err(e) => {
-                            let _ = tx.send(StreamMsg::Error(format!("{e:#}")));
-                            return;
-                        }
+    Ok(false)
+}
+
+/// Resend the request of the last failed turn.
+async fn retry(
+    lines: &mut Vec<ChatLine>,
+    provider: &(dyn crate::provider::Provider + Send + Sync),
+    turn: &mut Turn<'_>,
+) {
+    if turn.active_stream.is_some() {
+        lines.push(ChatLine::new("system", "(streaming in progress; wait for completion)"));
+        return;
+    }
+    match turn.last_request.clone().filter(|_| *turn.can_retry) {
+        Some(req) => start_turn(lines, provider, req, turn).await,
+        None => lines.push(ChatLine::new("system", "nothing to retry")),
+    }
+}
+
+/// Drop the reply to the last user message (and anything after it) and send
+/// that message again, with the current model and temperature. Unlike
+/// [`retry`], this works after a successful turn.
+async fn regenerate(
+    lines: &mut Vec<ChatLine>,
+    model: &str,
+    temperature: Option<f32>,
+    provider: &(dyn crate::provider::Provider + Send + Sync),
+    turn: &mut Turn<'_>,
+) {
+    if turn.active_stream.is_some() {
+        lines.push(ChatLine::new("system", "(streaming in progress; wait for completion)"));
+        return;
+    }
+    let (Some(mut req), Some(user)) = (turn.last_request.clone(), lines.iter().rposition(|l| l.role == "user")) else {
+        lines.push(ChatLine::new("system", "nothing to regenerate"));
+        return;
+    };
+    lines.truncate(user + 1);
+    req.model = model.to_string();
+    req.generation.temperature = temperature;
+    start_turn(lines, provider, req, turn).await;
+}
+
+/// Add an empty assistant line and stream `req` into it. A request that
+/// fails to start is reported inline like a failed stream.
+async fn start_turn(
+    lines: &mut Vec<ChatLine>,
+    provider: &(dyn crate::provider::Provider + Send + Sync),
+    mut req: crate::provider::ChatRequest,
+    turn: &mut Turn<'_>,
+) {
+    let bytes = req.prompt.len() + app::history_bytes(&req.history);
+    if let Some(limit) = app::input_token_limit(provider, &req.model, bytes).await {
+        let dropped = app::trim_history(&mut req.history, req.prompt.len(), limit);
+        if dropped > 0 {
+            lines.push(ChatLine::new(
+                "system",
+                format!("(left out the {dropped} oldest messages to fit the model's input limit of {limit} tokens)"),
+            ));
+        }
+    }
+
+    if let Some(budget) = turn.budget {
+        let estimate = crate::context::estimate_tokens(req.prompt.len() + app::history_bytes(&req.history)) as u64;
+        if turn.tokens_used + estimate > budget {
+            lines.push(ChatLine::new(
+                "system",
+                format!(
+                    "not sent: ~{estimate} tokens would exceed the budget of {budget} ({} left)",
+                    budget.saturating_sub(turn.tokens_used)
+                ),
+            ));
+            return;
+        }
+    }
+
+    lines.push(ChatLine::new("assistant", ""));
+    *turn.last_request = Some(req.clone());
+    *turn.can_retry = false;
+
+    let started = std::time::Instant::now();
+    let mut stream = match provider.stream_chat(req).await.context("failed to start stream") {
+        Ok(stream) => stream,
+        Err(e) => {
+            report_failure(lines, format!("{e:#}"));
+            *turn.can_retry = true;
+            return;
+        }
+    };
+
+    let (tx, rx) = mpsc::unbounded_channel::<StreamMsg>();
+    *turn.active_stream = Some(rx);
+
+    tokio::spawn(async move {
+        use tokio_stream::StreamExt;
+        let mut first = true;
+        while let Some(item) = stream.next().await {
+            if first {
+                first = false;
+                tracing::debug!(elapsed = ?started.elapsed(), "first chunk");
+            }
+            match item {
+                Ok(event) => {
+                    let msg = match event {
+                        ChatEvent::Text(t) => StreamMsg::Chunk(t),
+                        ChatEvent::Thought(t) => StreamMsg::Thought(t),
+                        ChatEvent::Alternate { index, text } => StreamMsg::Alternate(index, text),
+                        ChatEvent::Usage(usage) => StreamMsg::Usage(usage.total_tokens),
+                        ChatEvent::Logprobs(_)
+                        | ChatEvent::Raw(_)
+                        | ChatEvent::FunctionCall { .. }
+                        | ChatEvent::Finish { .. }
+                        | ChatEvent::Blocked { .. }
+                        | ChatEvent::Stats(_)
+                        | ChatEvent::Grounding(_) => continue,
+                    };
+                    if tx.send(msg).is_err() {
+                        return;
                     }
                 }
-                let _ = tx.send(StreamMsg::Done);
+                Err(e) => {
+                    let _ = tx.send(StreamMsg::Error(format!("{e:#}")));
+                    return;
+                }
+            }
+        }
+        let _ = tx.send(StreamMsg::Done);
+    });
+}
+
+/// Result of Tab completion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Completion {
+    /// New input line.
+    Replace(String),
+    /// Several options share no longer prefix than what was typed.
+    Ambiguous(Vec<String>),
+    None,
+}
+
+/// Complete a slash command (from [`HELP`]) or, after `/model `, a model name.
+fn complete(input: &str, model_names: &[String]) -> Completion {
+    let (lead, prefix, options): (&str, &str, Vec<String>) = if let Some(rest) = input.strip_prefix("/model ") {
+        ("/model ", rest, model_names.to_vec())
+    } else if input.starts_with('/') && !input.contains(' ') {
+        // Commands taking arguments complete with a trailing space.
+        let commands = HELP
+            .iter()
+            .filter_map(|(key, _)| {
+                let cmd = key.split(' ').next()?;
+                let cmd = cmd.strip_prefix('/')?;
+                Some(if key.contains(' ') { format!("/{cmd} ") } else { format!("/{cmd}") })
+            })
+            .collect();
+        ("", input, commands)
+    } else {
+        return Completion::None;
+    };
+
+    let matches: Vec<&String> = options.iter().filter(|o| o.starts_with(prefix)).collect();
+    match matches.as_slice() {
+        [] => Completion::None,
+        [only] => Completion::Replace(format!("{lead}{only}")),
+        [first, rest @ ..] => {
+            let common = rest.iter().fold(first.len(), |len, o| {
+                first.bytes().zip(o.bytes()).take(len).take_while(|(a, b)| a == b).count()
             });
+            let common = (0..=common).rev().find(|&i| first.is_char_boundary(i)).unwrap_or(0);
+            if common > prefix.len() {
+                Completion::Replace(format!("{lead}{}", &first[..common]))
+            } else {
+                Completion::Ambiguous(matches.iter().map(|m| m.trim_end().to_string()).collect())
+            }
         }
-        _ => {}
     }
+}
 
-    Ok(false)
+/// Keys for conversation search. Returns whether `key` was used. While
+/// browsing matches, other keys end the search (except scrolling, and in
+/// read-only sessions, where nothing else is typed).
+fn search_key(key: KeyEvent, read_only: bool, search: &mut Search) -> bool {
+    let ctrl_f = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('f');
+    match search.mode {
+        SearchMode::Off => {
+            if !(ctrl_f || (read_only && key.code == KeyCode::Char('/'))) {
+                return false;
+            }
+            search.query.clear();
+            search.mode = SearchMode::Editing;
+        }
+        SearchMode::Editing => match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => search.query.push(c),
+            KeyCode::Backspace => {
+                search.query.pop();
+            }
+            KeyCode::Enter if !search.query.is_empty() => {
+                // Start from the most recent match.
+                search.mode = SearchMode::Browsing;
+                search.current = usize::MAX;
+                search.jump = true;
+            }
+            KeyCode::Enter | KeyCode::Esc => *search = Search::default(),
+            _ => {}
+        },
+        SearchMode::Browsing => match key.code {
+            KeyCode::Char('n') if search.count > 0 => {
+                search.current = (search.current + 1) % search.count;
+                search.jump = true;
+            }
+            KeyCode::Char('N') if search.count > 0 => {
+                search.current = (search.current + search.count - 1) % search.count;
+                search.jump = true;
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {}
+            KeyCode::Esc => *search = Search::default(),
+            _ if ctrl_f => {
+                search.query.clear();
+                search.mode = SearchMode::Editing;
+            }
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => {
+                return false
+            }
+            _ if read_only => return false,
+            _ => {
+                *search = Search::default();
+                return false;
+            }
+        },
+    }
+    true
+}
+
+/// Scroll keys, shared by both modes. Read-only sessions also take
+/// `j`/`k`/`g`/`G` since there is no input line. Returns whether `code` was used.
+fn scroll_key(code: KeyCode, read_only: bool, scroll: &mut usize) -> bool {
+    const PAGE: usize = 10;
+    match code {
+        KeyCode::Up => *scroll = scroll.saturating_add(1),
+        KeyCode::Down => *scroll = scroll.saturating_sub(1),
+        KeyCode::PageUp => *scroll = scroll.saturating_add(PAGE),
+        KeyCode::PageDown => *scroll = scroll.saturating_sub(PAGE),
+        // Clamped to the top when drawn.
+        KeyCode::Home => *scroll = usize::MAX,
+        KeyCode::End => *scroll = 0,
+        KeyCode::Char('k') if read_only => *scroll = scroll.saturating_add(1),
+        KeyCode::Char('j') if read_only => *scroll = scroll.saturating_sub(1),
+        KeyCode::Char('g') if read_only => *scroll = usize::MAX,
+        KeyCode::Char('G') if read_only => *scroll = 0,
+        _ => return false,
+    }
+    true
 }
 
 fn draw(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     model: &str,
+    temperature: Option<f32>,
     lines: &[ChatLine],
     input: &str,
+    view: &mut ViewState,
 ) -> anyhow::Result<()> {
+    let ViewState {
+        style,
+        candidates,
+        simple,
+        read_only,
+        streaming,
+        show_thoughts,
+        show_help,
+        ref mut scroll,
+        ref mut search,
+        ref picker,
+    } = *view;
+    // Columns and rows taken by a pane's frame: a border all round, or just
+    // the title row when simple.
+    let (inset_x, inset_y) = if simple { (0u16, 1u16) } else { (1, 2) };
+    let dim = if simple { Style::default() } else { Style::default().add_modifier(Modifier::DIM) };
+    let input_lines = input.split('\n').count() as u16;
+    let input_rows = if read_only || search.mode == SearchMode::Editing {
+        1
+    } else {
+        input_lines.min(MAX_INPUT_ROWS)
+    };
     terminal.draw(|f| {
-        let chunks = Layout::default()
+        let mut chunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .constraints([Constraint::Min(1), Constraint::Length(input_rows + inset_y)])
             .split(f.area());
 
-        let mut text = Text::default();
-        for l in lines {
-            let role = format!("{}: ", l.role);
-            let style = match l.role {
-                "user" => Style::default().add_modifier(Modifier::BOLD),
-                "assistant" => Style::default(),
-                "error" => Style::default().add_modifier(Modifier::BOLD),
-                _ => Style::default(),
+        if show_thoughts {
+            let split = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Percentage(30), Constraint::Min(1)])
+                .split(chunks[0]);
+            // Reasoning of the latest turn that produced any.
+            let thought = lines
+                .iter()
+                .rev()
+                .find(|l| !l.thought.is_empty())
+                .map(|l| l.thought.clone())
+                .unwrap_or_default();
+            let pane = Paragraph::new(thought)
+                .style(dim)
+                .wrap(Wrap { trim: false })
+                .block(frame(simple).title("thoughts (F3 to hide)"));
+            f.render_widget(pane, split[0]);
+            chunks = [split[1], chunks[1]].into();
+        }
+
+        // One column per candidate; each shows the conversation with that
+        // candidate's answers.
+        let n = candidates.max(1);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints((0..n).map(|_| Constraint::Ratio(1, n)))
+            .split(chunks[0]);
+        for (i, area) in columns.iter().enumerate() {
+            let view = candidate_view(lines, i);
+            // Text is pre-wrapped so the row count (for scrolling) is known.
+            let width = area.width.saturating_sub(2 * inset_x) as usize;
+            let mut text = match style {
+                _ if simple => simple_text(&view, width, streaming),
+                MessageStyle::Compact => compact_text(&view, width),
+                MessageStyle::Blocks => block_text(&view, width),
+            };
+            let visible = area.height.saturating_sub(inset_y) as usize;
+            let max_scroll = text.lines.len().saturating_sub(visible);
+            let needle = match search.mode {
+                SearchMode::Off => String::new(),
+                _ => search.query.to_ascii_lowercase(),
             };
-            text.lines.push(Line::styled(role, style));
-            text.lines.extend(Text::from(l.text.clone()).lines);
-            text.lines.push(Line::from(""));
+            let match_rows = highlight_matches(&mut text, &needle);
+            if i == 0 {
+                search.count = match_rows.len();
+                if search.jump && !match_rows.is_empty() {
+                    search.current = search.current.min(match_rows.len() - 1);
+                    // Put the match a third of the way down the pane.
+                    let top = match_rows[search.current].saturating_sub(visible / 3).min(max_scroll);
+                    *scroll = max_scroll - top;
+                }
+                search.jump = false;
+                *scroll = (*scroll).min(max_scroll);
+            }
+            let top = max_scroll - (*scroll).min(max_scroll);
+            let mut chat = Paragraph::new(text).scroll((top.min(u16::MAX as usize) as u16, 0));
+            let mut title = if n == 1 {
+                format!("gemini tui — model: {model}")
+            } else {
+                format!("candidate {} — model: {model}", i + 1)
+            };
+            if let Some(t) = temperature {
+                title.push_str(&format!(" — temp: {t}"));
+            }
+            if *scroll > 0 {
+                title.push_str(" — scrolled (End to follow)");
+            }
+            if search.mode == SearchMode::Browsing {
+                match search.count {
+                    0 => title.push_str(&format!(" — \"{}\": no matches", search.query)),
+                    n => title.push_str(&format!(" — \"{}\": {}/{n}", search.query, search.current.min(n - 1) + 1)),
+                }
+            }
+            chat = chat.block(frame(simple).title(title));
+            f.render_widget(chat, *area);
         }
 
-        let chat = Paragraph::new(text)
-            .block(Block::default().borders(Borders::ALL).title(format!("gemini tui — model: {model}")))
-            .wrap(Wrap { trim: false });
+        if search.mode == SearchMode::Editing {
+            let prompt = Paragraph::new(format!("/{}", search.query))
+                .block(frame(simple).title("search — Enter to find, Esc to cancel"));
+            f.render_widget(prompt, chunks[1]);
+            let x = chunks[1].x + inset_x + 1 + search.query.chars().count() as u16;
+            f.set_cursor_position((x.min(chunks[1].x + chunks[1].width.saturating_sub(2)), chunks[1].y + 1));
+        } else if read_only {
+            let hint = Paragraph::new("↑/↓ j/k PgUp/PgDn to scroll, g/G top/bottom, / to search, q to quit")
+                .style(dim)
+                .block(frame(simple).title("read-only"));
+            f.render_widget(hint, chunks[1]);
+        } else {
+            let title = if input_lines > 1 { format!("input ({input_lines} lines)") } else { "input".to_string() };
+            let input_w = Paragraph::new(input.to_string())
+                .scroll((input_lines - input_rows, 0))
+                .block(frame(simple).title(title));
+
+            f.render_widget(input_w, chunks[1]);
 
-        let input_w = Paragraph::new(input.to_string())
-            .block(Block::default().borders(Borders::ALL).title("input"));
+            let last_line = input.rsplit('\n').next().unwrap_or_default();
+            let x = chunks[1].x + inset_x + last_line.chars().count() as u16;
+            let y = chunks[1].y + input_rows;
+            f.set_cursor_position((x.min(chunks[1].x + chunks[1].width.saturating_sub(2)), y));
+        }
 
-        f.render_widget(chat, chunks[0]);
-        f.render_widget(input_w, chunks[1]);
+        if show_help {
+            let key_w = HELP.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+            let mut text = Text::default();
+            for (key, desc) in HELP {
+                text.lines.push(Line::from(vec![
+                    Span::styled(format!("{key:<key_w$}  "), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(*desc),
+                ]));
+            }
+            let width = HELP
+                .iter()
+                .map(|(_, d)| key_w + 2 + d.chars().count())
+                .max()
+                .unwrap_or(0) as u16
+                + 4;
+            let area = centered(f.area(), width, HELP.len() as u16 + 2);
+            f.render_widget(Clear, area);
+            f.render_widget(
+                Paragraph::new(text).block(frame(simple).title("help — any key to close")),
+                area,
+            );
+        }
 
-        let x = chunks[1].x + 1 + input.chars().count() as u16;
-        let y = chunks[1].y + 1;
-        f.set_cursor_position((x.min(chunks[1].x + chunks[1].width.saturating_sub(2)), y));
+        if let Some(p) = picker {
+            let now = auth::now_secs();
+            let rows: Vec<String> = p
+                .entries
+                .iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    let marker = if i == p.selected { ">" } else { " " };
+                    let age = format_age(now.saturating_sub(e.saved_at));
+                    format!("{marker} {}  {} messages  {}  {age}", e.name, e.messages, e.model)
+                })
+                .collect();
+            let title = match p.confirm {
+                Some(n) => format!("{n} unsaved message(s) will be lost — Enter to load anyway, Esc to cancel"),
+                None => "saved conversations — Enter to load, Esc to cancel".to_string(),
+            };
+            let width = rows.iter().map(|r| r.chars().count()).chain([title.chars().count()]).max().unwrap_or(0) as u16 + 4;
+            let area = centered(f.area(), width, rows.len() as u16 + inset_y);
+            let visible = area.height.saturating_sub(inset_y) as usize;
+            let top = (p.selected + 1).saturating_sub(visible);
+            let selected = if simple { Style::default() } else { Style::default().add_modifier(Modifier::REVERSED) };
+            let text: Vec<Line> = rows
+                .into_iter()
+                .enumerate()
+                .map(|(i, r)| if i == p.selected { Line::styled(r, selected) } else { Line::raw(r) })
+                .collect();
+            f.render_widget(Clear, area);
+            f.render_widget(
+                Paragraph::new(text)
+                    .scroll((top.min(u16::MAX as usize) as u16, 0))
+                    .block(frame(simple).title(title)),
+                area,
+            );
+        }
     })?;
     Ok(())
 }
+
+/// `lines` with each assistant message replaced by candidate `index`'s text.
+fn candidate_view(lines: &[ChatLine], index: usize) -> std::borrow::Cow<'_, [ChatLine]> {
+    if index == 0 {
+        return std::borrow::Cow::Borrowed(lines);
+    }
+    let view = lines
+        .iter()
+        .map(|l| match l.role {
+            "assistant" => ChatLine::new("assistant", l.alternates.get(index - 1).cloned().unwrap_or_default()),
+            _ => l.clone(),
+        })
+        .collect::<Vec<_>>();
+    std::borrow::Cow::Owned(view)
+}
+
+/// Highlight case-insensitive (ASCII) occurrences of `needle` in `text` and
+/// return the indexes of the rows that contain one. Matches are found within
+/// single spans, which hold a whole row of message text.
+fn highlight_matches(text: &mut Text<'static>, needle: &str) -> Vec<usize> {
+    let mut rows = Vec::new();
+    if needle.is_empty() {
+        return rows;
+    }
+    let hit = Style::default().add_modifier(Modifier::REVERSED);
+    for (row, line) in text.lines.iter_mut().enumerate() {
+        let mut spans = Vec::with_capacity(line.spans.len());
+        let mut found = false;
+        for span in std::mem::take(&mut line.spans) {
+            let lower = span.content.to_ascii_lowercase();
+            if !lower.contains(needle) {
+                spans.push(span);
+                continue;
+            }
+            found = true;
+            let content = span.content.into_owned();
+            let mut start = 0;
+            for (at, _) in lower.match_indices(needle) {
+                if at > start {
+                    spans.push(Span::styled(content[start..at].to_string(), span.style));
+                }
+                spans.push(Span::styled(content[at..at + needle.len()].to_string(), span.style.patch(hit)));
+                start = at + needle.len();
+            }
+            if start < content.len() {
+                spans.push(Span::styled(content[start..].to_string(), span.style));
+            }
+        }
+        line.spans = spans;
+        if found {
+            rows.push(row);
+        }
+    }
+    rows
+}
+
+/// `secs` ago, coarsely: "42s ago", "5m ago", "3h ago", "2d ago".
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// A `width` x `height` rectangle centered in `area`, clipped to fit.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// A pane's frame: bordered, or only a title row when simple.
+fn frame<'a>(simple: bool) -> Block<'a> {
+    if simple {
+        Block::default()
+    } else {
+        Block::default().borders(Borders::ALL)
+    }
+}
+
+/// Unstyled layout for screen readers: a spelled-out speaker label, the
+/// text, and an explicit marker once each assistant reply is complete.
+fn simple_text(lines: &[ChatLine], width: usize, streaming: bool) -> Text<'static> {
+    let mut text = Text::default();
+    for (i, l) in lines.iter().enumerate() {
+        let speaker = match l.role {
+            "user" => "You",
+            "assistant" => "Assistant",
+            "error" => "Error",
+            _ => "System",
+        };
+        let partial = if l.partial { " (partial)" } else { "" };
+        text.lines.push(Line::from(format!("{speaker}{partial}:")));
+        text.lines.extend(wrap_text(&l.text, width).into_iter().map(Line::from));
+        let in_progress = streaming && i + 1 == lines.len();
+        if l.role == "assistant" && !in_progress {
+            text.lines.push(Line::from("(end of response)"));
+        }
+        text.lines.push(Line::from(""));
+    }
+    text
+}
+
+fn role_style(role: &str) -> Style {
+    match role {
+        "user" => Style::default().add_modifier(Modifier::BOLD),
+        "assistant" => Style::default(),
+        "error" => Style::default().add_modifier(Modifier::BOLD),
+        _ => Style::default(),
+    }
+}
+
+fn compact_text(lines: &[ChatLine], width: usize) -> Text<'static> {
+    let mut text = Text::default();
+    for l in lines {
+        text.lines.push(Line::styled(format!("{}: ", l.label()), role_style(l.role)));
+        text.lines.extend(wrap_text(&l.text, width).into_iter().map(Line::from));
+        text.lines.push(Line::from(""));
+    }
+    text
+}
+
+/// Render each message as a bordered block, `width` columns wide, with the
+/// role as a badge in the top border.
+fn block_text(lines: &[ChatLine], width: usize) -> Text<'static> {
+    if width < 8 {
+        return compact_text(lines, width);
+    }
+    let border = Style::default().add_modifier(Modifier::DIM);
+    let inner = width - 4;
+
+    let mut text = Text::default();
+    for l in lines {
+        let badge = format!(" {} ", l.label());
+        let fill = width.saturating_sub(3 + badge.chars().count());
+        text.lines.push(Line::from(vec![
+            Span::styled("╭─", border),
+            Span::styled(badge, role_style(l.role).add_modifier(Modifier::REVERSED)),
+            Span::styled(format!("{}╮", "─".repeat(fill)), border),
+        ]));
+        for row in wrap_text(&l.text, inner) {
+            let pad = inner.saturating_sub(row.chars().count());
+            text.lines.push(Line::from(vec![
+                Span::styled("│ ", border),
+                Span::raw(format!("{row}{}", " ".repeat(pad))),
+                Span::styled(" │", border),
+            ]));
+        }
+        text.lines.push(Line::styled(format!("╰{}╯", "─".repeat(width - 2)), border));
+    }
+    text
+}
+
+/// Greedy word wrap by character count. Newlines always break; words longer
+/// than `width` are split.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut out = Vec::new();
+    for para in text.split('\n') {
+        let mut cur = String::new();
+        let mut cur_len = 0;
+        for word in para.split(' ') {
+            let word_len = word.chars().count();
+            if cur_len > 0 && cur_len + 1 + word_len <= width {
+                cur.push(' ');
+                cur.push_str(word);
+                cur_len += 1 + word_len;
+                continue;
+            }
+            if cur_len > 0 {
+                out.push(std::mem::take(&mut cur));
+            }
+            let mut rest = word;
+            while rest.chars().count() > width {
+                let split = rest.char_indices().nth(width).map(|(i, _)| i).unwrap_or(rest.len());
+                out.push(rest[..split].to_string());
+                rest = &rest[split..];
+            }
+            cur.push_str(rest);
+            cur_len = rest.chars().count();
+        }
+        out.push(cur);
+    }
+    out
+}